@@ -4,16 +4,16 @@ use databench_rs::generator::Generator;
 
 /*
 fn generator_chernobyl_benchmark(c: &mut Criterion) {
-    c.bench_function("Chernobyl new", |b| b.iter(|| Chernobyl::new(4, 10000)));
+    c.bench_function("Chernobyl new", |b| b.iter(|| Chernobyl::new(4, 10000, 0)));
     c.bench_function("Chernobyl get messages", |b| {
-        let chernobyl = Chernobyl::new(4, 10000).expect("Error creating Chernobyl");
+        let mut chernobyl = Chernobyl::new(4, 10000, 0).expect("Error creating Chernobyl");
         b.iter(|| chernobyl.get_message().expect("Error getting message"))
     });
 }
 */
 
 fn bench_crypto(c: &mut Criterion) {
-    let chernobyl = Chernobyl::new(4, 10000).expect("Error creating Chernobyl");
+    let mut chernobyl = Chernobyl::new(4, 10000, 0).expect("Error creating Chernobyl");
     let messages = (0..1000)
         .map(|_| {
             let msg = chernobyl.get_message().expect("Error getting message");
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Errors of a sender or receiver counted by category, e.g. `send` or `commit`. Only touched
+/// on the error path, so a lock is cheap enough.
+#[derive(Debug, Default)]
+pub struct ErrorCounter {
+    counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl ErrorCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, category: &str) {
+        #[allow(clippy::expect_used)]
+        let mut counts = self.counts.lock().expect("Failed to get error lock");
+        *counts.entry(category.to_string()).or_default() += 1;
+    }
+
+    pub fn counts(&self) -> BTreeMap<String, u64> {
+        #[allow(clippy::expect_used)]
+        self.counts
+            .lock()
+            .expect("Failed to get error lock")
+            .clone()
+    }
+}
+
+/// Adds the counts of `from` to `into`.
+pub fn merge(into: &mut BTreeMap<String, u64>, from: &BTreeMap<String, u64>) {
+    for (category, count) in from {
+        *into.entry(category.clone()).or_default() += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{merge, ErrorCounter};
+
+    #[test]
+    fn test_error_counter() {
+        let errors = ErrorCounter::new();
+        errors.add("send");
+        errors.add("send");
+        errors.add("flush");
+        let mut total = errors.counts();
+        assert_eq!(total["send"], 2);
+
+        merge(&mut total, &[("commit".to_string(), 3)].into());
+        merge(&mut total, &errors.counts());
+        assert_eq!(total["send"], 4);
+        assert_eq!(total["commit"], 3);
+    }
+}
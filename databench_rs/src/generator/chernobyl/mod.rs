@@ -1,10 +1,14 @@
 pub mod json_struct;
 
 use crate::generator::chernobyl::json_struct::{Type, Unit};
-use crate::generator::{Generator, Message};
+use crate::generator::{
+    Clock, Generator, Message, LOGICAL_EPOCH_NS, LOGICAL_TICK_NS, STREAM_FIELD,
+};
 use crate::helper::rand_entry;
-use rand::Rng;
-use std::collections::HashMap;
+use crate::latency::{TIMESTAMP_MS_FIELD, TIMESTAMP_NS_FIELD};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 
 #[derive(Clone)]
@@ -17,23 +21,53 @@ pub struct TopicInternal {
 pub struct Chernobyl {
     split_point: usize,
     topics: Vec<TopicInternal>,
+    rng: ChaCha8Rng,
+    clock: Clock,
+    /// Messages generated so far, the ticks of a logical clock.
+    ticks: u128,
+    /// The seed in hex, sent in every message to tell streams apart.
+    stream: String,
+}
+
+impl Chernobyl {
+    fn timestamp_ns(&mut self) -> anyhow::Result<u128> {
+        let ticks = self.ticks;
+        self.ticks += 1;
+        Ok(match self.clock {
+            Clock::Wall => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_nanos(),
+            Clock::Logical => LOGICAL_EPOCH_NS + ticks * LOGICAL_TICK_NS,
+        })
+    }
 }
 
 impl Generator for Chernobyl {
-    fn new(split_point: usize, number_of_topics: usize) -> anyhow::Result<Self> {
+    fn new(split_point: usize, number_of_topics: usize, seed: u64) -> anyhow::Result<Self> {
         let mut c = Self {
             split_point,
             topics: Vec::with_capacity(number_of_topics),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            clock: Clock::Wall,
+            ticks: 0,
+            stream: format!("{:016x}", seed),
         };
         c.generate_topics(number_of_topics)?;
         Ok(c)
     }
 
-    fn get_message(&self) -> anyhow::Result<Message> {
-        let topic = rand_entry(&self.topics);
+    fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn get_message(&mut self) -> anyhow::Result<Message> {
+        let nano_time = self.timestamp_ns()?;
+        let rng = &mut self.rng;
+        let topic = rand_entry(rng, &self.topics);
         let topic_name_split: Vec<&str> = topic.name.split('.').collect();
-        let mut data: HashMap<String, String> = HashMap::new();
-        let mut rng = rand::thread_rng();
+        // Ordered, so the same values always serialize to the same payload
+        let mut data: BTreeMap<String, String> = BTreeMap::new();
 
         let topic_name = topic_name_split
             .iter()
@@ -41,21 +75,20 @@ impl Generator for Chernobyl {
             .cloned()
             .collect::<Vec<_>>()
             .join(".");
-        let mut key = topic_name_split
+        let key = topic_name_split
             .iter()
             .skip(self.split_point)
             .cloned()
             .collect::<Vec<_>>()
             .join(".");
 
-        let nano_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_nanos();
-
         data.insert(
-            "timestamp_ms".to_owned(),
+            TIMESTAMP_MS_FIELD.to_owned(),
             format!("{}", nano_time / 1_000_000),
         );
+        // High resolution send time for latency measurements
+        data.insert(TIMESTAMP_NS_FIELD.to_owned(), format!("{}", nano_time));
+        data.insert(STREAM_FIELD.to_owned(), self.stream.clone());
 
         // Match on topic.unit
         match topic.unit {
@@ -230,28 +263,30 @@ impl Generator for Chernobyl {
             },
         }
 
-        // HashMap to json bytes
+        // Map to json bytes
         let data_as_json = serde_json::to_vec(&data)?;
 
         Ok(Message {
             topic: topic_name,
             value: data_as_json,
             key,
+            headers: vec![],
+            sequence: None,
         })
     }
 
     fn generate_topics(&mut self, number_of_topics: usize) -> anyhow::Result<()> {
-        let mut rng = rand::thread_rng();
+        let rng = &mut self.rng;
         let mut topic: Vec<String> = vec![];
 
         let pp = json_struct::load()?;
         let chernobyl = pp
-            .get(0)
-            .map_or(Err(anyhow::anyhow!("No powerplant found")), Ok)?;
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No powerplant found"))?;
         let site = chernobyl
             .sites
-            .get(0)
-            .map_or(Err(anyhow::anyhow!("No site found")), Ok)?;
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No site found"))?;
 
         for _ in 0..number_of_topics {
             topic.push("umh.v1.".to_owned());
@@ -264,22 +299,22 @@ impl Generator for Chernobyl {
             topic.push(rand_number);
             topic.push(".".to_owned());
 
-            let area = rand_entry(&site.areas);
+            let area = rand_entry(rng, &site.areas);
             topic.push(area.area.to_owned());
             topic.push(".".to_owned());
 
-            let production_line = rand_entry(&area.production_lines);
+            let production_line = rand_entry(rng, &area.production_lines);
             topic.push(production_line.production_line.to_owned());
             topic.push(".".to_owned());
 
-            let work_cell = rand_entry(&production_line.work_cells);
+            let work_cell = rand_entry(rng, &production_line.work_cells);
             topic.push(work_cell.work_cell.to_owned());
             topic.push(".".to_owned());
 
             topic.push(work_cell.tag_group.to_owned());
             topic.push(".".to_owned());
 
-            let tag = rand_entry(&work_cell.tags);
+            let tag = rand_entry(rng, &work_cell.tags);
             topic.push(tag.name.to_owned());
 
             let t: TopicInternal = TopicInternal {
@@ -298,21 +333,24 @@ impl Generator for Chernobyl {
 #[cfg(test)]
 mod tests {
     use crate::generator::chernobyl::Chernobyl;
-    use crate::generator::Generator;
+    use crate::generator::{
+        stream_seed, Clock, Generator, LOGICAL_EPOCH_NS, LOGICAL_TICK_NS, STREAM_FIELD,
+    };
+    use crate::latency::{TIMESTAMP_MS_FIELD, TIMESTAMP_NS_FIELD};
+    use std::collections::HashSet;
 
     #[test]
     #[allow(clippy::unwrap_used)]
     fn test_chernobyl() {
-        let c = Chernobyl::new(3, 100).unwrap();
+        let mut c = Chernobyl::new(3, 100, 42).unwrap();
+        // Key must be in this form: <site><1-4>.<area>.<line>.<cell>.<tag group>.<tag>
+        let re = regex::Regex::new(r"^\w+[1-4](?:\.\w+){5}$").unwrap();
         for _ in 0..100 {
             let m = c.get_message().unwrap();
 
             // Assert that the topic is correct
             assert_eq!(m.topic, "umh.v1.chernobylnuclearpowerplant");
 
-            // Key must be in this form: ^(?:\w+\.){6}\d+$
-            let re = regex::Regex::new(r"^(?:\w+\.){6}\d+$").unwrap();
-
             assert!(re.is_match(&m.key));
 
             // Assert that the value is valid json
@@ -320,4 +358,71 @@ mod tests {
             assert!(v.is_object());
         }
     }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_chernobyl_seeded() {
+        // Everything but the send timestamps
+        let messages = |c: &mut Chernobyl| {
+            (0..100)
+                .map(|_| {
+                    let m = c.get_message().unwrap();
+                    let mut v: serde_json::Value = serde_json::from_slice(&m.value).unwrap();
+                    let data = v.as_object_mut().unwrap();
+                    data.remove(TIMESTAMP_MS_FIELD);
+                    data.remove(TIMESTAMP_NS_FIELD);
+                    (m.topic, m.key, v)
+                })
+                .collect::<Vec<_>>()
+        };
+        let topics = |c: &Chernobyl| c.topics.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+
+        let mut a = Chernobyl::new(3, 100, 42).unwrap();
+        let mut b = Chernobyl::new(3, 100, 42).unwrap();
+        assert_eq!(topics(&a), topics(&b));
+        assert_eq!(messages(&mut a), messages(&mut b));
+
+        let mut other = Chernobyl::new(3, 100, stream_seed(42, 1)).unwrap();
+        assert_ne!(topics(&a), topics(&other));
+        assert_ne!(messages(&mut a), messages(&mut other));
+        assert_ne!(stream_seed(42, 1), stream_seed(43, 0));
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_chernobyl_logical_clock() {
+        let payloads = |seed: u64| {
+            let mut c = Chernobyl::new(3, 100, seed)
+                .unwrap()
+                .with_clock(Clock::Logical);
+            (0..1000)
+                .map(|_| {
+                    let m = c.get_message().unwrap();
+                    (format!("{}.{}", m.topic, m.key), m.value)
+                })
+                .collect::<Vec<_>>()
+        };
+        let a = payloads(stream_seed(42, 0));
+        assert_eq!(a, payloads(stream_seed(42, 0)));
+
+        let v: serde_json::Value = serde_json::from_slice(&a[1].1).unwrap();
+        let ns: u128 = v[TIMESTAMP_NS_FIELD].as_str().unwrap().parse().unwrap();
+        assert_eq!(ns, LOGICAL_EPOCH_NS + LOGICAL_TICK_NS);
+        assert_eq!(
+            v[STREAM_FIELD].as_str().unwrap(),
+            format!("{:016x}", stream_seed(42, 0))
+        );
+
+        // Streams share topics, but not messages, even with seeds equal modulo the tick
+        let b = payloads(stream_seed(42, 0).wrapping_add(LOGICAL_TICK_NS as u64));
+        let topics = |messages: &[(String, Vec<u8>)]| {
+            messages
+                .iter()
+                .map(|(topic, _)| topic.clone())
+                .collect::<HashSet<_>>()
+        };
+        assert!(!topics(&a).is_disjoint(&topics(&b)));
+        let a: HashSet<_> = a.into_iter().collect();
+        assert!(b.iter().all(|message| !a.contains(message)));
+    }
 }
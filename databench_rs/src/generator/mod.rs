@@ -1,16 +1,81 @@
+use crate::generator::sequence::SequenceStamp;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
 pub mod chernobyl;
+pub mod sequence;
+
+/// Kafka header and MQTT5 user property holding the send time in nanoseconds since the unix epoch.
+pub const SEND_TIMESTAMP_HEADER: &str = "databench-timestamp-ns";
+pub const PRODUCER_ID_HEADER: &str = "databench-producer-id";
+pub const SEQUENCE_HEADER: &str = "databench-seq";
+/// Payload field holding the seed of the generator stream in hex, so no two streams
+/// generate the same message.
+pub const STREAM_FIELD: &str = "stream";
+
+/// Where benchmark metadata (send timestamp, producer id and sequence number) is carried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataLocation {
+    /// JSON fields in the payload
+    #[default]
+    Payload,
+    /// Kafka headers, the payload is left as generated
+    Headers,
+}
+
+/// Where generated messages take their `timestamp_ms` and `timestamp_ns` from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Clock {
+    /// The time the message is generated, which latencies are measured from
+    #[default]
+    Wall,
+    /// A per-generator counter that starts at [`LOGICAL_EPOCH_NS`] and ticks by
+    /// [`LOGICAL_TICK_NS`] per message, so the same seed gives byte-identical payloads.
+    /// Latencies then need the send timestamp in headers
+    Logical,
+}
+
+/// Start of the [`Clock::Logical`] timestamps, 2023-11-14 22:13:20 UTC.
+pub const LOGICAL_EPOCH_NS: u128 = 1_700_000_000_000_000_000;
+/// Advance of the [`Clock::Logical`] timestamps per message, one millisecond.
+pub const LOGICAL_TICK_NS: u128 = 1_000_000;
 
 #[derive(Debug)]
 pub struct Message {
     pub topic: String,
     pub value: Vec<u8>,
     pub key: String,
+    /// Sent as Kafka headers, MQTT senders ignore them.
+    pub headers: Vec<(String, Vec<u8>)>,
+    /// Set by a [`sequence::Sequencer`], `None` for unstamped messages.
+    pub sequence: Option<SequenceStamp>,
+}
+
+/// Seed of the `stream`th generator of a run seeded with `seed`, e.g. one per producer thread.
+/// Different seeds or streams give unrelated generators. Their topics overlap, they all pick
+/// from the same plant, but their messages don't, as every message carries its stream seed in
+/// [`STREAM_FIELD`].
+pub fn stream_seed(seed: u64, stream: u64) -> u64 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&seed.to_le_bytes());
+    hasher.update(&stream.to_le_bytes());
+    let mut bytes = [0; 8];
+    hasher.finalize_xof().fill(&mut bytes);
+    u64::from_le_bytes(bytes)
 }
 
+/// Generators are deterministic: the same seed gives the same topics and messages, apart
+/// from the send timestamps unless they come from a [`Clock::Logical`].
 pub trait Generator {
-    fn new(split_point: usize, number_of_topics: usize) -> anyhow::Result<Self>
+    fn new(split_point: usize, number_of_topics: usize, seed: u64) -> anyhow::Result<Self>
+    where
+        Self: Sized;
+    /// Takes message timestamps from `clock`, [`Clock::Wall`] by default.
+    fn with_clock(self, clock: Clock) -> Self
     where
         Self: Sized;
-    fn get_message(&self) -> anyhow::Result<Message>;
+    fn get_message(&mut self) -> anyhow::Result<Message>;
     fn generate_topics(&mut self, number_of_topics: usize) -> anyhow::Result<()>;
 }
@@ -0,0 +1,218 @@
+use crate::generator::{Message, MetadataLocation, PRODUCER_ID_HEADER, SEQUENCE_HEADER};
+use std::collections::HashMap;
+
+/// Payload field holding the id of the producer that sent a message.
+pub const PRODUCER_ID_FIELD: &str = "producer_id";
+/// Payload field holding the per producer and topic/key sequence number.
+pub const SEQUENCE_FIELD: &str = "seq";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SequenceStamp {
+    pub producer_id: String,
+    pub seq: u64,
+}
+
+impl SequenceStamp {
+    /// Stable id of this message, independent of its content. Sequence numbers are counted
+    /// per re-assembled topic, so the id includes that `stream`.
+    pub fn id(&self, stream: &str) -> String {
+        format!("{}:{}:{}", self.producer_id, stream, self.seq)
+    }
+}
+
+/// Stamps messages with a producer id and a sequence number that increases by one per
+/// re-assembled topic (`<topic>.<key>`), starting at zero.
+pub struct Sequencer {
+    producer_id: String,
+    next: HashMap<String, u64>,
+}
+
+impl Sequencer {
+    pub fn new(producer_id: String) -> Self {
+        Self {
+            producer_id,
+            next: HashMap::new(),
+        }
+    }
+
+    /// Sets `message.sequence` and adds it to the JSON payload or the headers.
+    pub fn stamp(&mut self, message: &mut Message, location: MetadataLocation) {
+        let next = self
+            .next
+            .entry(format!("{}.{}", message.topic, message.key))
+            .or_default();
+        let stamp = SequenceStamp {
+            producer_id: self.producer_id.clone(),
+            seq: *next,
+        };
+        *next += 1;
+
+        match location {
+            MetadataLocation::Payload => insert_fields(&mut message.value, &stamp),
+            MetadataLocation::Headers => {
+                message.headers.push((
+                    PRODUCER_ID_HEADER.to_string(),
+                    stamp.producer_id.clone().into_bytes(),
+                ));
+                message.headers.push((
+                    SEQUENCE_HEADER.to_string(),
+                    stamp.seq.to_string().into_bytes(),
+                ));
+            }
+        }
+        message.sequence = Some(stamp);
+    }
+
+    /// Takes back the sequence number of the last message stamped on its topic/key when it
+    /// could not be sent, so the stream has no gap.
+    pub fn rewind(&mut self, message: &Message) {
+        let Some(stamp) = &message.sequence else {
+            return;
+        };
+        if let Some(next) = self
+            .next
+            .get_mut(&format!("{}.{}", message.topic, message.key))
+        {
+            if *next == stamp.seq + 1 {
+                *next = stamp.seq;
+            }
+        }
+    }
+}
+
+/// Inserts the stamp before the closing brace of a JSON object, leaves anything else alone.
+fn insert_fields(payload: &mut Vec<u8>, stamp: &SequenceStamp) {
+    let Some(end) = payload.iter().rposition(|b| *b == b'}') else {
+        return;
+    };
+    let empty = payload[..end]
+        .iter()
+        .rev()
+        .find(|b| !b.is_ascii_whitespace())
+        == Some(&b'{');
+    let fields = format!(
+        "{}\"{}\":\"{}\",\"{}\":\"{}\"",
+        if empty { "" } else { "," },
+        PRODUCER_ID_FIELD,
+        stamp.producer_id,
+        SEQUENCE_FIELD,
+        stamp.seq
+    );
+    payload.splice(end..end, fields.into_bytes());
+}
+
+/// Reads the stamp back from a JSON payload without fully parsing it.
+pub fn extract_sequence(payload: &[u8]) -> Option<SequenceStamp> {
+    let producer_id = find_string_field(payload, PRODUCER_ID_FIELD)?;
+    let seq = find_string_field(payload, SEQUENCE_FIELD)?.parse().ok()?;
+    Some(SequenceStamp { producer_id, seq })
+}
+
+/// Reads the stamp from Kafka headers or MQTT5 user properties.
+pub fn sequence_from_headers<'a>(
+    headers: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> Option<SequenceStamp> {
+    let (mut producer_id, mut seq) = (None, None);
+    for (key, value) in headers {
+        match key {
+            PRODUCER_ID_HEADER => producer_id = std::str::from_utf8(value).ok(),
+            SEQUENCE_HEADER => seq = std::str::from_utf8(value).ok()?.parse().ok(),
+            _ => {}
+        }
+    }
+    Some(SequenceStamp {
+        producer_id: producer_id?.to_string(),
+        seq: seq?,
+    })
+}
+
+/// Returns the value of `"field":"value"` or `"field":value`.
+fn find_string_field(payload: &[u8], field: &str) -> Option<String> {
+    let needle = format!("\"{}\"", field);
+    let start = payload
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())?
+        + needle.len();
+
+    let value: Vec<u8> = payload[start..]
+        .iter()
+        .skip_while(|b| matches!(b, b' ' | b':' | b'"'))
+        .take_while(|b| !matches!(b, b'"' | b',' | b'}'))
+        .copied()
+        .collect();
+    String::from_utf8(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::sequence::{
+        extract_sequence, sequence_from_headers, SequenceStamp, Sequencer,
+    };
+    use crate::generator::{Message, MetadataLocation};
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_sequencer() {
+        let message = |topic: &str, value: &str| Message {
+            topic: topic.to_string(),
+            value: value.as_bytes().to_vec(),
+            key: "k".to_string(),
+            headers: vec![],
+            sequence: None,
+        };
+        let mut sequencer = Sequencer::new("p1".to_string());
+
+        let mut first = message("a", r#"{"value":"1"}"#);
+        let mut second = message("a", "{}");
+        let mut other = message("b", r#"{"value":"1"}"#);
+        let mut in_headers = message("a", "{}");
+        sequencer.stamp(&mut first, MetadataLocation::Payload);
+        sequencer.stamp(&mut second, MetadataLocation::Payload);
+        sequencer.stamp(&mut other, MetadataLocation::Payload);
+        sequencer.stamp(&mut in_headers, MetadataLocation::Headers);
+
+        assert_eq!(
+            String::from_utf8(first.value.clone()).unwrap(),
+            r#"{"value":"1","producer_id":"p1","seq":"0"}"#
+        );
+        assert_eq!(
+            String::from_utf8(second.value.clone()).unwrap(),
+            r#"{"producer_id":"p1","seq":"1"}"#
+        );
+        assert_eq!(other.sequence.as_ref().unwrap().seq, 0);
+        assert_ne!(
+            first.sequence.as_ref().unwrap().id("a.k"),
+            other.sequence.as_ref().unwrap().id("b.k")
+        );
+        assert_eq!(
+            extract_sequence(&second.value),
+            Some(SequenceStamp {
+                producer_id: "p1".to_string(),
+                seq: 1
+            })
+        );
+        assert_eq!(extract_sequence(br#"{"value":"1"}"#), None);
+
+        assert_eq!(in_headers.value, b"{}");
+        assert_eq!(
+            sequence_from_headers(
+                in_headers
+                    .headers
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_slice()))
+            ),
+            Some(SequenceStamp {
+                producer_id: "p1".to_string(),
+                seq: 2
+            })
+        );
+
+        // A message that was never sent gives its number to the next one
+        let mut unsent = message("a", "{}");
+        sequencer.stamp(&mut unsent, MetadataLocation::Payload);
+        sequencer.rewind(&unsent);
+        let mut next = message("a", "{}");
+        sequencer.stamp(&mut next, MetadataLocation::Payload);
+        assert_eq!(next.sequence.unwrap().seq, 3);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// First 16 bytes of the blake3 digest of `<dotted topic><payload>`.
+pub type MessageHash = u128;
+
+const SHARDS: usize = 64;
+
+pub fn hash_message(hasher: &mut blake3::Hasher, topic: &str, payload: &[u8]) -> MessageHash {
+    hasher.reset();
+    hasher.update(topic.as_bytes());
+    hasher.update(payload);
+    truncate(hasher.finalize().as_bytes())
+}
+
+/// Parses a hex encoded blake3 digest, full length or already truncated.
+pub fn parse_hex_hash(hex_hash: &str) -> Option<MessageHash> {
+    let bytes = hex::decode(hex_hash).ok()?;
+    (bytes.len() >= 16).then(|| truncate(&bytes))
+}
+
+fn truncate(bytes: &[u8]) -> MessageHash {
+    let mut prefix = [0u8; 16];
+    prefix.copy_from_slice(&bytes[..16]);
+    u128::from_be_bytes(prefix)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Sent,
+    Received,
+}
+
+impl Side {
+    fn delta(self) -> i64 {
+        match self {
+            Side::Sent => 1,
+            Side::Received => -1,
+        }
+    }
+}
+
+/// Sharded multiset of message hashes. Sent messages count up and received messages count
+/// down, entries that reach zero are removed.
+///
+/// Given only to a sender or only to a receiver it holds every hash that side saw. Shared by
+/// both it reconciles them online and only holds the messages still in flight.
+#[derive(Debug)]
+pub struct HashLedger {
+    shards: Vec<Mutex<HashMap<MessageHash, i64>>>,
+}
+
+impl Default for HashLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashLedger {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    /// Records and drains `hashes`.
+    pub fn record(&self, hashes: &mut Vec<MessageHash>, side: Side) {
+        let delta = side.delta();
+        for hash in hashes.drain(..) {
+            #[allow(clippy::expect_used)]
+            let mut shard = self.shards[hash as usize % SHARDS]
+                .lock()
+                .expect("Failed to get ledger lock");
+            let count = shard.entry(hash).or_default();
+            *count += delta;
+            if *count == 0 {
+                shard.remove(&hash);
+            }
+        }
+    }
+
+    /// Number of distinct hashes currently held.
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                #[allow(clippy::expect_used)]
+                shard.lock().expect("Failed to get ledger lock").len()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` with every hash and its count, positive for sent and negative for received.
+    pub fn for_each(&self, mut f: impl FnMut(MessageHash, i64)) {
+        for shard in &self.shards {
+            #[allow(clippy::expect_used)]
+            let shard = shard.lock().expect("Failed to get ledger lock");
+            for (&hash, &count) in shard.iter() {
+                f(hash, count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashes::{hash_message, parse_hex_hash, HashLedger, Side};
+
+    #[test]
+    fn test_hash_ledger() {
+        let mut hasher = blake3::Hasher::new();
+        let a = hash_message(&mut hasher, "a.b", b"1");
+        let b = hash_message(&mut hasher, "a.b", b"2");
+        assert_ne!(a, b);
+
+        let full = blake3::hash(b"a.b1").to_hex();
+        assert_eq!(parse_hex_hash(full.as_str()), Some(a));
+        assert_eq!(parse_hex_hash("abcd"), None);
+
+        let ledger = HashLedger::new();
+        ledger.record(&mut vec![a, b], Side::Sent);
+        ledger.record(&mut vec![a], Side::Received);
+        assert_eq!(ledger.len(), 1);
+
+        ledger.record(&mut vec![b, b], Side::Received);
+        let mut counts = vec![];
+        ledger.for_each(|hash, count| counts.push((hash, count)));
+        assert_eq!(counts, vec![(b, -1)]);
+    }
+}
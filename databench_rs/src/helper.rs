@@ -1,5 +1,31 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-pub fn rand_entry<T>(entries: &[T]) -> &T {
-    &entries[thread_rng().gen_range(0..entries.len())]
+pub fn rand_entry<'a, T, R: Rng>(rng: &mut R, entries: &'a [T]) -> &'a T {
+    &entries[rng.gen_range(0..entries.len())]
+}
+
+/// Collects `KAFKA_BROKER_URL_0`, `KAFKA_BROKER_URL_1`, ... until the first missing index.
+pub fn kafka_brokers_from_env() -> Vec<String> {
+    (0..)
+        .map_while(|i| std::env::var(format!("KAFKA_BROKER_URL_{}", i)).ok())
+        .collect()
+}
+
+/// Parses the single `<address>:<port>` broker MQTT clients connect to.
+pub fn parse_mqtt_broker(brokers: &[String]) -> anyhow::Result<(String, u16)> {
+    if brokers.len() != 1 {
+        return Err(anyhow::anyhow!("Only one broker is supported for MQTT"));
+    }
+    let split_pos = match brokers[0].rfind(':') {
+        None => {
+            return Err(anyhow::anyhow!(
+                "Broker address must be in the format <address>:<port>"
+            ))
+        }
+        Some(v) => v,
+    };
+
+    let (broker, port) = brokers[0].split_at(split_pos);
+    let port = &port[1..];
+    Ok((broker.to_string(), port.parse::<u16>()?))
 }
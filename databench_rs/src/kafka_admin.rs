@@ -0,0 +1,303 @@
+use crate::kafka_config::{client_config, KafkaSecurity};
+use crate::runner::DEFAULT_KAFKA_TOPIC;
+use clap::ValueEnum;
+use futures::executor;
+use rdkafka::admin::{
+    AdminClient, AdminOptions, NewTopic, ResourceSpecifier, TopicReplication, TopicResult,
+};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::types::RDKafkaErrorCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time allowed for admin requests and for the cluster to apply them.
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What happens to the benchmark topic after the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TopicCleanup {
+    #[default]
+    Keep,
+    Delete,
+    /// Delete and recreate it with the same settings, leaving an empty topic.
+    Purge,
+}
+
+/// The benchmark topic, (re)created before a run so it never depends on earlier state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicSpec {
+    #[serde(default = "default_topic")]
+    pub name: String,
+    #[serde(default = "default_partitions")]
+    pub partitions: i32,
+    #[serde(default = "default_replication_factor")]
+    pub replication_factor: i32,
+    #[serde(default)]
+    pub min_insync_replicas: Option<u32>,
+    #[serde(default)]
+    pub retention_ms: Option<i64>,
+    /// Further topic configs, applied last.
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+    #[serde(default)]
+    pub after_run: TopicCleanup,
+}
+
+fn default_topic() -> String {
+    DEFAULT_KAFKA_TOPIC.to_string()
+}
+
+fn default_partitions() -> i32 {
+    1
+}
+
+fn default_replication_factor() -> i32 {
+    1
+}
+
+impl TopicSpec {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            partitions: default_partitions(),
+            replication_factor: default_replication_factor(),
+            min_insync_replicas: None,
+            retention_ms: None,
+            config: BTreeMap::new(),
+            after_run: TopicCleanup::default(),
+        }
+    }
+
+    /// Topic configs the topic is created with.
+    pub fn topic_config(&self) -> BTreeMap<String, String> {
+        let mut config = BTreeMap::new();
+        if let Some(min_insync_replicas) = self.min_insync_replicas {
+            config.insert(
+                "min.insync.replicas".to_string(),
+                min_insync_replicas.to_string(),
+            );
+        }
+        if let Some(retention_ms) = self.retention_ms {
+            config.insert("retention.ms".to_string(), retention_ms.to_string());
+        }
+        config.extend(self.config.clone());
+        config
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PartitionDescription {
+    pub id: i32,
+    pub leader: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+}
+
+/// Partitions and non-default configs of a topic as the cluster reports them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TopicDescription {
+    pub name: String,
+    pub partitions: Vec<PartitionDescription>,
+    pub config: BTreeMap<String, String>,
+}
+
+impl TopicDescription {
+    /// Partitions with fewer in-sync replicas than replicas.
+    pub fn under_replicated(&self) -> usize {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.isr.len() < partition.replicas.len())
+            .count()
+    }
+}
+
+pub struct KafkaAdmin {
+    admin: AdminClient<DefaultClientContext>,
+}
+
+impl KafkaAdmin {
+    pub fn new(brokers: &[String], security: &KafkaSecurity) -> anyhow::Result<Self> {
+        let mut config = BTreeMap::from([("bootstrap.servers".to_string(), brokers.join(","))]);
+        config.extend(
+            security
+                .settings()?
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        Ok(Self {
+            admin: client_config(&config).create()?,
+        })
+    }
+
+    /// Deletes the topic if it exists and creates it from `spec`.
+    pub fn create_topic(&self, spec: &TopicSpec) -> anyhow::Result<()> {
+        self.delete_topic(&spec.name)?;
+
+        let config = spec.topic_config();
+        let topic = config.iter().fold(
+            NewTopic::new(
+                &spec.name,
+                spec.partitions,
+                TopicReplication::Fixed(spec.replication_factor),
+            ),
+            |topic, (key, value)| topic.set(key, value),
+        );
+        println!(
+            "Creating topic {} with {} partitions and replication factor {}",
+            spec.name, spec.partitions, spec.replication_factor
+        );
+        let results = executor::block_on(self.admin.create_topics([&topic], &options()))?;
+        check(results, &[])?;
+        self.wait_for(&spec.name, true)
+    }
+
+    /// Deletes the topic and waits until it is gone, a missing topic is not an error.
+    pub fn delete_topic(&self, name: &str) -> anyhow::Result<()> {
+        if !self.exists(name)? {
+            return Ok(());
+        }
+        println!("Deleting topic {}", name);
+        let results = executor::block_on(self.admin.delete_topics(&[name], &options()))?;
+        // Someone else may have deleted it in the meantime
+        check(results, &[RDKafkaErrorCode::UnknownTopicOrPartition])?;
+        self.wait_for(name, false)
+    }
+
+    pub fn describe_topic(&self, name: &str) -> anyhow::Result<TopicDescription> {
+        let metadata = self.admin.inner().fetch_metadata(None, ADMIN_TIMEOUT)?;
+        let topic = metadata
+            .topics()
+            .iter()
+            .find(|topic| topic.name() == name)
+            .ok_or_else(|| anyhow::anyhow!("Topic {} does not exist", name))?;
+        let mut partitions: Vec<PartitionDescription> = topic
+            .partitions()
+            .iter()
+            .map(|partition| PartitionDescription {
+                id: partition.id(),
+                leader: partition.leader(),
+                replicas: partition.replicas().to_vec(),
+                isr: partition.isr().to_vec(),
+            })
+            .collect();
+        partitions.sort_by_key(|partition| partition.id);
+
+        let resources = executor::block_on(
+            self.admin
+                .describe_configs([&ResourceSpecifier::Topic(name)], &options()),
+        )?;
+        let mut config = BTreeMap::new();
+        for resource in resources {
+            let resource = resource.map_err(|e| anyhow::anyhow!("{:?}", e))?;
+            for entry in resource.entries {
+                if entry.is_default || entry.is_sensitive {
+                    continue;
+                }
+                if let Some(value) = entry.value {
+                    config.insert(entry.name, value);
+                }
+            }
+        }
+
+        Ok(TopicDescription {
+            name: name.to_string(),
+            partitions,
+            config,
+        })
+    }
+
+    /// Applies `spec.after_run`.
+    pub fn clean_up(&self, spec: &TopicSpec) -> anyhow::Result<()> {
+        match spec.after_run {
+            TopicCleanup::Keep => Ok(()),
+            TopicCleanup::Delete => self.delete_topic(&spec.name),
+            TopicCleanup::Purge => self.create_topic(spec),
+        }
+    }
+
+    /// Checks the cluster metadata, asking for the topic by name could auto-create it.
+    fn exists(&self, name: &str) -> anyhow::Result<bool> {
+        let metadata = self.admin.inner().fetch_metadata(None, ADMIN_TIMEOUT)?;
+        Ok(metadata.topics().iter().any(|topic| {
+            topic.name() == name
+                && topic.error().map(RDKafkaErrorCode::from)
+                    != Some(RDKafkaErrorCode::UnknownTopicOrPartition)
+        }))
+    }
+
+    /// Waits until every broker the metadata comes from agrees the topic (no longer) exists.
+    fn wait_for(&self, name: &str, exists: bool) -> anyhow::Result<()> {
+        let start = Instant::now();
+        while self.exists(name)? != exists {
+            if start.elapsed() > ADMIN_TIMEOUT {
+                return Err(anyhow::anyhow!(
+                    "Timed out waiting for topic {} to be {}",
+                    name,
+                    if exists { "created" } else { "deleted" }
+                ));
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        Ok(())
+    }
+}
+
+fn options() -> AdminOptions {
+    AdminOptions::new().operation_timeout(Some(ADMIN_TIMEOUT))
+}
+
+/// Fails on the first topic error that is not in `ignore`.
+fn check(results: Vec<TopicResult>, ignore: &[RDKafkaErrorCode]) -> anyhow::Result<()> {
+    for result in results {
+        match result {
+            Err((topic, code)) if !ignore.contains(&code) => {
+                return Err(anyhow::anyhow!("Topic {}: {}", topic, code))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kafka_admin::{PartitionDescription, TopicDescription, TopicSpec};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_topic_config() {
+        let mut spec = TopicSpec::new("bench");
+        spec.min_insync_replicas = Some(2);
+        spec.retention_ms = Some(3_600_000);
+        spec.config
+            .insert("retention.ms".to_string(), "60000".to_string());
+        let config = spec.topic_config();
+        assert_eq!(config["min.insync.replicas"], "2");
+        // Raw configs win
+        assert_eq!(config["retention.ms"], "60000");
+
+        let description = TopicDescription {
+            name: "bench".to_string(),
+            partitions: vec![
+                PartitionDescription {
+                    id: 0,
+                    leader: 1,
+                    replicas: vec![1, 2, 3],
+                    isr: vec![1, 2, 3],
+                },
+                PartitionDescription {
+                    id: 1,
+                    leader: 2,
+                    replicas: vec![2, 3, 1],
+                    isr: vec![2],
+                },
+            ],
+            config: BTreeMap::new(),
+        };
+        assert_eq!(description.under_replicated(), 1);
+    }
+}
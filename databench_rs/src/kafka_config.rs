@@ -0,0 +1,400 @@
+use crate::secret::Secret;
+use clap::ValueEnum;
+use rdkafka::ClientConfig;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Settings whose values are replaced before the config is printed or reported.
+const SECRET_SETTINGS: &[&str] = &[
+    "sasl.password",
+    "sasl.oauthbearer.client.secret",
+    "ssl.key.password",
+    "ssl.key.pem",
+    "ssl.keystore.password",
+];
+
+/// Named sets of librdkafka settings for common benchmark goals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KafkaPreset {
+    /// Large compressed batches, leader acks only
+    Throughput,
+    /// No batching delay or compression
+    LowLatency,
+    /// Idempotent producer acked by all in-sync replicas, consumers only see committed messages
+    ExactlyOnce,
+}
+
+impl KafkaPreset {
+    pub fn producer_settings(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            KafkaPreset::Throughput => &[
+                ("acks", "1"),
+                ("linger.ms", "50"),
+                ("batch.num.messages", "10000"),
+                ("batch.size", "1048576"),
+                ("compression.type", "lz4"),
+                ("enable.idempotence", "false"),
+            ],
+            KafkaPreset::LowLatency => &[
+                ("acks", "1"),
+                ("linger.ms", "0"),
+                ("compression.type", "none"),
+                ("enable.idempotence", "false"),
+                ("socket.nagle.disable", "true"),
+            ],
+            KafkaPreset::ExactlyOnce => &[
+                ("acks", "all"),
+                ("linger.ms", "5"),
+                ("compression.type", "lz4"),
+                ("enable.idempotence", "true"),
+                ("max.in.flight.requests.per.connection", "5"),
+            ],
+        }
+    }
+
+    pub fn consumer_settings(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            KafkaPreset::Throughput => {
+                &[("fetch.min.bytes", "1048576"), ("fetch.wait.max.ms", "100")]
+            }
+            KafkaPreset::LowLatency => &[
+                ("fetch.min.bytes", "1"),
+                ("fetch.wait.max.ms", "10"),
+                ("socket.nagle.disable", "true"),
+            ],
+            KafkaPreset::ExactlyOnce => &[("isolation.level", "read_committed")],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityProtocol {
+    Plaintext,
+    Ssl,
+    #[value(name = "sasl_plaintext")]
+    SaslPlaintext,
+    #[value(name = "sasl_ssl")]
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SecurityProtocol::Plaintext => "plaintext",
+            SecurityProtocol::Ssl => "ssl",
+            SecurityProtocol::SaslPlaintext => "sasl_plaintext",
+            SecurityProtocol::SaslSsl => "sasl_ssl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+pub enum SaslMechanism {
+    #[serde(rename = "plain")]
+    Plain,
+    #[value(name = "scram-sha-256")]
+    #[serde(rename = "scram-sha-256")]
+    ScramSha256,
+    #[value(name = "scram-sha-512")]
+    #[serde(rename = "scram-sha-512")]
+    ScramSha512,
+}
+
+impl SaslMechanism {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+/// TLS and SASL settings of a Kafka cluster, shared by all of its clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KafkaSecurity {
+    /// Defaults to `sasl_ssl` with SASL credentials, `ssl` with only certificates and the
+    /// librdkafka default (plaintext) otherwise.
+    #[serde(default)]
+    pub protocol: Option<SecurityProtocol>,
+    /// Defaults to PLAIN when credentials are given.
+    #[serde(default)]
+    pub sasl_mechanism: Option<SaslMechanism>,
+    #[serde(default)]
+    pub sasl_username: Option<Secret>,
+    #[serde(default)]
+    pub sasl_password: Option<Secret>,
+    /// CA certificate to verify the brokers with, the system store is used if not set.
+    #[serde(default)]
+    pub ca_location: Option<PathBuf>,
+    /// Client certificate and key for mTLS.
+    #[serde(default)]
+    pub certificate_location: Option<PathBuf>,
+    #[serde(default)]
+    pub key_location: Option<PathBuf>,
+    #[serde(default)]
+    pub key_password: Option<Secret>,
+}
+
+impl KafkaSecurity {
+    /// Resolves the secrets into librdkafka settings.
+    pub fn settings(&self) -> anyhow::Result<Vec<(&'static str, String)>> {
+        let sasl = self.sasl_username.is_some() || self.sasl_password.is_some();
+        let tls = self.ca_location.is_some()
+            || self.certificate_location.is_some()
+            || self.key_location.is_some();
+        if self.sasl_username.is_some() != self.sasl_password.is_some() {
+            return Err(anyhow::anyhow!(
+                "Kafka SASL needs both a username and a password"
+            ));
+        }
+        if self.certificate_location.is_some() != self.key_location.is_some() {
+            return Err(anyhow::anyhow!(
+                "Kafka mTLS needs both a client certificate and a key"
+            ));
+        }
+
+        let mut settings = vec![];
+        let protocol = match self.protocol {
+            Some(protocol) => Some(protocol),
+            None if sasl => Some(SecurityProtocol::SaslSsl),
+            None if tls => Some(SecurityProtocol::Ssl),
+            None => None,
+        };
+        if let Some(protocol) = protocol {
+            settings.push(("security.protocol", protocol.as_str().to_string()));
+        }
+        if let (Some(username), Some(password)) = (&self.sasl_username, &self.sasl_password) {
+            let mechanism = self.sasl_mechanism.unwrap_or(SaslMechanism::Plain);
+            settings.push(("sasl.mechanism", mechanism.as_str().to_string()));
+            settings.push(("sasl.username", username.resolve()?));
+            settings.push(("sasl.password", password.resolve()?));
+        }
+        let paths = [
+            ("ssl.ca.location", &self.ca_location),
+            ("ssl.certificate.location", &self.certificate_location),
+            ("ssl.key.location", &self.key_location),
+        ];
+        for (key, path) in paths {
+            if let Some(path) = path {
+                settings.push((key, path.display().to_string()));
+            }
+        }
+        if let Some(key_password) = &self.key_password {
+            settings.push(("ssl.key.password", key_password.resolve()?));
+        }
+        Ok(settings)
+    }
+}
+
+/// Preset, security and raw librdkafka overrides for one producer or consumer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KafkaClientSettings {
+    pub preset: Option<KafkaPreset>,
+    pub security: KafkaSecurity,
+    /// Applied last, so they win over databench's defaults, the preset and the security
+    /// settings.
+    pub overrides: BTreeMap<String, String>,
+}
+
+impl KafkaClientSettings {
+    /// Layers `defaults`, the preset's producer settings, security and the overrides.
+    pub fn producer_config(
+        &self,
+        defaults: &[(&str, &str)],
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        self.layer(
+            defaults,
+            self.preset.map(|preset| preset.producer_settings()),
+        )
+    }
+
+    /// Layers `defaults`, the preset's consumer settings, security and the overrides.
+    pub fn consumer_config(
+        &self,
+        defaults: &[(&str, &str)],
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        self.layer(
+            defaults,
+            self.preset.map(|preset| preset.consumer_settings()),
+        )
+    }
+
+    fn layer(
+        &self,
+        defaults: &[(&str, &str)],
+        preset: Option<&[(&str, &str)]>,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let mut config: BTreeMap<String, String> = defaults
+            .iter()
+            .chain(preset.unwrap_or_default())
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        config.extend(
+            self.security
+                .settings()?
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        config.extend(self.overrides.clone());
+        Ok(config)
+    }
+}
+
+/// Copy of `config` that is safe to print.
+pub fn redact(config: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    config
+        .iter()
+        .map(
+            |(key, value)| match SECRET_SETTINGS.contains(&key.as_str()) {
+                true => (key.clone(), "[redacted]".to_string()),
+                false => (key.clone(), value.clone()),
+            },
+        )
+        .collect()
+}
+
+/// Serializes librdkafka settings from a scenario with their secrets redacted.
+pub fn serialize_redacted<S: Serializer>(
+    config: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    redact(config).serialize(serializer)
+}
+
+/// Copy of a command line that is safe to print, with the values of secret settings passed
+/// as `--producer-config <key>=<value>` or `--producer-config=<key>=<value>` replaced.
+pub fn redact_arguments(arguments: impl IntoIterator<Item = String>) -> Vec<String> {
+    arguments
+        .into_iter()
+        .map(|argument| {
+            let (flag, setting) = match argument
+                .strip_prefix("--")
+                .and_then(|flag| flag.split_once('='))
+            {
+                Some((flag, setting)) => (format!("--{}=", flag), setting),
+                None => (String::new(), argument.as_str()),
+            };
+            match setting.split_once('=') {
+                Some((key, _)) if SECRET_SETTINGS.contains(&key) => {
+                    format!("{}{}=[redacted]", flag, key)
+                }
+                _ => argument.clone(),
+            }
+        })
+        .collect()
+}
+
+pub fn client_config(config: &BTreeMap<String, String>) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    for (key, value) in config {
+        client_config.set(key, value);
+    }
+    client_config
+}
+
+/// Parses a `key=value` librdkafka setting.
+pub fn parse_setting(setting: &str) -> anyhow::Result<(String, String)> {
+    match setting.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(anyhow::anyhow!(
+            "Kafka setting must be in the format <key>=<value>, got {}",
+            setting
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kafka_config::{
+        parse_setting, redact, redact_arguments, KafkaClientSettings, KafkaPreset, KafkaSecurity,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_layered_config() {
+        let settings = KafkaClientSettings {
+            preset: Some(KafkaPreset::ExactlyOnce),
+            overrides: [parse_setting("linger.ms=20").unwrap()].into(),
+            ..Default::default()
+        };
+        let config = settings
+            .producer_config(&[("bootstrap.servers", "b:9092"), ("acks", "1")])
+            .unwrap();
+        assert_eq!(config["bootstrap.servers"], "b:9092");
+        assert_eq!(config["acks"], "all");
+        assert_eq!(config["enable.idempotence"], "true");
+        assert_eq!(config["linger.ms"], "20");
+
+        let config = settings.consumer_config(&[]).unwrap();
+        assert_eq!(config["isolation.level"], "read_committed");
+
+        assert_eq!(
+            parse_setting("sasl.password=a=b").unwrap(),
+            ("sasl.password".to_string(), "a=b".to_string())
+        );
+        assert!(parse_setting("=x").is_err());
+        assert!(parse_setting("acks").is_err());
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_security_settings() {
+        std::env::set_var("DATABENCH_TEST_KAFKA_USER", "bench");
+        std::env::set_var("DATABENCH_TEST_KAFKA_PASSWORD", "hunter2");
+        let settings = KafkaClientSettings {
+            security: KafkaSecurity {
+                sasl_username: Some("env:DATABENCH_TEST_KAFKA_USER".parse().unwrap()),
+                sasl_password: Some("env:DATABENCH_TEST_KAFKA_PASSWORD".parse().unwrap()),
+                ca_location: Some(PathBuf::from("/etc/kafka/ca.crt")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = settings.consumer_config(&[]).unwrap();
+        assert_eq!(config["security.protocol"], "sasl_ssl");
+        assert_eq!(config["sasl.mechanism"], "PLAIN");
+        assert_eq!(config["sasl.username"], "bench");
+        assert_eq!(config["sasl.password"], "hunter2");
+        assert_eq!(config["ssl.ca.location"], "/etc/kafka/ca.crt");
+        assert_eq!(redact(&config)["sasl.password"], "[redacted]");
+        assert_eq!(redact(&config)["sasl.username"], "bench");
+
+        let mtls = KafkaSecurity {
+            certificate_location: Some(PathBuf::from("client.crt")),
+            ..Default::default()
+        };
+        assert!(mtls.settings().is_err());
+    }
+
+    #[test]
+    fn test_redact_arguments() {
+        let arguments = [
+            "databench",
+            "--producer-config",
+            "sasl.password=hunter2",
+            "--consumer-config=ssl.key.password=hunter2",
+            "--producer-config",
+            "linger.ms=5",
+            "--brokers=localhost:9092",
+        ]
+        .map(String::from);
+        assert_eq!(
+            redact_arguments(arguments),
+            vec![
+                "databench",
+                "--producer-config",
+                "sasl.password=[redacted]",
+                "--consumer-config=ssl.key.password=[redacted]",
+                "--producer-config",
+                "linger.ms=5",
+                "--brokers=localhost:9092",
+            ]
+        );
+    }
+}
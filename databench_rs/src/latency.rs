@@ -0,0 +1,293 @@
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+/// Payload field holding the send time in nanoseconds since the unix epoch.
+pub const TIMESTAMP_NS_FIELD: &str = "timestamp_ns";
+/// Millisecond fallback used by the UMH data model.
+pub const TIMESTAMP_MS_FIELD: &str = "timestamp_ms";
+
+/// Highest trackable latency (one hour) in microseconds.
+const HIGHEST_TRACKABLE_US: u64 = 3_600_000_000;
+const SIGNIFICANT_DIGITS: u8 = 3;
+
+/// How often receiver threads merge their latencies into the shared recorder, so the metrics
+/// endpoint stays current while messages trickle in.
+pub const MERGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Upper bounds of the exported histogram buckets in microseconds.
+pub const LATENCY_BUCKETS_US: &[u64] = &[
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Records produce-to-consume latencies overall and, if enabled, per topic.
+///
+/// Every histogram takes about 188KB, so the per-topic breakdown is off by default: with it,
+/// each receiver thread holds one histogram per `<topic>.<key>` it has seen.
+pub struct LatencyRecorder {
+    overall: Histogram<u64>,
+    by_topic: bool,
+    per_topic: HashMap<String, Histogram<u64>>,
+    without_timestamp: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub mean_us: f64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencyReport {
+    pub overall: LatencySummary,
+    pub per_topic: BTreeMap<String, LatencySummary>,
+    /// Messages that carried no send timestamp and were not recorded.
+    pub without_timestamp: u64,
+}
+
+/// Overall latencies as cumulative counts at [`LATENCY_BUCKETS_US`], the way Prometheus
+/// histograms are exported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyBuckets {
+    /// Upper bound in microseconds and the number of latencies up to it.
+    pub buckets: Vec<(u64, u64)>,
+    pub sum_us: f64,
+    pub count: u64,
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyRecorder {
+    pub fn new() -> Self {
+        Self {
+            overall: new_histogram(),
+            by_topic: false,
+            per_topic: HashMap::new(),
+            without_timestamp: 0,
+        }
+    }
+
+    /// Also keeps a histogram per re-assembled dotted topic.
+    pub fn with_per_topic(mut self, per_topic: bool) -> Self {
+        self.by_topic = per_topic;
+        self
+    }
+
+    /// Records the latency of a message given its re-assembled dotted topic and payload.
+    pub fn record(&mut self, topic: &str, payload: &[u8], received_at: SystemTime) {
+        let Some(sent_ns) = extract_send_timestamp_ns(payload) else {
+            self.without_timestamp += 1;
+            return;
+        };
+        self.record_sent_ns(topic, sent_ns, received_at);
+    }
+
+    /// Records the latency of a message whose send timestamp is already known,
+    /// e.g. from a header or MQTT5 user property.
+    pub fn record_sent_ns(&mut self, topic: &str, sent_ns: u128, received_at: SystemTime) {
+        let received_ns = received_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        // Clocks can be slightly off between hosts, count those as zero latency
+        let latency_us = (received_ns.saturating_sub(sent_ns) / 1_000) as u64;
+
+        self.overall.saturating_record(latency_us);
+        if !self.by_topic {
+            return;
+        }
+        match self.per_topic.get_mut(topic) {
+            Some(histogram) => histogram.saturating_record(latency_us),
+            None => {
+                let mut histogram = new_histogram();
+                histogram.saturating_record(latency_us);
+                self.per_topic.insert(topic.to_string(), histogram);
+            }
+        }
+    }
+
+    /// Moves everything recorded in `other` into this recorder. The histograms of `other` are
+    /// reset rather than dropped, so a thread merging regularly keeps reusing them.
+    pub fn merge(&mut self, other: &mut LatencyRecorder) {
+        if let Err(e) = self.overall.add(&other.overall) {
+            eprintln!("Error merging latency histograms: {:?}", e);
+        }
+        other.overall.reset();
+        for (topic, histogram) in other.per_topic.iter_mut() {
+            if histogram.is_empty() {
+                continue;
+            }
+            match self.per_topic.get_mut(topic) {
+                Some(existing) => {
+                    if let Err(e) = existing.add(&*histogram) {
+                        eprintln!("Error merging latency histograms: {:?}", e);
+                    }
+                }
+                None => {
+                    self.per_topic.insert(topic.clone(), histogram.clone());
+                }
+            }
+            histogram.reset();
+        }
+        self.without_timestamp += other.without_timestamp;
+        other.without_timestamp = 0;
+    }
+
+    pub fn buckets(&self) -> LatencyBuckets {
+        LatencyBuckets {
+            buckets: LATENCY_BUCKETS_US
+                .iter()
+                .map(|&bound| (bound, self.overall.count_between(0, bound)))
+                .collect(),
+            sum_us: self.overall.mean() * self.overall.len() as f64,
+            count: self.overall.len(),
+        }
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        LatencyReport {
+            overall: summarize(&self.overall),
+            per_topic: self
+                .per_topic
+                .iter()
+                .map(|(topic, histogram)| (topic.clone(), summarize(histogram)))
+                .collect(),
+            without_timestamp: self.without_timestamp,
+        }
+    }
+}
+
+#[allow(clippy::expect_used)]
+fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_max(HIGHEST_TRACKABLE_US, SIGNIFICANT_DIGITS)
+        .expect("Histogram bounds are constant and valid")
+}
+
+fn summarize(histogram: &Histogram<u64>) -> LatencySummary {
+    if histogram.is_empty() {
+        return LatencySummary::default();
+    }
+    LatencySummary {
+        count: histogram.len(),
+        mean_us: histogram.mean(),
+        p50_us: histogram.value_at_quantile(0.5),
+        p90_us: histogram.value_at_quantile(0.9),
+        p99_us: histogram.value_at_quantile(0.99),
+        p999_us: histogram.value_at_quantile(0.999),
+        max_us: histogram.max(),
+    }
+}
+
+/// Finds the send timestamp in a generator JSON payload without fully parsing it.
+/// Prefers [`TIMESTAMP_NS_FIELD`] and falls back to [`TIMESTAMP_MS_FIELD`].
+pub fn extract_send_timestamp_ns(payload: &[u8]) -> Option<u128> {
+    if let Some(ns) = find_number_field(payload, TIMESTAMP_NS_FIELD) {
+        return Some(ns);
+    }
+    find_number_field(payload, TIMESTAMP_MS_FIELD).map(|ms| ms * 1_000_000)
+}
+
+/// Returns the value of `"field":123` or `"field":"123"`.
+fn find_number_field(payload: &[u8], field: &str) -> Option<u128> {
+    let needle = format!("\"{}\"", field);
+    let start = payload
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())?
+        + needle.len();
+
+    payload[start..]
+        .iter()
+        .skip_while(|b| matches!(b, b' ' | b':' | b'"'))
+        .take_while(|b| b.is_ascii_digit())
+        .fold(None, |acc: Option<u128>, b| {
+            Some(acc.unwrap_or(0).checked_mul(10)? + u128::from(b - b'0'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::latency::{extract_send_timestamp_ns, LatencyRecorder};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_extract_send_timestamp() {
+        assert_eq!(
+            extract_send_timestamp_ns(br#"{"timestamp_ms":"1700000000000","value":"1"}"#),
+            Some(1_700_000_000_000_000_000)
+        );
+        assert_eq!(
+            extract_send_timestamp_ns(
+                br#"{"timestamp_ms":"1700000000000","timestamp_ns": 1700000000000123456}"#
+            ),
+            Some(1_700_000_000_000_123_456)
+        );
+        assert_eq!(extract_send_timestamp_ns(br#"{"value":"1"}"#), None);
+    }
+
+    #[test]
+    fn test_latency_recorder() {
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let sent_ns = sent
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let payload = format!("{{\"timestamp_ns\":\"{}\"}}", sent_ns);
+
+        let mut recorder = LatencyRecorder::new().with_per_topic(true);
+        let mut other = LatencyRecorder::new().with_per_topic(true);
+        for ms in 1..=100 {
+            let target = if ms % 2 == 0 {
+                &mut recorder
+            } else {
+                &mut other
+            };
+            target.record("a.b", payload.as_bytes(), sent + Duration::from_millis(ms));
+        }
+        other.record("a.c", b"{}", sent);
+        recorder.merge(&mut other);
+
+        let report = recorder.report();
+        assert_eq!(report.overall.count, 100);
+        assert_eq!(report.without_timestamp, 1);
+        assert_eq!(report.per_topic["a.b"].count, 100);
+        assert!(!report.per_topic.contains_key("a.c"));
+        assert!((49_900..=50_100).contains(&report.overall.p50_us));
+        assert!((99_000..=100_100).contains(&report.overall.max_us));
+
+        let buckets = recorder.buckets();
+        assert_eq!(buckets.count, 100);
+        assert_eq!(buckets.buckets[8], (50_000, 50));
+        assert_eq!(buckets.buckets.last(), Some(&(10_000_000, 100)));
+
+        // Merged histograms are emptied but kept for the next round
+        assert_eq!(other.report().per_topic["a.b"].count, 0);
+        other.record("a.b", payload.as_bytes(), sent + Duration::from_millis(1));
+        recorder.merge(&mut other);
+        assert_eq!(recorder.report().per_topic["a.b"].count, 101);
+    }
+
+    #[test]
+    fn test_latency_recorder_without_per_topic() {
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut recorder = LatencyRecorder::new();
+        let mut other = LatencyRecorder::new();
+        recorder.record_sent_ns("a.b", 1_700_000_000_000_000_000, sent);
+        other.record_sent_ns("a.c", 1_700_000_000_000_000_000, sent);
+        recorder.merge(&mut other);
+
+        let report = recorder.report();
+        assert_eq!(report.overall.count, 2);
+        assert!(report.per_topic.is_empty());
+    }
+}
@@ -4,8 +4,21 @@
 #![deny(clippy::unwrap_used)]
 #![deny(clippy::expect_used)]
 
+pub mod errors;
 pub mod generator;
+pub mod hashes;
 pub mod helper;
+pub mod kafka_admin;
+pub mod kafka_config;
+pub mod latency;
+pub mod metrics;
+pub mod mqtt_config;
 pub mod receiver;
+pub mod report;
+pub mod runner;
+pub mod scenario;
+pub mod secret;
 pub mod sender;
 pub mod tests;
+pub mod throughput;
+pub mod verify;
@@ -0,0 +1,614 @@
+#![deny(clippy::panic)]
+#![deny(clippy::panicking_unwrap)]
+#![deny(unsafe_code)]
+#![deny(clippy::unwrap_used)]
+#![deny(clippy::expect_used)]
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use databench_rs::generator::{Clock, MetadataLocation};
+use databench_rs::helper::kafka_brokers_from_env;
+use databench_rs::kafka_admin::{TopicCleanup, TopicSpec};
+use databench_rs::kafka_config::{
+    parse_setting, KafkaClientSettings, KafkaPreset, KafkaSecurity, SaslMechanism, SecurityProtocol,
+};
+use databench_rs::metrics::{Metrics, MetricsServer};
+use databench_rs::mqtt_config::MqttSecurity;
+use databench_rs::receiver::commit::{CommitMode, CommitStrategy};
+use databench_rs::report::{PhaseReport, RunBrokers, RunReport};
+use databench_rs::runner::{
+    self, GeneratorKind, KafkaReceiveOptions, KafkaSendOptions, MqttReceiveOptions,
+    MqttSendOptions, PhaseOptions, ReceiveOptions, ReceiverKind, SendOptions, SenderKind,
+    TopicOptions, DEFAULT_KAFKA_TOPIC,
+};
+use databench_rs::scenario::{PhaseKind, Scenario};
+use databench_rs::secret::Secret;
+use databench_rs::sender::kafka::Transactions;
+use databench_rs::sender::rate::{RateLimit, RateUnit};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "databench-rs", version, about = "Benchmarks the UMH stack")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    report: ReportArgs,
+    /// Serve Prometheus metrics of the running phase on http://<ADDR>/metrics, e.g. 0.0.0.0:9898
+    #[arg(long, global = true, value_name = "ADDR")]
+    metrics_addr: Option<String>,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Write a JSON report of the run with its parameters, results and errors to this file
+    #[arg(long, global = true)]
+    report: Option<PathBuf>,
+    /// Write a CSV summary with one row per phase and receiver to this file
+    #[arg(long, global = true)]
+    report_csv: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Produce generated messages to Kafka or MQTT
+    Send(SendArgs),
+    /// Consume messages from Kafka or MQTT
+    Receive(ReceiveArgs),
+    /// Produce while consuming from Kafka or MQTT and compare both sides
+    Roundtrip(RoundtripArgs),
+    /// Run the phases of a TOML or YAML scenario file in order
+    Scenario(ScenarioArgs),
+}
+
+#[derive(Args)]
+struct BrokerArgs {
+    /// Comma separated Kafka brokers [default: KAFKA_BROKER_URL_0, KAFKA_BROKER_URL_1, ...]
+    #[arg(long, value_delimiter = ',')]
+    kafka_brokers: Vec<String>,
+    /// MQTT broker as <host>:<port>
+    #[arg(long, env = "MQTT_BROKER_URL")]
+    mqtt_broker: Option<String>,
+    #[command(flatten)]
+    kafka_security: KafkaSecurityArgs,
+    #[command(flatten)]
+    mqtt_security: MqttSecurityArgs,
+}
+
+#[derive(Args)]
+struct MqttSecurityArgs {
+    /// Connect to the MQTT broker with TLS, implied by the certificate options
+    #[arg(long)]
+    mqtt_tls: bool,
+    /// CA certificate to verify the MQTT broker with [default: system certificates]
+    #[arg(long)]
+    mqtt_ca: Option<PathBuf>,
+    /// Client certificate for MQTT client certificate authentication, needs --mqtt-ca
+    #[arg(long)]
+    mqtt_cert: Option<PathBuf>,
+    /// Client key for MQTT client certificate authentication
+    #[arg(long)]
+    mqtt_key: Option<PathBuf>,
+    /// MQTT username as env:<VARIABLE> or file:<path>
+    #[arg(long)]
+    mqtt_username: Option<Secret>,
+    /// MQTT password as env:<VARIABLE> or file:<path>
+    #[arg(long)]
+    mqtt_password: Option<Secret>,
+}
+
+impl MqttSecurityArgs {
+    fn security(&self) -> MqttSecurity {
+        MqttSecurity {
+            tls: self.mqtt_tls,
+            ca_location: self.mqtt_ca.clone(),
+            certificate_location: self.mqtt_cert.clone(),
+            key_location: self.mqtt_key.clone(),
+            username: self.mqtt_username.clone(),
+            password: self.mqtt_password.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct KafkaSecurityArgs {
+    /// Kafka security protocol [default: sasl_ssl with SASL credentials, ssl with certificates]
+    #[arg(long, value_enum)]
+    kafka_security_protocol: Option<SecurityProtocol>,
+    /// Kafka SASL mechanism [default: plain]
+    #[arg(long, value_enum)]
+    kafka_sasl_mechanism: Option<SaslMechanism>,
+    /// Kafka SASL username as env:<VARIABLE> or file:<path>
+    #[arg(long)]
+    kafka_sasl_username: Option<Secret>,
+    /// Kafka SASL password as env:<VARIABLE> or file:<path>
+    #[arg(long)]
+    kafka_sasl_password: Option<Secret>,
+    /// CA certificate to verify the Kafka brokers with
+    #[arg(long)]
+    kafka_ssl_ca: Option<PathBuf>,
+    /// Client certificate for Kafka mTLS
+    #[arg(long)]
+    kafka_ssl_cert: Option<PathBuf>,
+    /// Client key for Kafka mTLS
+    #[arg(long)]
+    kafka_ssl_key: Option<PathBuf>,
+    /// Password of the Kafka client key as env:<VARIABLE> or file:<path>
+    #[arg(long)]
+    kafka_ssl_key_password: Option<Secret>,
+}
+
+impl KafkaSecurityArgs {
+    fn security(&self) -> KafkaSecurity {
+        KafkaSecurity {
+            protocol: self.kafka_security_protocol,
+            sasl_mechanism: self.kafka_sasl_mechanism,
+            sasl_username: self.kafka_sasl_username.clone(),
+            sasl_password: self.kafka_sasl_password.clone(),
+            ca_location: self.kafka_ssl_ca.clone(),
+            certificate_location: self.kafka_ssl_cert.clone(),
+            key_location: self.kafka_ssl_key.clone(),
+            key_password: self.kafka_ssl_key_password.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct SenderArgs {
+    #[arg(long, value_enum, default_value_t = SenderKind::Kafka)]
+    sender: SenderKind,
+    #[command(flatten)]
+    generator: GeneratorArgs,
+    #[command(flatten)]
+    rate: RateArgs,
+    /// Stamp messages with a producer id and per topic sequence number to detect gaps and reordering
+    #[arg(long)]
+    sequence_numbers: bool,
+    /// Kafka only: carry the send timestamp and sequence stamp in the payload or in headers
+    #[arg(long, value_enum, default_value_t = MetadataLocation::Payload)]
+    metadata: MetadataLocation,
+    /// Number of Kafka producer threads, each with its own generator and producer
+    #[arg(long, default_value_t = KafkaSendOptions::default().threads)]
+    threads: usize,
+    /// Named set of producer settings
+    #[arg(long, value_enum)]
+    producer_preset: Option<KafkaPreset>,
+    /// librdkafka producer setting as <key>=<value>, applied after the preset, repeatable
+    #[arg(long, value_parser = parse_setting)]
+    producer_config: Vec<(String, String)>,
+    /// Send Kafka messages in transactions of this many messages, only committed ones count as sent
+    #[arg(long)]
+    transaction_size: Option<usize>,
+    /// Fraction of transactions to abort, their messages must never be received
+    #[arg(long, default_value_t = 0.0, requires = "transaction_size")]
+    abort_ratio: f64,
+    /// MQTT QoS level (0, 1 or 2)
+    #[arg(long, default_value_t = MqttSendOptions::default().qos)]
+    qos: u8,
+    /// Publish MQTT messages with the retain flag
+    #[arg(long)]
+    retain: bool,
+    /// Number of parallel MQTT client connections
+    #[arg(long, default_value_t = MqttSendOptions::default().connections)]
+    connections: usize,
+}
+
+#[derive(Args)]
+struct GeneratorArgs {
+    #[arg(long, value_enum, default_value_t = GeneratorKind::Chernobyl)]
+    generator: GeneratorKind,
+    /// Number of topic segments that form the Kafka topic, the rest becomes the key
+    #[arg(long, default_value_t = 3)]
+    split_point: usize,
+    /// Number of distinct topics to generate
+    #[arg(long, default_value_t = 100)]
+    topics: usize,
+    /// Seed for topics and messages, the same seed sends the same messages apart from their
+    /// timestamps. Random if not set, the seed used is printed and reported
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Clock of the payload timestamps, logical makes seeded payloads identical across runs
+    /// and needs --metadata headers
+    #[arg(long, value_enum, default_value_t = Clock::Wall)]
+    clock: Clock,
+}
+
+#[derive(Args)]
+struct RateArgs {
+    /// Constant target rate, unlimited if not set. Use a scenario for other load profiles
+    #[arg(long)]
+    rate: Option<f64>,
+    #[arg(long, value_enum, default_value_t = RateUnitArg::Messages)]
+    rate_unit: RateUnitArg,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RateUnitArg {
+    Messages,
+    Bytes,
+}
+
+#[derive(Args)]
+struct ReceiverArgs {
+    #[arg(long, value_enum, default_value_t = ReceiverKind::Kafka)]
+    receiver: ReceiverKind,
+    /// Topic to receive from [default: depends on the receiver]
+    #[arg(long)]
+    topic: Option<String>,
+    /// Report latencies per <topic>.<key> besides overall, one histogram per stream and thread
+    #[arg(long)]
+    latency_per_topic: bool,
+    /// Kafka consumer group [default: unique per run]
+    #[arg(long)]
+    group_id: Option<String>,
+    /// Number of Kafka consumers in the group, each on its own thread
+    #[arg(long, default_value_t = KafkaReceiveOptions::default().consumers)]
+    consumers: usize,
+    /// Named set of consumer settings
+    #[arg(long, value_enum)]
+    consumer_preset: Option<KafkaPreset>,
+    /// librdkafka consumer setting as <key>=<value>, applied after the preset, repeatable
+    #[arg(long, value_parser = parse_setting)]
+    consumer_config: Vec<(String, String)>,
+    /// When Kafka consumers commit their offsets
+    #[arg(long, value_enum, default_value_t = CommitMode::Auto)]
+    commit_strategy: CommitMode,
+    /// Messages between synchronous commits with --commit-strategy sync
+    #[arg(long, default_value_t = 1000)]
+    commit_every: u64,
+    /// Milliseconds between asynchronous commits with --commit-strategy async
+    #[arg(long, default_value_t = 1000)]
+    commit_interval_ms: u64,
+    /// Kill the first Kafka consumer without committing after this many seconds and start a new
+    /// one, the others follow over the same time again. Needs a commit strategy other than auto
+    #[arg(long)]
+    restart_after: Option<u64>,
+    /// MQTT5 shared subscription group, subscribes to $share/<group>/<topic>
+    #[arg(long)]
+    shared_group: Option<String>,
+    /// Number of parallel MQTT5 receiver connections
+    #[arg(long, default_value_t = MqttReceiveOptions::default().connections)]
+    receiver_connections: usize,
+    /// MQTT3 receiver client id [default: unique per run]
+    #[arg(long)]
+    client_id: Option<String>,
+    /// Keep the MQTT3 session on the broker across reconnects instead of starting clean
+    #[arg(long)]
+    persistent_session: bool,
+}
+
+#[derive(Args)]
+struct TopicArgs {
+    /// Delete and recreate the Kafka receiver's topic before the run
+    #[arg(long)]
+    create_topic: bool,
+    /// Partitions of the created topic
+    #[arg(long, default_value_t = 1, requires = "create_topic")]
+    partitions: i32,
+    /// Replication factor of the created topic
+    #[arg(long, default_value_t = 1, requires = "create_topic")]
+    replication_factor: i32,
+    #[arg(long, requires = "create_topic")]
+    min_insync_replicas: Option<u32>,
+    #[arg(long, requires = "create_topic")]
+    retention_ms: Option<i64>,
+    /// Topic config as <key>=<value>, repeatable
+    #[arg(long, value_parser = parse_setting, requires = "create_topic")]
+    topic_config: Vec<(String, String)>,
+    /// What to do with the created topic after the run
+    #[arg(long, value_enum, default_value_t = TopicCleanup::Keep, requires = "create_topic")]
+    after_run: TopicCleanup,
+}
+
+#[derive(Args)]
+struct SendArgs {
+    #[command(flatten)]
+    brokers: BrokerArgs,
+    #[command(flatten)]
+    sender: SenderArgs,
+    #[command(flatten)]
+    topic: TopicArgs,
+    /// Seconds to send for
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+}
+
+#[derive(Args)]
+struct ReceiveArgs {
+    #[command(flatten)]
+    brokers: BrokerArgs,
+    #[command(flatten)]
+    receiver: ReceiverArgs,
+    #[command(flatten)]
+    topic: TopicArgs,
+    /// Seconds to receive for
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+}
+
+#[derive(Args)]
+struct RoundtripArgs {
+    #[command(flatten)]
+    brokers: BrokerArgs,
+    #[command(flatten)]
+    sender: SenderArgs,
+    #[command(flatten)]
+    receiver: ReceiverArgs,
+    #[command(flatten)]
+    topic: TopicArgs,
+    /// Seconds to send for
+    #[arg(long, default_value_t = 60)]
+    duration: u64,
+    /// Maximum seconds to wait for the receiver to catch up after sending stopped
+    #[arg(long, default_value_t = 120)]
+    drain: u64,
+    /// Reconcile sent and received hashes while running, keeping only in-flight messages in memory
+    #[arg(long)]
+    streaming_verification: bool,
+}
+
+#[derive(Args)]
+struct ScenarioArgs {
+    /// Path to a .toml, .yaml or .yml scenario
+    path: PathBuf,
+}
+
+impl RateArgs {
+    fn rate_limit(&self) -> Option<RateLimit> {
+        let unit = match self.rate_unit {
+            RateUnitArg::Messages => RateUnit::Messages,
+            RateUnitArg::Bytes => RateUnit::Bytes,
+        };
+        self.rate.map(|rate| RateLimit::constant(rate, unit))
+    }
+}
+
+impl BrokerArgs {
+    fn kafka(&self) -> anyhow::Result<Vec<String>> {
+        let brokers = if self.kafka_brokers.is_empty() {
+            kafka_brokers_from_env()
+        } else {
+            self.kafka_brokers.clone()
+        };
+        if brokers.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No Kafka brokers given, use --kafka-brokers or KAFKA_BROKER_URL_0"
+            ));
+        }
+        Ok(brokers)
+    }
+
+    fn mqtt(&self) -> anyhow::Result<Vec<String>> {
+        match &self.mqtt_broker {
+            Some(broker) => Ok(vec![broker.clone()]),
+            None => Err(anyhow::anyhow!(
+                "No MQTT broker given, use --mqtt-broker or MQTT_BROKER_URL"
+            )),
+        }
+    }
+
+    fn run_brokers(&self) -> RunBrokers {
+        RunBrokers {
+            kafka: self.kafka().unwrap_or_default(),
+            mqtt: self.mqtt_broker.clone(),
+        }
+    }
+
+    fn send_options(&self, sender: &SenderArgs) -> anyhow::Result<SendOptions> {
+        Ok(SendOptions {
+            sender: sender.sender,
+            brokers: match sender.sender {
+                SenderKind::Kafka => self.kafka()?,
+                SenderKind::Mqtt => self.mqtt()?,
+            },
+            generator: sender.generator.generator,
+            split_point: sender.generator.split_point,
+            topics: sender.generator.topics,
+            seed: sender.generator.seed,
+            rate: sender.rate.rate_limit(),
+            sequence_numbers: sender.sequence_numbers,
+            metadata: sender.metadata,
+            clock: sender.generator.clock,
+            kafka: KafkaSendOptions {
+                threads: sender.threads,
+                client: KafkaClientSettings {
+                    preset: sender.producer_preset,
+                    security: self.kafka_security.security(),
+                    overrides: sender.producer_config.iter().cloned().collect(),
+                },
+                transactions: sender.transaction_size.map(|size| Transactions {
+                    size,
+                    abort_ratio: sender.abort_ratio,
+                }),
+            },
+            mqtt: MqttSendOptions {
+                qos: sender.qos,
+                retain: sender.retain,
+                connections: sender.connections,
+                security: self.mqtt_security.security(),
+            },
+        })
+    }
+
+    fn receive_options(&self, receiver: &ReceiverArgs) -> anyhow::Result<ReceiveOptions> {
+        Ok(ReceiveOptions {
+            receiver: receiver.receiver,
+            brokers: match receiver.receiver {
+                ReceiverKind::Kafka => self.kafka()?,
+                ReceiverKind::Mqtt | ReceiverKind::Mqtt5 => self.mqtt()?,
+            },
+            topic: receiver
+                .topic
+                .clone()
+                .unwrap_or_else(|| receiver.receiver.default_topic().to_string()),
+            latency_per_topic: receiver.latency_per_topic,
+            kafka: KafkaReceiveOptions {
+                group_id: receiver.group_id.clone(),
+                consumers: receiver.consumers,
+                client: KafkaClientSettings {
+                    preset: receiver.consumer_preset,
+                    security: self.kafka_security.security(),
+                    overrides: receiver.consumer_config.iter().cloned().collect(),
+                },
+                commit: CommitStrategy::from_mode(
+                    receiver.commit_strategy,
+                    receiver.commit_every,
+                    receiver.commit_interval_ms,
+                ),
+                restart_after: receiver.restart_after.map(Duration::from_secs),
+            },
+            mqtt: MqttReceiveOptions {
+                shared_group: receiver.shared_group.clone(),
+                connections: receiver.receiver_connections,
+                client_id: receiver.client_id.clone(),
+                clean_session: !receiver.persistent_session,
+                security: self.mqtt_security.security(),
+            },
+        })
+    }
+}
+
+impl TopicArgs {
+    /// The topic is the Kafka receiver's, or the default Kafka topic without one.
+    fn topic_options(
+        &self,
+        brokers: &BrokerArgs,
+        receiver: Option<&ReceiverArgs>,
+    ) -> anyhow::Result<Option<TopicOptions>> {
+        if !self.create_topic {
+            return Ok(None);
+        }
+        let name = match receiver {
+            Some(receiver) if receiver.receiver == ReceiverKind::Kafka => receiver
+                .topic
+                .clone()
+                .unwrap_or_else(|| DEFAULT_KAFKA_TOPIC.to_string()),
+            _ => DEFAULT_KAFKA_TOPIC.to_string(),
+        };
+        Ok(Some(TopicOptions {
+            brokers: brokers.kafka()?,
+            security: brokers.kafka_security.security(),
+            spec: TopicSpec {
+                partitions: self.partitions,
+                replication_factor: self.replication_factor,
+                min_insync_replicas: self.min_insync_replicas,
+                retention_ms: self.retention_ms,
+                config: self.topic_config.iter().cloned().collect(),
+                after_run: self.after_run,
+                ..TopicSpec::new(&name)
+            },
+        }))
+    }
+}
+
+impl ReportArgs {
+    fn write(&self, report: &RunReport) -> anyhow::Result<()> {
+        if let Some(path) = &self.report {
+            std::fs::write(path, serde_json::to_string_pretty(report)?)?;
+            println!("Wrote report to {}", path.display());
+        }
+        if let Some(path) = &self.report_csv {
+            std::fs::write(path, report.to_csv())?;
+            println!("Wrote CSV report to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Starts the metrics server if an address was given. The server stops when dropped.
+fn serve_metrics(addr: Option<&str>) -> anyhow::Result<Option<(Arc<Metrics>, MetricsServer)>> {
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+    let metrics = Arc::new(Metrics::new());
+    let server = MetricsServer::start(addr, metrics.clone())?;
+    println!("Serving metrics on http://{}/metrics", server.local_addr());
+    Ok(Some((metrics, server)))
+}
+
+/// Runs a single phase command and reports it as a run of its own.
+fn run_command(
+    name: &str,
+    options: &PhaseOptions,
+    brokers: RunBrokers,
+    report: &ReportArgs,
+) -> anyhow::Result<()> {
+    let result = runner::run_phase(options)?;
+    let phase = PhaseReport::new(name, PhaseKind::Steady, &result);
+    report.write(&RunReport::new(brokers, vec![phase]))
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+    let served = serve_metrics(cli.metrics_addr.as_deref())?;
+    let metrics = served.as_ref().map(|(metrics, _)| metrics.clone());
+
+    match cli.command {
+        Command::Send(args) => {
+            let options = PhaseOptions {
+                send: Some(args.brokers.send_options(&args.sender)?),
+                receivers: vec![],
+                duration: Duration::from_secs(args.duration),
+                drain: Duration::ZERO,
+                streaming_verification: false,
+                topic: args.topic.topic_options(&args.brokers, None)?,
+                metrics: metrics.clone(),
+            };
+            run_command("send", &options, args.brokers.run_brokers(), &cli.report)?;
+        }
+        Command::Receive(args) => {
+            let options = PhaseOptions {
+                send: None,
+                receivers: vec![args.brokers.receive_options(&args.receiver)?],
+                duration: Duration::from_secs(args.duration),
+                drain: Duration::ZERO,
+                streaming_verification: false,
+                topic: args
+                    .topic
+                    .topic_options(&args.brokers, Some(&args.receiver))?,
+                metrics: metrics.clone(),
+            };
+            run_command("receive", &options, args.brokers.run_brokers(), &cli.report)?;
+        }
+        Command::Roundtrip(args) => {
+            let options = PhaseOptions {
+                send: Some(args.brokers.send_options(&args.sender)?),
+                receivers: vec![args.brokers.receive_options(&args.receiver)?],
+                duration: Duration::from_secs(args.duration),
+                drain: Duration::from_secs(args.drain),
+                streaming_verification: args.streaming_verification,
+                topic: args
+                    .topic
+                    .topic_options(&args.brokers, Some(&args.receiver))?,
+                metrics: metrics.clone(),
+            };
+            run_command(
+                "roundtrip",
+                &options,
+                args.brokers.run_brokers(),
+                &cli.report,
+            )?;
+        }
+        Command::Scenario(args) => {
+            let scenario = Scenario::load(&args.path)?;
+            let phases = scenario.run(metrics, |report| {
+                match serde_json::to_string_pretty(report) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("Failed to serialize phase report: {}", e),
+                }
+            })?;
+            let failed = phases
+                .iter()
+                .find_map(|phase| Some((phase.name.clone(), phase.error.clone()?)));
+            let report = RunReport::new(scenario.resolved_brokers(), phases);
+            cli.report.write(&report.with_scenario(scenario))?;
+            // Only after the report is written, so it is there for the failed phase too
+            if let Some((name, error)) = failed {
+                return Err(anyhow::anyhow!("Phase {} failed: {}", name, error));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,397 @@
+use crate::latency::LatencyBuckets;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// What a sender or receiver reports to the metrics endpoint at one moment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reading {
+    /// Messages sent or received so far.
+    pub messages: u64,
+    pub errors: BTreeMap<String, u64>,
+    /// Receivers only.
+    pub latency: Option<LatencyBuckets>,
+    /// `None` for clients that don't track their connection.
+    pub reconnects: Option<u64>,
+}
+
+/// Live view of a sender or receiver that can be read from the metrics server's thread.
+pub type Probe = Arc<dyn Fn() -> Reading + Send + Sync>;
+
+struct Component {
+    labels: Vec<(&'static str, String)>,
+    probe: Probe,
+}
+
+/// Senders and receivers of the running phase, rendered in the Prometheus text format.
+///
+/// Only counters and gauges of the current state are exported, no rates. A rate since the
+/// previous scrape would be wrong as soon as more than one scraper polls the endpoint, use
+/// `rate()` in PromQL instead.
+#[derive(Default)]
+pub struct Metrics {
+    senders: Mutex<Vec<Component>>,
+    receivers: Mutex<Vec<Component>>,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets the components of the previous phase.
+    pub fn clear(&self) {
+        #[allow(clippy::expect_used)]
+        self.senders
+            .lock()
+            .expect("Failed to get metrics lock")
+            .clear();
+        #[allow(clippy::expect_used)]
+        self.receivers
+            .lock()
+            .expect("Failed to get metrics lock")
+            .clear();
+    }
+
+    pub fn register_sender(&self, kind: &str, probe: Probe) {
+        #[allow(clippy::expect_used)]
+        let mut senders = self.senders.lock().expect("Failed to get metrics lock");
+        let labels = vec![
+            ("sender", senders.len().to_string()),
+            ("kind", kind.to_string()),
+        ];
+        senders.push(Component { labels, probe });
+    }
+
+    pub fn register_receiver(&self, kind: &str, topic: &str, probe: Probe) {
+        #[allow(clippy::expect_used)]
+        let mut receivers = self.receivers.lock().expect("Failed to get metrics lock");
+        let labels = vec![
+            ("receiver", receivers.len().to_string()),
+            ("kind", kind.to_string()),
+            ("topic", topic.to_string()),
+        ];
+        receivers.push(Component { labels, probe });
+    }
+
+    pub fn render(&self) -> String {
+        #[allow(clippy::expect_used)]
+        let senders: Vec<_> = self
+            .senders
+            .lock()
+            .expect("Failed to get metrics lock")
+            .iter()
+            .map(|sender| (sender.labels.clone(), (sender.probe)()))
+            .collect();
+        #[allow(clippy::expect_used)]
+        let receivers: Vec<_> = self
+            .receivers
+            .lock()
+            .expect("Failed to get metrics lock")
+            .iter()
+            .map(|receiver| (receiver.labels.clone(), (receiver.probe)()))
+            .collect();
+        let sent: u64 = senders.iter().map(|(_, reading)| reading.messages).sum();
+
+        let mut out = String::new();
+        family(
+            &mut out,
+            "databench_sent_messages_total",
+            "counter",
+            "Messages sent, for Kafka once the brokers acknowledged them.",
+        );
+        for (labels, reading) in &senders {
+            sample(
+                &mut out,
+                "databench_sent_messages_total",
+                labels,
+                reading.messages,
+            );
+        }
+
+        family(
+            &mut out,
+            "databench_received_messages_total",
+            "counter",
+            "Messages received.",
+        );
+        for (labels, reading) in &receivers {
+            sample(
+                &mut out,
+                "databench_received_messages_total",
+                labels,
+                reading.messages,
+            );
+        }
+        family(
+            &mut out,
+            "databench_in_flight_messages",
+            "gauge",
+            "Messages sent in this phase that the receiver has not seen yet.",
+        );
+        for (labels, reading) in &receivers {
+            let in_flight = sent.saturating_sub(reading.messages);
+            sample(&mut out, "databench_in_flight_messages", labels, in_flight);
+        }
+
+        family(
+            &mut out,
+            "databench_errors_total",
+            "counter",
+            "Failed operations by category, e.g. Kafka deliveries, polls or commits.",
+        );
+        for (labels, reading) in senders.iter().chain(receivers.iter()) {
+            for (category, count) in &reading.errors {
+                let mut labels = labels.clone();
+                labels.push(("category", category.clone()));
+                sample(&mut out, "databench_errors_total", &labels, count);
+            }
+        }
+
+        family(
+            &mut out,
+            "databench_reconnects_total",
+            "counter",
+            "Times a receiver reconnected after losing its connection.",
+        );
+        for (labels, reading) in &receivers {
+            if let Some(reconnects) = reading.reconnects {
+                sample(&mut out, "databench_reconnects_total", labels, reconnects);
+            }
+        }
+
+        family(
+            &mut out,
+            "databench_latency_seconds",
+            "histogram",
+            "Time from sending to receiving a message.",
+        );
+        for (labels, reading) in &receivers {
+            if let Some(latency) = &reading.latency {
+                histogram(&mut out, "databench_latency_seconds", labels, latency);
+            }
+        }
+        out
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, String)], value: impl fmt::Display) {
+    let _ = writeln!(out, "{}{{{}}} {}", name, render_labels(labels), value);
+}
+
+fn histogram(out: &mut String, name: &str, labels: &[(&str, String)], latency: &LatencyBuckets) {
+    let bucket = format!("{}_bucket", name);
+    for (bound_us, count) in &latency.buckets {
+        let mut labels = labels.to_vec();
+        labels.push(("le", (*bound_us as f64 / 1e6).to_string()));
+        sample(out, &bucket, &labels, count);
+    }
+    let mut labels_inf = labels.to_vec();
+    labels_inf.push(("le", "+Inf".to_string()));
+    sample(out, &bucket, &labels_inf, latency.count);
+    sample(out, &format!("{}_sum", name), labels, latency.sum_us / 1e6);
+    sample(out, &format!("{}_count", name), labels, latency.count);
+}
+
+fn render_labels(labels: &[(&str, String)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Serves `GET /metrics` on a background thread until dropped.
+pub struct MetricsServer {
+    running: Arc<AtomicBool>,
+    addr: SocketAddr,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(addr: &str, metrics: Arc<Metrics>) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| anyhow::anyhow!("Failed to listen for metrics on {}: {}", addr, e))?;
+        // Non-blocking, so the thread notices when the server is dropped
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let handle = thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = serve(stream, &metrics) {
+                            eprintln!("Error serving metrics: {:?}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => eprintln!("Error accepting metrics connection: {:?}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            running,
+            addr,
+            handle: Some(handle),
+        })
+    }
+
+    /// The address actually bound, e.g. to find the port when started on port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Answers a single request and closes the connection.
+fn serve(stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, the request has no body we care about
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::latency::LatencyBuckets;
+    use crate::metrics::{Metrics, MetricsServer, Reading};
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+
+    fn scrape(addr: SocketAddr, path: &str) -> String {
+        let mut response = String::new();
+        if let Ok(mut stream) = TcpStream::connect(addr) {
+            let _ = write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            let _ = stream.read_to_string(&mut response);
+        }
+        response
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_metrics_endpoint() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.register_sender(
+            "kafka",
+            Arc::new(|| Reading {
+                messages: 100,
+                errors: BTreeMap::from([("delivery".to_string(), 2)]),
+                ..Default::default()
+            }),
+        );
+        metrics.register_receiver(
+            "mqtt",
+            "umh/v1/\"bench\"",
+            Arc::new(|| Reading {
+                messages: 90,
+                latency: Some(LatencyBuckets {
+                    buckets: vec![(1_000, 60), (10_000, 90)],
+                    sum_us: 180_000.0,
+                    count: 90,
+                }),
+                reconnects: Some(1),
+                ..Default::default()
+            }),
+        );
+        let server = MetricsServer::start("127.0.0.1:0", metrics).unwrap();
+
+        let response = scrape(server.local_addr(), "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let receiver = r#"receiver="0",kind="mqtt",topic="umh/v1/\"bench\"""#;
+        for line in [
+            r#"databench_sent_messages_total{sender="0",kind="kafka"} 100"#.to_string(),
+            r#"databench_errors_total{sender="0",kind="kafka",category="delivery"} 2"#.to_string(),
+            format!("databench_received_messages_total{{{}}} 90", receiver),
+            format!("databench_in_flight_messages{{{}}} 10", receiver),
+            format!("databench_reconnects_total{{{}}} 1", receiver),
+            format!(
+                r#"databench_latency_seconds_bucket{{{},le="0.001"}} 60"#,
+                receiver
+            ),
+            format!(
+                r#"databench_latency_seconds_bucket{{{},le="+Inf"}} 90"#,
+                receiver
+            ),
+            format!("databench_latency_seconds_sum{{{}}} 0.18", receiver),
+        ] {
+            assert!(response.lines().any(|l| l == line), "missing {}", line);
+        }
+        // Counters don't depend on who scraped before
+        let again = scrape(server.local_addr(), "/metrics");
+        assert_eq!(
+            again
+                .lines()
+                .skip_while(|l| !l.is_empty())
+                .collect::<Vec<_>>(),
+            response
+                .lines()
+                .skip_while(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+        );
+
+        assert!(scrape(server.local_addr(), "/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::secret::Secret;
+use rumqttc::{Key, Transport};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// TLS and authentication settings of an MQTT broker, shared by MQTT3 and MQTT5 clients.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttSecurity {
+    /// Connect with TLS, implied by any of the certificate settings.
+    #[serde(default)]
+    pub tls: bool,
+    /// PEM CA certificate to verify the broker with, the system store is used if not set.
+    #[serde(default)]
+    pub ca_location: Option<PathBuf>,
+    /// PEM client certificate and key for client certificate authentication.
+    #[serde(default)]
+    pub certificate_location: Option<PathBuf>,
+    #[serde(default)]
+    pub key_location: Option<PathBuf>,
+    #[serde(default)]
+    pub username: Option<Secret>,
+    #[serde(default)]
+    pub password: Option<Secret>,
+}
+
+impl MqttSecurity {
+    pub fn uses_tls(&self) -> bool {
+        self.tls
+            || self.ca_location.is_some()
+            || self.certificate_location.is_some()
+            || self.key_location.is_some()
+    }
+
+    /// The TLS transport to connect with, `None` for plain TCP.
+    pub fn transport(&self) -> anyhow::Result<Option<Transport>> {
+        if !self.uses_tls() {
+            return Ok(None);
+        }
+        let client_auth = match (&self.certificate_location, &self.key_location) {
+            (Some(certificate), Some(key)) => Some((read(certificate)?, read_key(key)?)),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "MQTT client certificate authentication needs both a certificate and a key"
+                ))
+            }
+        };
+        match (&self.ca_location, client_auth) {
+            (Some(ca), client_auth) => Ok(Some(Transport::tls(read(ca)?, client_auth, None))),
+            (None, None) => Ok(Some(Transport::tls_with_default_config())),
+            (None, Some(_)) => Err(anyhow::anyhow!(
+                "MQTT client certificate authentication needs a CA certificate"
+            )),
+        }
+    }
+
+    /// Resolved username and password, `None` for anonymous clients.
+    pub fn credentials(&self) -> anyhow::Result<Option<(String, String)>> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => {
+                Ok(Some((username.resolve()?, password.resolve()?)))
+            }
+            (Some(username), None) => Ok(Some((username.resolve()?, String::new()))),
+            (None, Some(_)) => Err(anyhow::anyhow!("MQTT password given without a username")),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub fn apply(&self, options: &mut rumqttc::MqttOptions) -> anyhow::Result<()> {
+        if let Some(transport) = self.transport()? {
+            options.set_transport(transport);
+        }
+        if let Some((username, password)) = self.credentials()? {
+            options.set_credentials(username, password);
+        }
+        Ok(())
+    }
+
+    pub fn apply_v5(&self, options: &mut rumqttc::v5::MqttOptions) -> anyhow::Result<()> {
+        if let Some(transport) = self.transport()? {
+            options.set_transport(transport);
+        }
+        if let Some((username, password)) = self.credentials()? {
+            options.set_credentials(username, password);
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Reads a PEM private key, PKCS#1 keys are passed as RSA and PKCS#8 keys as ECC (which
+/// rumqttc parses as PKCS#8 regardless of the algorithm).
+fn read_key(path: &Path) -> anyhow::Result<Key> {
+    let key = read(path)?;
+    if key
+        .windows(b"BEGIN RSA PRIVATE KEY".len())
+        .any(|window| window == b"BEGIN RSA PRIVATE KEY")
+    {
+        Ok(Key::RSA(key))
+    } else {
+        Ok(Key::ECC(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mqtt_config::MqttSecurity;
+    use rumqttc::Transport;
+    use std::path::PathBuf;
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_mqtt_security() {
+        assert!(MqttSecurity::default().transport().unwrap().is_none());
+        assert!(MqttSecurity::default().credentials().unwrap().is_none());
+
+        let tls = MqttSecurity {
+            tls: true,
+            ..Default::default()
+        };
+        assert!(matches!(tls.transport().unwrap(), Some(Transport::Tls(_))));
+
+        let missing_ca = MqttSecurity {
+            certificate_location: Some(PathBuf::from("client.crt")),
+            ..Default::default()
+        };
+        assert!(missing_ca.uses_tls());
+        assert!(missing_ca.transport().is_err());
+
+        std::env::set_var("DATABENCH_TEST_MQTT_USER", "bench");
+        let user_only = MqttSecurity {
+            username: Some("env:DATABENCH_TEST_MQTT_USER".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            user_only.credentials().unwrap(),
+            Some(("bench".to_string(), String::new()))
+        );
+        let password_only = MqttSecurity {
+            password: Some("env:DATABENCH_TEST_MQTT_USER".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(password_only.credentials().is_err());
+    }
+}
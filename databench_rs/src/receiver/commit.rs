@@ -0,0 +1,120 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// When a Kafka consumer commits its offsets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum CommitStrategy {
+    /// librdkafka's periodic auto commit.
+    #[default]
+    Auto,
+    /// Synchronous commit after every `every` messages.
+    Sync { every: u64 },
+    /// Asynchronous commit every `interval_ms`.
+    Async { interval_ms: u64 },
+    /// Synchronous commit right after the received hashes were handed to the verifier, so
+    /// only messages that are accounted for are committed.
+    AfterVerify,
+}
+
+/// Name of a commit strategy on the command line, the parameters are separate arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CommitMode {
+    Auto,
+    Sync,
+    Async,
+    AfterVerify,
+}
+
+impl CommitStrategy {
+    pub fn from_mode(mode: CommitMode, every: u64, interval_ms: u64) -> Self {
+        match mode {
+            CommitMode::Auto => CommitStrategy::Auto,
+            CommitMode::Sync => CommitStrategy::Sync {
+                every: every.max(1),
+            },
+            CommitMode::Async => CommitStrategy::Async { interval_ms },
+            CommitMode::AfterVerify => CommitStrategy::AfterVerify,
+        }
+    }
+
+    pub fn auto_commit(&self) -> bool {
+        matches!(self, CommitStrategy::Auto)
+    }
+}
+
+/// Commits, restarts and redeliveries of a Kafka receiver.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CommitReport {
+    pub strategy: CommitStrategy,
+    /// Successful offset commits, as confirmed by the broker.
+    pub commits: u64,
+    pub commit_errors: u64,
+    /// Consumers that were killed and restarted mid-run.
+    pub restarts: u64,
+    /// Messages delivered again at or below an offset that was already received.
+    pub redelivered: u64,
+    pub redelivered_by_partition: BTreeMap<String, u64>,
+}
+
+/// Highest offset received per partition, over all consumers of a receiver.
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    highest: HashMap<String, i64>,
+    redelivered: BTreeMap<String, u64>,
+}
+
+impl OffsetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a received offset and returns whether it was received before.
+    pub fn record(&mut self, partition: &str, offset: i64) -> bool {
+        match self.highest.get_mut(partition) {
+            Some(highest) if offset <= *highest => {
+                *self.redelivered.entry(partition.to_string()).or_default() += 1;
+                true
+            }
+            Some(highest) => {
+                *highest = offset;
+                false
+            }
+            None => {
+                self.highest.insert(partition.to_string(), offset);
+                false
+            }
+        }
+    }
+
+    pub fn redelivered(&self) -> &BTreeMap<String, u64> {
+        &self.redelivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::receiver::commit::{CommitMode, CommitStrategy, OffsetTracker};
+
+    #[test]
+    fn test_offset_tracker() {
+        let mut tracker = OffsetTracker::new();
+        for offset in 0..10 {
+            assert!(!tracker.record("t/0", offset));
+        }
+        assert!(!tracker.record("t/1", 3));
+        // Restarted from the committed offset 5
+        for offset in 5..12 {
+            tracker.record("t/0", offset);
+        }
+        assert_eq!(tracker.redelivered()["t/0"], 5);
+        assert!(!tracker.redelivered().contains_key("t/1"));
+
+        assert_eq!(
+            CommitStrategy::from_mode(CommitMode::Sync, 0, 1000),
+            CommitStrategy::Sync { every: 1 }
+        );
+        assert!(CommitStrategy::default().auto_commit());
+    }
+}
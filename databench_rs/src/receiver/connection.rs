@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionEventKind {
+    Disconnected,
+    Reconnected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionEvent {
+    pub kind: ConnectionEventKind,
+    /// Milliseconds since the unix epoch.
+    pub at_ms: u64,
+    /// Error that caused a disconnect.
+    pub reason: Option<String>,
+}
+
+/// Disconnects and reconnects of a receiver, for failover tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionSummary {
+    pub disconnects: u64,
+    pub reconnects: u64,
+    /// Sum of the time between each disconnect and the following reconnect.
+    pub downtime_ms: u64,
+    pub longest_outage_ms: u64,
+    /// True if the last disconnect was never followed by a reconnect.
+    pub still_disconnected: bool,
+    pub events: Vec<ConnectionEvent>,
+}
+
+/// Records connection state changes as seen by a single client.
+///
+/// Repeated errors while already disconnected (e.g. failed reconnect attempts) are not
+/// recorded again, so every outage is exactly one disconnect and at most one reconnect.
+#[derive(Debug, Default)]
+pub struct ConnectionLog {
+    events: Vec<ConnectionEvent>,
+    connected: bool,
+    ever_connected: bool,
+}
+
+impl ConnectionLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a successful connect and returns whether it was a reconnect.
+    pub fn connected(&mut self, at: SystemTime) -> bool {
+        let reconnect = self.ever_connected && !self.connected;
+        if reconnect {
+            self.events.push(ConnectionEvent {
+                kind: ConnectionEventKind::Reconnected,
+                at_ms: unix_ms(at),
+                reason: None,
+            });
+        }
+        self.connected = true;
+        self.ever_connected = true;
+        reconnect
+    }
+
+    pub fn disconnected(&mut self, at: SystemTime, reason: String) {
+        if !self.connected {
+            return;
+        }
+        self.connected = false;
+        self.events.push(ConnectionEvent {
+            kind: ConnectionEventKind::Disconnected,
+            at_ms: unix_ms(at),
+            reason: Some(reason),
+        });
+    }
+
+    pub fn events(&self) -> &[ConnectionEvent] {
+        &self.events
+    }
+}
+
+pub(crate) fn unix_ms(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Pairs every disconnect with the reconnect that followed it.
+pub fn summarize(events: &[ConnectionEvent]) -> ConnectionSummary {
+    let mut summary = ConnectionSummary {
+        events: events.to_vec(),
+        ..Default::default()
+    };
+    let mut down_since = None;
+    for event in events {
+        match event.kind {
+            ConnectionEventKind::Disconnected => {
+                summary.disconnects += 1;
+                down_since = Some(event.at_ms);
+            }
+            ConnectionEventKind::Reconnected => {
+                summary.reconnects += 1;
+                if let Some(since) = down_since.take() {
+                    let outage = event.at_ms.saturating_sub(since);
+                    summary.downtime_ms += outage;
+                    summary.longest_outage_ms = summary.longest_outage_ms.max(outage);
+                }
+            }
+        }
+    }
+    summary.still_disconnected = down_since.is_some();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::receiver::connection::{summarize, ConnectionEventKind, ConnectionLog};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_connection_log() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let mut log = ConnectionLog::new();
+        assert!(!log.connected(at(0)));
+        log.disconnected(at(1_000), "connection reset".to_string());
+        // Failed reconnect attempts don't count as further disconnects
+        log.disconnected(at(2_000), "connection refused".to_string());
+        assert!(log.connected(at(4_000)));
+        log.disconnected(at(10_000), "connection reset".to_string());
+        assert!(log.connected(at(10_500)));
+        log.disconnected(at(20_000), "connection reset".to_string());
+
+        let summary = summarize(log.events());
+        assert_eq!(summary.disconnects, 3);
+        assert_eq!(summary.reconnects, 2);
+        assert_eq!(summary.downtime_ms, 3_500);
+        assert_eq!(summary.longest_outage_ms, 3_000);
+        assert!(summary.still_disconnected);
+        assert_eq!(summary.events[1].kind, ConnectionEventKind::Reconnected);
+        assert_eq!(
+            summary.events[0].reason.as_deref(),
+            Some("connection reset")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceEventKind {
+    Assigned,
+    Revoked,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RebalanceEvent {
+    /// Index of the consumer within the receiver.
+    pub consumer: usize,
+    pub kind: RebalanceEventKind,
+    /// Milliseconds since the unix epoch.
+    pub at_ms: u64,
+    /// Partitions as `<topic>/<partition>`.
+    pub partitions: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Members of a Kafka consumer group and how partitions moved between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConsumerGroupSummary {
+    pub group_id: String,
+    pub consumers: usize,
+    pub assignments: u64,
+    pub revocations: u64,
+    pub errors: u64,
+    /// Partitions held by each consumer at the end of the run.
+    pub assignment: BTreeMap<usize, Vec<String>>,
+    pub events: Vec<RebalanceEvent>,
+}
+
+/// Replays the events in order, so both eager and incremental (cooperative) assignments end
+/// up as the partitions each consumer held last.
+pub fn summarize(
+    group_id: &str,
+    consumers: usize,
+    events: &[RebalanceEvent],
+) -> ConsumerGroupSummary {
+    let mut summary = ConsumerGroupSummary {
+        group_id: group_id.to_string(),
+        consumers,
+        events: events.to_vec(),
+        ..Default::default()
+    };
+    let mut held: BTreeMap<usize, BTreeSet<&str>> = (0..consumers)
+        .map(|consumer| (consumer, BTreeSet::new()))
+        .collect();
+    for event in events {
+        let partitions = held.entry(event.consumer).or_default();
+        match event.kind {
+            RebalanceEventKind::Assigned => {
+                summary.assignments += 1;
+                partitions.extend(event.partitions.iter().map(String::as_str));
+            }
+            RebalanceEventKind::Revoked => {
+                summary.revocations += 1;
+                for partition in &event.partitions {
+                    partitions.remove(partition.as_str());
+                }
+            }
+            RebalanceEventKind::Error => summary.errors += 1,
+        }
+    }
+    summary.assignment = held
+        .into_iter()
+        .map(|(consumer, partitions)| {
+            (
+                consumer,
+                partitions.into_iter().map(str::to_string).collect(),
+            )
+        })
+        .collect();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::receiver::group::{summarize, RebalanceEvent, RebalanceEventKind};
+
+    #[test]
+    fn test_summarize_rebalances() {
+        let event = |consumer, kind, partitions: &[&str]| RebalanceEvent {
+            consumer,
+            kind,
+            at_ms: 0,
+            partitions: partitions.iter().map(|p| p.to_string()).collect(),
+            error: None,
+        };
+        let events = vec![
+            event(0, RebalanceEventKind::Assigned, &["t/0", "t/1", "t/2"]),
+            // Consumer 1 joins, consumer 0 hands over a partition
+            event(0, RebalanceEventKind::Revoked, &["t/0", "t/1", "t/2"]),
+            event(0, RebalanceEventKind::Assigned, &["t/0", "t/1"]),
+            event(1, RebalanceEventKind::Assigned, &["t/2"]),
+        ];
+
+        let summary = summarize("databench-1", 3, &events);
+        assert_eq!(summary.assignments, 3);
+        assert_eq!(summary.revocations, 1);
+        assert_eq!(summary.assignment[&0], vec!["t/0", "t/1"]);
+        assert_eq!(summary.assignment[&1], vec!["t/2"]);
+        assert!(summary.assignment[&2].is_empty());
+    }
+}
@@ -1,19 +1,252 @@
+use crate::errors::ErrorCounter;
+use crate::generator::sequence::{extract_sequence, sequence_from_headers, SequenceStamp};
+use crate::generator::SEND_TIMESTAMP_HEADER;
+use crate::hashes::{hash_message, HashLedger, MessageHash, Side};
+use crate::kafka_config::{client_config, redact, KafkaClientSettings};
+use crate::latency::{LatencyRecorder, LatencyReport, MERGE_INTERVAL};
+use crate::metrics::{Probe, Reading};
+use crate::receiver::commit::{CommitReport, CommitStrategy, OffsetTracker};
+use crate::receiver::connection::unix_ms;
+use crate::receiver::group::{self, ConsumerGroupSummary, RebalanceEvent, RebalanceEventKind};
+use crate::receiver::sequence::{SequenceReport, SequenceTracker};
 use crate::receiver::Receiver;
-use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance};
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::message::{BorrowedHeaders, Headers};
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::types::RDKafkaErrorCode;
 use rdkafka::util::Timeout;
-use rdkafka::{ClientConfig, Message};
-use std::collections::VecDeque;
+use rdkafka::{ClientContext, Message};
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::sleep;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
 
 pub(crate) struct KafkaReceiver {
     receiving: Arc<AtomicBool>,
     brokers: Vec<String>,
     topic: String,
-    hashes: Arc<RwLock<VecDeque<String>>>,
+    hashes: Arc<HashLedger>,
     received_message_cnt: Arc<AtomicU64>,
+    latency: Arc<RwLock<LatencyRecorder>>,
+    latency_per_topic: bool,
+    sequences: Arc<Mutex<SequenceTracker>>,
+    ids: Arc<Mutex<Vec<(String, MessageHash)>>>,
+    /// Only batch verification matches ids, streaming verification leaves them out.
+    collect_ids: bool,
+    group_id: String,
+    consumers: usize,
+    rebalances: Arc<Mutex<Vec<RebalanceEvent>>>,
+    settings: KafkaClientSettings,
+    commit: CommitStrategy,
+    restart_after: Option<Duration>,
+    offsets: Arc<Mutex<OffsetTracker>>,
+    counters: Arc<CommitCounters>,
+    errors: Arc<ErrorCounter>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct CommitCounters {
+    commits: AtomicU64,
+    commit_errors: AtomicU64,
+    restarts: AtomicU64,
+}
+
+impl KafkaReceiver {
+    /// Consumer group to join, unique per run if not set so no committed offsets are reused.
+    pub fn with_group_id(mut self, group_id: Option<String>) -> Self {
+        if let Some(group_id) = group_id {
+            self.group_id = group_id;
+        }
+        self
+    }
+
+    /// Preset and librdkafka overrides applied on top of the defaults.
+    pub fn with_client_settings(mut self, settings: KafkaClientSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// The librdkafka settings every consumer is created with, including resolved secrets.
+    pub fn client_config(&self) -> anyhow::Result<BTreeMap<String, String>> {
+        self.settings.consumer_config(&[
+            ("bootstrap.servers", &self.brokers.join(",")),
+            ("group.id", &self.group_id),
+            ("enable.auto.commit", &self.commit.auto_commit().to_string()),
+            ("auto.offset.reset", "earliest"),
+            // Never hand out messages of aborted or still open transactions
+            ("isolation.level", "read_committed"),
+        ])
+    }
+
+    /// Number of consumers in the group, each polling on its own thread.
+    pub fn with_consumers(mut self, consumers: usize) -> Self {
+        self.consumers = consumers.max(1);
+        self
+    }
+
+    pub fn with_commit_strategy(mut self, commit: CommitStrategy) -> Self {
+        self.commit = commit;
+        self
+    }
+
+    /// Kills every consumer without a final commit and starts a new one in its place, which
+    /// resumes from the last committed offsets. The first consumer goes `restart_after` into
+    /// the run, the others one after another over the following `restart_after`. Needs a commit
+    /// strategy other than auto commit, which librdkafka runs when a consumer is closed.
+    pub fn with_restart_after(mut self, restart_after: Option<Duration>) -> Self {
+        self.restart_after = restart_after;
+        self
+    }
+
+    /// Also breaks latencies down per `<topic>.<key>`, at the cost of one histogram per
+    /// stream and thread.
+    pub fn with_latency_per_topic(mut self, per_topic: bool) -> Self {
+        self.latency_per_topic = per_topic;
+        self
+    }
+}
+
+/// Creates a consumer and subscribes it to `topic`.
+fn new_consumer(
+    config: &BTreeMap<String, String>,
+    context: GroupContext,
+    topic: &str,
+) -> KafkaResult<BaseConsumer<GroupContext>> {
+    let consumer: BaseConsumer<GroupContext> =
+        client_config(config).create_with_context(context)?;
+    consumer.subscribe(&[topic])?;
+    Ok(consumer)
+}
+
+/// Commits the offsets of everything polled so far, having nothing to commit is fine.
+fn commit(consumer: &BaseConsumer<GroupContext>, mode: CommitMode) {
+    match consumer.commit_consumer_state(mode) {
+        Ok(()) | Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => {}
+        Err(e) => eprintln!("Error committing offsets: {}", e),
+    }
+}
+
+/// Records the rebalances and commits of one consumer, called from its poll.
+#[derive(Clone)]
+struct GroupContext {
+    consumer: usize,
+    events: Arc<Mutex<Vec<RebalanceEvent>>>,
+    counters: Arc<CommitCounters>,
+    /// Set when partitions were taken away, so the consumer hands over what it received
+    /// before another one picks them up.
+    revoked: Arc<AtomicBool>,
+}
+
+impl ClientContext for GroupContext {}
+
+impl ConsumerContext for GroupContext {
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        let (kind, partitions, error) = match rebalance {
+            Rebalance::Assign(list) => (RebalanceEventKind::Assigned, partition_names(list), None),
+            Rebalance::Revoke(list) => {
+                self.revoked.store(true, Ordering::Relaxed);
+                (RebalanceEventKind::Revoked, partition_names(list), None)
+            }
+            Rebalance::Error(error) => (RebalanceEventKind::Error, vec![], Some(error.to_string())),
+        };
+        println!(
+            "Consumer {} {:?} {}",
+            self.consumer,
+            kind,
+            error.as_deref().unwrap_or(&partitions.join(","))
+        );
+        #[allow(clippy::expect_used)]
+        self.events
+            .lock()
+            .expect("Failed to get rebalance lock")
+            .push(RebalanceEvent {
+                consumer: self.consumer,
+                kind,
+                at_ms: unix_ms(SystemTime::now()),
+                partitions,
+                error,
+            });
+    }
+
+    fn commit_callback(&self, result: KafkaResult<()>, _offsets: &TopicPartitionList) {
+        match result {
+            Ok(()) => {
+                self.counters.commits.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(KafkaError::ConsumerCommit(RDKafkaErrorCode::NoOffset)) => {}
+            Err(e) => {
+                eprintln!("Consumer {} failed to commit: {}", self.consumer, e);
+                self.counters.commit_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Offsets and sequence stamps a consumer received since it last handed them to the shared
+/// trackers. Both trackers depend on the order messages arrive in, so they are replayed rather
+/// than merged.
+#[derive(Default)]
+struct PendingReceipts {
+    received: Vec<(String, String, i64, Option<SequenceStamp>)>,
+}
+
+impl PendingReceipts {
+    fn push(
+        &mut self,
+        partition: String,
+        topic: String,
+        offset: i64,
+        stamp: Option<SequenceStamp>,
+    ) {
+        self.received.push((partition, topic, offset, stamp));
+    }
+
+    /// Records everything received so far, taking each lock once.
+    fn hand_over(&mut self, offsets: &Mutex<OffsetTracker>, sequences: &Mutex<SequenceTracker>) {
+        if self.received.is_empty() {
+            return;
+        }
+        #[allow(clippy::expect_used)]
+        let mut offsets = offsets.lock().expect("Failed to get offset lock");
+        #[allow(clippy::expect_used)]
+        let mut sequences = sequences.lock().expect("Failed to get sequence lock");
+        for (partition, topic, offset, stamp) in self.received.drain(..) {
+            offsets.record(&partition, offset);
+            sequences.record(&partition, &topic, stamp);
+        }
+    }
+}
+
+fn receiver_errors(errors: &ErrorCounter, counters: &CommitCounters) -> BTreeMap<String, u64> {
+    let mut errors = errors.counts();
+    let commit_errors = counters.commit_errors.load(Ordering::Relaxed);
+    if commit_errors > 0 {
+        errors.insert("commit".to_string(), commit_errors);
+    }
+    errors
+}
+
+fn partition_names(list: &TopicPartitionList) -> Vec<String> {
+    list.elements()
+        .iter()
+        .map(|element| format!("{}/{}", element.topic(), element.partition()))
+        .collect()
+}
+
+/// Key and value of every header that has a value.
+fn header_pairs(headers: Option<&BorrowedHeaders>) -> Vec<(&str, &[u8])> {
+    headers
+        .map(|headers| {
+            headers
+                .iter()
+                .filter_map(|header| header.value.map(|value| (header.key, value)))
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 impl Receiver for KafkaReceiver {
@@ -25,8 +258,23 @@ impl Receiver for KafkaReceiver {
             brokers,
             receiving: Arc::new(AtomicBool::new(false)),
             topic,
-            hashes: Arc::new(RwLock::new(VecDeque::new())),
+            hashes: Arc::new(HashLedger::new()),
             received_message_cnt: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(RwLock::new(LatencyRecorder::new())),
+            latency_per_topic: false,
+            sequences: Arc::new(Mutex::new(SequenceTracker::new())),
+            ids: Arc::new(Mutex::new(Vec::new())),
+            collect_ids: true,
+            group_id: format!("databench-{:08x}", rand::random::<u32>()),
+            consumers: 1,
+            rebalances: Arc::new(Mutex::new(Vec::new())),
+            settings: KafkaClientSettings::default(),
+            commit: CommitStrategy::default(),
+            restart_after: None,
+            offsets: Arc::new(Mutex::new(OffsetTracker::new())),
+            counters: Arc::new(CommitCounters::default()),
+            errors: Arc::new(ErrorCounter::new()),
+            handles: Vec::new(),
         })
     }
 
@@ -36,97 +284,300 @@ impl Receiver for KafkaReceiver {
             return Ok(());
         }
 
-        let receiving = self.receiving.clone();
-
-        // Init kafka consumer
-        let consumer: BaseConsumer = ClientConfig::new()
-            .set("bootstrap.servers", self.brokers.join(","))
-            .set("group.id", "databench")
-            .set("enable.auto.commit", "true")
-            .set("auto.offset.reset", "earliest")
-            .create()?;
+        if self.commit.auto_commit() && self.restart_after.is_some() {
+            return Err(anyhow::anyhow!(
+                "Restarting consumers needs a commit strategy other than auto"
+            ));
+        }
+        let config = self.client_config()?;
+        let started = Instant::now();
+        for consumer_id in 0..self.consumers {
+            let restart_at = self
+                .restart_after
+                .map(|after| started + restart_delay(after, consumer_id, self.consumers));
+            // Init kafka consumer
+            let context = GroupContext {
+                consumer: consumer_id,
+                events: self.rebalances.clone(),
+                counters: self.counters.clone(),
+                revoked: Arc::new(AtomicBool::new(false)),
+            };
+            let consumer = new_consumer(&config, context.clone(), &self.topic)?;
 
-        let hashes = self.hashes.clone();
-        let received_messages_counter = self.received_message_cnt.clone();
+            let receiving = self.receiving.clone();
+            let hashes = self.hashes.clone();
+            let received_messages_counter = self.received_message_cnt.clone();
+            let latency = self.latency.clone();
+            let latency_per_topic = self.latency_per_topic;
+            let sequences = self.sequences.clone();
+            let ids = self.ids.clone();
+            let collect_ids = self.collect_ids;
+            let topic = self.topic.clone();
+            let offsets = self.offsets.clone();
+            let counters = self.counters.clone();
+            let errors = self.errors.clone();
+            let strategy = self.commit;
+            let config = config.clone();
 
-        let topic = self.topic.clone();
-        thread::spawn(move || {
-            let topics = vec![topic.as_str()];
-            let mut hasher = blake3::Hasher::new();
-            let mut thread_hashes = VecDeque::new();
+            self.handles.push(thread::spawn(move || {
+                let mut restart_at = restart_at;
+                let mut last_commit = Instant::now();
+                let mut since_commit = 0;
+                let mut hasher = blake3::Hasher::new();
+                let mut thread_hashes = Vec::new();
+                let mut thread_latency = LatencyRecorder::new().with_per_topic(latency_per_topic);
+                let mut last_merge = Instant::now();
+                let mut thread_ids = Vec::new();
+                let mut thread_received = 0;
+                let mut pending = PendingReceipts::default();
+                let revoked = context.revoked.clone();
+                let mut consumer = Some(consumer);
 
-            #[allow(clippy::expect_used)]
-            consumer
-                .subscribe(&topics)
-                .expect("Can't subscribe to specified topics");
-            while receiving.load(Ordering::Relaxed) {
-                match consumer.poll(Timeout::After(std::time::Duration::from_millis(1000))) {
-                    None => {
-                        sleep(std::time::Duration::from_millis(10));
-                        continue;
+                while receiving.load(Ordering::Relaxed) {
+                    if restart_at.is_some_and(|at| Instant::now() >= at) {
+                        restart_at = None;
+                        println!("Killing consumer {} without committing", consumer_id);
+                        // Gone before its replacement starts, like a crashed process. It leaves
+                        // the group when dropped, without auto commit there is nothing to commit.
+                        consumer = None;
+                        match new_consumer(&config, context.clone(), &topic) {
+                            Ok(restarted) => consumer = Some(restarted),
+                            Err(e) => {
+                                eprintln!("Failed to restart consumer {}: {}", consumer_id, e);
+                                errors.add("restart");
+                                break;
+                            }
+                        };
+                        counters.restarts.fetch_add(1, Ordering::Relaxed);
+                        since_commit = 0;
                     }
-                    Some(kafkaresult) => match kafkaresult {
-                        Ok(message) => {
-                            hasher.reset();
-                            let key = message.key().unwrap_or_else(|| "".as_bytes());
-                            let key_string = String::from_utf8_lossy(key).to_string();
-                            let topic = format!("{}.{}", message.topic(), key_string);
-
-                            hasher.update(topic.as_bytes());
-                            hasher.update(message.payload().unwrap_or_else(|| "".as_bytes()));
+                    let Some(consumer) = &consumer else {
+                        break;
+                    };
+                    if revoked.swap(false, Ordering::Relaxed) {
+                        pending.hand_over(&offsets, &sequences);
+                    }
+                    if last_merge.elapsed() >= MERGE_INTERVAL {
+                        last_merge = Instant::now();
+                        #[allow(clippy::expect_used)]
+                        latency
+                            .write()
+                            .expect("Failed to get write lock")
+                            .merge(&mut thread_latency);
+                    }
+                    if let CommitStrategy::Async { interval_ms } = strategy {
+                        if last_commit.elapsed() >= Duration::from_millis(interval_ms) {
+                            commit(consumer, CommitMode::Async);
+                            last_commit = Instant::now();
+                        }
+                    }
+                    match consumer.poll(Timeout::After(std::time::Duration::from_millis(1000))) {
+                        None => {
+                            sleep(std::time::Duration::from_millis(10));
+                            continue;
+                        }
+                        Some(kafkaresult) => match kafkaresult {
+                            Ok(message) => {
+                                let received_at = SystemTime::now();
+                                let key = message.key().unwrap_or_else(|| "".as_bytes());
+                                let key_string = String::from_utf8_lossy(key).to_string();
+                                let topic = format!("{}.{}", message.topic(), key_string);
+                                let payload = message.payload().unwrap_or_else(|| "".as_bytes());
+                                let headers = header_pairs(message.headers());
 
-                            let raw_hash = hasher.finalize();
-                            let hash = hex::encode(raw_hash.as_bytes());
+                                // Metadata in headers takes precedence over the payload
+                                match headers
+                                    .iter()
+                                    .find(|(key, _)| *key == SEND_TIMESTAMP_HEADER)
+                                    .and_then(|(_, value)| std::str::from_utf8(value).ok())
+                                    .and_then(|value| value.parse::<u128>().ok())
+                                {
+                                    Some(sent_ns) => {
+                                        thread_latency.record_sent_ns(&topic, sent_ns, received_at)
+                                    }
+                                    None => thread_latency.record(&topic, payload, received_at),
+                                }
+                                let stamp = sequence_from_headers(headers)
+                                    .or_else(|| extract_sequence(payload));
+                                let hash = hash_message(&mut hasher, &topic, payload);
+                                if let Some(stamp) = stamp.as_ref().filter(|_| collect_ids) {
+                                    thread_ids.push((stamp.id(&topic), hash));
+                                }
+                                let partition =
+                                    format!("{}/{}", message.topic(), message.partition());
+                                pending.push(partition, topic, message.offset(), stamp);
+                                thread_hashes.push(hash);
+                                received_messages_counter.fetch_add(1, Ordering::Relaxed);
+                                thread_received += 1;
 
-                            thread_hashes.push_back(hash);
-                            received_messages_counter.fetch_add(1, Ordering::Relaxed);
-                        }
-                        Err(error) => {
-                            eprintln!("Error while receiving from kafka: {}", error)
+                                if let CommitStrategy::Sync { every } = strategy {
+                                    since_commit += 1;
+                                    if since_commit >= every {
+                                        commit(consumer, CommitMode::Sync);
+                                        since_commit = 0;
+                                    }
+                                }
+                            }
+                            Err(error) => {
+                                eprintln!("Error while receiving from kafka: {}", error);
+                                errors.add("poll");
+                            }
+                        },
+                    };
+                    if thread_received >= 10000 {
+                        thread_received = 0;
+                        pending.hand_over(&offsets, &sequences);
+                        println!(
+                            "Received {} messages",
+                            received_messages_counter.load(Ordering::Relaxed)
+                        );
+                        hashes.record(&mut thread_hashes, Side::Received);
+                        #[allow(clippy::expect_used)]
+                        ids.lock()
+                            .expect("Failed to get id lock")
+                            .append(&mut thread_ids);
+                        if strategy == CommitStrategy::AfterVerify {
+                            commit(consumer, CommitMode::Sync);
                         }
-                    },
-                };
-                if received_messages_counter.load(Ordering::Relaxed) % 10000 == 0 {
-                    println!(
-                        "Received {} messages",
-                        received_messages_counter.load(Ordering::Relaxed)
-                    );
-                    let hashlock = hashes.write();
-                    #[allow(clippy::expect_used)]
-                    let mut hashes = hashlock.expect("Failed to get write lock");
-                    for hash in thread_hashes.drain(..) {
-                        hashes.push_back(hash);
                     }
-                    thread_hashes.clear();
                 }
-            }
 
-            println!(
-                "Received {} messages",
-                received_messages_counter.load(Ordering::Relaxed)
-            );
-            let hashlock = hashes.write();
-            #[allow(clippy::expect_used)]
-            let mut hashes = hashlock.expect("Failed to get write lock");
-            for hash in thread_hashes.drain(..) {
-                hashes.push_back(hash);
-            }
-            thread_hashes.clear();
-        });
+                println!(
+                    "Received {} messages",
+                    received_messages_counter.load(Ordering::Relaxed)
+                );
+                pending.hand_over(&offsets, &sequences);
+                hashes.record(&mut thread_hashes, Side::Received);
+                #[allow(clippy::expect_used)]
+                ids.lock()
+                    .expect("Failed to get id lock")
+                    .append(&mut thread_ids);
+
+                #[allow(clippy::expect_used)]
+                latency
+                    .write()
+                    .expect("Failed to get write lock")
+                    .merge(&mut thread_latency);
+                // Shut down cleanly, auto commit commits when the consumer is closed
+                if let Some(consumer) = consumer.filter(|_| !strategy.auto_commit()) {
+                    commit(&consumer, CommitMode::Sync);
+                }
+            }));
+        }
 
         Ok(())
     }
 
     fn end(&mut self) {
-        self.receiving.swap(false, Ordering::Relaxed);
+        self.receiving.store(false, Ordering::Relaxed);
+        // Each thread hands over its last hashes, latencies and stamps before it returns
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
     }
 
-    fn get_received_messages_hashes(&self) -> VecDeque<String> {
-        #[allow(clippy::expect_used)]
-        self.hashes.read().expect("Failed to get read lock").clone()
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>) {
+        self.hashes = ledger;
+        self.collect_ids = false;
+    }
+
+    fn get_received_messages_hashes(&self) -> Arc<HashLedger> {
+        self.hashes.clone()
     }
 
     fn get_received_messages(&self) -> u64 {
         self.received_message_cnt.load(Ordering::Relaxed)
     }
+
+    fn get_received_counter(&self) -> Arc<AtomicU64> {
+        self.received_message_cnt.clone()
+    }
+
+    fn get_probe(&self) -> Probe {
+        let received = self.received_message_cnt.clone();
+        let errors = self.errors.clone();
+        let counters = self.counters.clone();
+        let latency = self.latency.clone();
+        Arc::new(move || Reading {
+            messages: received.load(Ordering::Relaxed),
+            errors: receiver_errors(&errors, &counters),
+            #[allow(clippy::expect_used)]
+            latency: Some(latency.read().expect("Failed to get read lock").buckets()),
+            reconnects: None,
+        })
+    }
+
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        receiver_errors(&self.errors, &self.counters)
+    }
+
+    fn get_client_config(&self) -> Option<BTreeMap<String, String>> {
+        self.client_config().ok().map(|config| redact(&config))
+    }
+
+    fn get_consumer_group(&self) -> Option<ConsumerGroupSummary> {
+        #[allow(clippy::expect_used)]
+        let events = self
+            .rebalances
+            .lock()
+            .expect("Failed to get rebalance lock");
+        Some(group::summarize(&self.group_id, self.consumers, &events))
+    }
+
+    fn get_commit_report(&self) -> Option<CommitReport> {
+        #[allow(clippy::expect_used)]
+        let offsets = self.offsets.lock().expect("Failed to get offset lock");
+        Some(CommitReport {
+            strategy: self.commit,
+            commits: self.counters.commits.load(Ordering::Relaxed),
+            commit_errors: self.counters.commit_errors.load(Ordering::Relaxed),
+            restarts: self.counters.restarts.load(Ordering::Relaxed),
+            redelivered: offsets.redelivered().values().sum(),
+            redelivered_by_partition: offsets.redelivered().clone(),
+        })
+    }
+
+    fn get_received_message_ids(&self) -> Vec<(String, MessageHash)> {
+        #[allow(clippy::expect_used)]
+        self.ids.lock().expect("Failed to get id lock").clone()
+    }
+
+    fn get_sequence_report(&self) -> SequenceReport {
+        #[allow(clippy::expect_used)]
+        self.sequences
+            .lock()
+            .expect("Failed to get sequence lock")
+            .report()
+    }
+
+    fn get_latency(&self) -> LatencyReport {
+        #[allow(clippy::expect_used)]
+        self.latency
+            .read()
+            .expect("Failed to get read lock")
+            .report()
+    }
+}
+
+/// How long into the run consumer `consumer` of `consumers` is killed: the first after `after`,
+/// the others spread over the following `after`, so the group never loses all of them at once.
+fn restart_delay(after: Duration, consumer: usize, consumers: usize) -> Duration {
+    after + after.mul_f64(consumer as f64 / consumers.max(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::receiver::kafka::restart_delay;
+    use std::time::Duration;
+
+    #[test]
+    fn test_restart_delay() {
+        let after = Duration::from_secs(10);
+        let delays: Vec<_> = (0..4).map(|c| restart_delay(after, c, 4)).collect();
+        assert_eq!(
+            delays,
+            [10_000, 12_500, 15_000, 17_500].map(Duration::from_millis)
+        );
+        assert_eq!(restart_delay(after, 0, 1), after);
+    }
 }
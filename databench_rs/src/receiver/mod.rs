@@ -1,15 +1,77 @@
+use crate::hashes::{HashLedger, MessageHash};
+use crate::latency::LatencyReport;
+use crate::metrics::Probe;
+use crate::receiver::commit::CommitReport;
+use crate::receiver::connection::ConnectionEvent;
+use crate::receiver::group::ConsumerGroupSummary;
+use crate::receiver::mqtt5::ExpiryReport;
+use crate::receiver::sequence::SequenceReport;
 use anyhow::Result;
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 
 pub(crate) trait Receiver {
     fn new(brokers: Vec<String>, topic: String) -> Result<Self>
     where
         Self: Sized;
     fn begin(&mut self) -> Result<()>;
+    /// Stops receiving and returns once every thread has handed over its hashes, latencies
+    /// and sequence stamps.
     fn end(&mut self);
-    fn get_received_messages_hashes(&self) -> VecDeque<String>;
+    /// Records received hashes into `ledger` instead of the receiver's own, e.g. to share it
+    /// with the sender for streaming verification. Must be called before `begin`. Streaming
+    /// verification only matches hashes, so no sequence stamp ids are kept from then on.
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>);
+    fn get_received_messages_hashes(&self) -> Arc<HashLedger>;
     fn get_received_messages(&self) -> u64;
+    /// The live counter behind `get_received_messages`, e.g. to sample throughput.
+    fn get_received_counter(&self) -> Arc<AtomicU64>;
+    /// Live counts, errors, latencies and reconnects for the metrics endpoint.
+    fn get_probe(&self) -> Probe;
+    fn get_latency(&self) -> LatencyReport;
+    /// Gaps, reordering and duplicates of sequence stamped messages.
+    fn get_sequence_report(&self) -> SequenceReport;
+    /// Disconnects and reconnects in the order they happened, empty if not tracked.
+    fn get_connection_events(&self) -> Vec<ConnectionEvent> {
+        Vec::new()
+    }
+    /// Errors while receiving by category, e.g. `poll` or `commit`.
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        BTreeMap::new()
+    }
+    /// Effective librdkafka settings with secrets redacted, `None` for non-Kafka receivers.
+    fn get_client_config(&self) -> Option<BTreeMap<String, String>> {
+        None
+    }
+    /// Partition assignment and rebalances, `None` for receivers without consumer groups.
+    fn get_consumer_group(&self) -> Option<ConsumerGroupSummary> {
+        None
+    }
+    /// Offset commits, restarts and redeliveries, `None` for receivers without offsets.
+    fn get_commit_report(&self) -> Option<CommitReport> {
+        None
+    }
+    /// Messages with a message expiry interval, `None` for receivers that can't see it.
+    fn get_expiry_report(&self) -> Option<ExpiryReport> {
+        None
+    }
+    /// Hashes that received messages were published with while their content hashed
+    /// differently. They are recorded under the published hash, so they can be told apart from
+    /// lost messages, and are counted as corrupted.
+    fn get_corrupted_hashes(&self) -> Vec<MessageHash> {
+        Vec::new()
+    }
+    /// Sequence stamp ids and hashes of the received messages, empty if not tracked.
+    fn get_received_message_ids(&self) -> Vec<(String, MessageHash)> {
+        Vec::new()
+    }
 }
 
+pub mod commit;
+pub mod connection;
+pub mod group;
 pub mod kafka;
 pub mod mqtt;
+pub mod mqtt5;
+pub mod sequence;
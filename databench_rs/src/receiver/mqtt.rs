@@ -1,18 +1,71 @@
+use crate::errors::ErrorCounter;
+use crate::generator::sequence::extract_sequence;
+use crate::hashes::{hash_message, HashLedger, MessageHash, Side};
+use crate::helper::parse_mqtt_broker;
+use crate::latency::{LatencyRecorder, LatencyReport, MERGE_INTERVAL};
+use crate::metrics::{Probe, Reading};
+use crate::mqtt_config::MqttSecurity;
+use crate::receiver::connection::{ConnectionEvent, ConnectionEventKind, ConnectionLog};
+use crate::receiver::sequence::{SequenceReport, SequenceTracker};
 use crate::receiver::Receiver;
 use rumqttc::{Event, Incoming, MqttOptions};
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread::sleep;
+use std::thread::{self, JoinHandle};
+use std::time::{Instant, SystemTime};
 
 pub(crate) struct MQTT3Receiver {
     broker: String,
     port: u16,
     topic: String,
     receiving: Arc<AtomicBool>,
-    hashes: Arc<RwLock<VecDeque<String>>>,
+    hashes: Arc<HashLedger>,
+    ids: Arc<Mutex<Vec<(String, MessageHash)>>>,
+    /// Only batch verification matches ids, streaming verification leaves them out.
+    collect_ids: bool,
     received_message_cnt: Arc<AtomicU64>,
+    latency: Arc<RwLock<LatencyRecorder>>,
+    latency_per_topic: bool,
+    sequences: Arc<Mutex<SequenceTracker>>,
+    connection_log: Arc<RwLock<ConnectionLog>>,
+    client_id: String,
+    clean_session: bool,
+    security: MqttSecurity,
+    errors: Arc<ErrorCounter>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MQTT3Receiver {
+    /// Fixed client id, e.g. to resume a persistent session from an earlier run.
+    /// A unique id is generated if `None`.
+    pub fn with_client_id(mut self, client_id: Option<String>) -> Self {
+        if let Some(client_id) = client_id {
+            self.client_id = client_id;
+        }
+        self
+    }
+
+    /// With a persistent session (`false`) the broker keeps the subscription and queues
+    /// QoS 1 messages while the receiver is disconnected.
+    pub fn with_clean_session(mut self, clean_session: bool) -> Self {
+        self.clean_session = clean_session;
+        self
+    }
+
+    /// TLS, client certificate and username/password settings of the broker.
+    pub fn with_security(mut self, security: MqttSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Also breaks latencies down per `<topic>.<key>`, at the cost of one histogram per
+    /// stream and thread.
+    pub fn with_latency_per_topic(mut self, per_topic: bool) -> Self {
+        self.latency_per_topic = per_topic;
+        self
+    }
 }
 
 impl Receiver for MQTT3Receiver {
@@ -20,28 +73,26 @@ impl Receiver for MQTT3Receiver {
     where
         Self: Sized,
     {
-        if brokers.len() != 1 {
-            return Err(anyhow::anyhow!("Only one broker is supported for MQTT"));
-        }
-        let split_pos = match brokers[0].find(':') {
-            None => {
-                return Err(anyhow::anyhow!(
-                    "Broker address must be in the format <address>:<port>"
-                ))
-            }
-            Some(v) => {v}
-        };
-
-        let (broker, port) = brokers[0].split_at(split_pos);
-        let port = &port[1..];
+        let (broker, port) = parse_mqtt_broker(&brokers)?;
 
         Ok(Self {
-            broker: broker.to_string(),
-            port: port.parse::<u16>()?,
+            broker,
+            port,
             topic,
             receiving: Arc::new(AtomicBool::new(false)),
-            hashes: Arc::new(RwLock::new(VecDeque::new())),
+            hashes: Arc::new(HashLedger::new()),
+            ids: Arc::new(Mutex::new(Vec::new())),
+            collect_ids: true,
             received_message_cnt: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(RwLock::new(LatencyRecorder::new())),
+            latency_per_topic: false,
+            sequences: Arc::new(Mutex::new(SequenceTracker::new())),
+            connection_log: Arc::new(RwLock::new(ConnectionLog::new())),
+            client_id: format!("databench-receiver-{:08x}", rand::random::<u32>()),
+            clean_session: true,
+            security: MqttSecurity::default(),
+            errors: Arc::new(ErrorCounter::new()),
+            handles: Vec::new(),
         })
     }
 
@@ -53,19 +104,41 @@ impl Receiver for MQTT3Receiver {
 
         let receiving = self.receiving.clone();
 
-        let mqttoptions = MqttOptions::new("databench", self.broker.as_str(), self.port);
+        let mut mqttoptions =
+            MqttOptions::new(self.client_id.as_str(), self.broker.as_str(), self.port);
+        mqttoptions.set_clean_session(self.clean_session);
+        self.security.apply(&mut mqttoptions)?;
 
+        // Subscribing happens on every ConnAck without a session, so it is repeated after reconnects
         let (mut mqtt_client, mut notifications) = rumqttc::Client::new(mqttoptions, 1000);
-        mqtt_client.subscribe(self.topic.as_str(), rumqttc::QoS::AtLeastOnce)?;
+        let topic = self.topic.clone();
 
         let hashes = self.hashes.clone();
+        let ids = self.ids.clone();
+        let collect_ids = self.collect_ids;
         let received_messages_counter = self.received_message_cnt.clone();
+        let latency = self.latency.clone();
+        let latency_per_topic = self.latency_per_topic;
+        let sequences = self.sequences.clone();
+        let connection_log = self.connection_log.clone();
+        let errors = self.errors.clone();
 
-        thread::spawn(move || {
+        self.handles.push(thread::spawn(move || {
             let mut hasher = blake3::Hasher::new();
-            let mut thread_hashes = VecDeque::new();
+            let mut thread_hashes = Vec::new();
+            let mut thread_ids = Vec::new();
+            let mut thread_latency = LatencyRecorder::new().with_per_topic(latency_per_topic);
+            let mut last_merge = Instant::now();
 
             while receiving.load(Ordering::Relaxed) {
+                if last_merge.elapsed() >= MERGE_INTERVAL {
+                    last_merge = Instant::now();
+                    #[allow(clippy::expect_used)]
+                    latency
+                        .write()
+                        .expect("Failed to get write lock")
+                        .merge(&mut thread_latency);
+                }
                 match notifications.recv_timeout(std::time::Duration::from_millis(1000)) {
                     Err(_) => {
                         sleep(std::time::Duration::from_millis(1000));
@@ -75,17 +148,51 @@ impl Receiver for MQTT3Receiver {
                         match event_result {
                             Ok(event) => {
                                 match event {
+                                    Event::Incoming(Incoming::ConnAck(connack)) => {
+                                        #[allow(clippy::expect_used)]
+                                        let reconnect = connection_log
+                                            .write()
+                                            .expect("Failed to get write lock")
+                                            .connected(SystemTime::now());
+                                        if reconnect {
+                                            println!(
+                                                "Reconnected (session present: {})",
+                                                connack.session_present
+                                            );
+                                        }
+                                        if !connack.session_present {
+                                            if let Err(e) = mqtt_client.subscribe(
+                                                topic.as_str(),
+                                                rumqttc::QoS::AtLeastOnce,
+                                            ) {
+                                                eprintln!("Error subscribing: {:?}", e);
+                                                errors.add("subscribe");
+                                            }
+                                        }
+                                    }
                                     Event::Incoming(incoming_event) => {
                                         if let Incoming::Publish(publish) = incoming_event {
-                                            hasher.reset();
+                                            let received_at = SystemTime::now();
                                             let topic = publish.topic.replace('/', ".");
-                                            hasher.update(topic.as_bytes());
-                                            hasher.update(&publish.payload);
-
-                                            let raw_hash = hasher.finalize();
-                                            let hash = hex::encode(raw_hash.as_bytes());
-
-                                            thread_hashes.push_back(hash);
+                                            thread_latency.record(
+                                                &topic,
+                                                &publish.payload,
+                                                received_at,
+                                            );
+                                            let stamp = extract_sequence(&publish.payload);
+                                            let hash =
+                                                hash_message(&mut hasher, &topic, &publish.payload);
+                                            if let Some(stamp) =
+                                                stamp.as_ref().filter(|_| collect_ids)
+                                            {
+                                                thread_ids.push((stamp.id(&topic), hash));
+                                            }
+                                            #[allow(clippy::expect_used)]
+                                            sequences
+                                                .lock()
+                                                .expect("Failed to get sequence lock")
+                                                .record(&publish.topic, &topic, stamp);
+                                            thread_hashes.push(hash);
                                             received_messages_counter
                                                 .fetch_add(1, Ordering::Relaxed);
                                         }
@@ -97,24 +204,32 @@ impl Receiver for MQTT3Receiver {
                             }
                             Err(conn_error) => {
                                 eprintln!("Connection error: {:?}", conn_error);
+                                #[allow(clippy::expect_used)]
+                                connection_log
+                                    .write()
+                                    .expect("Failed to get write lock")
+                                    .disconnected(SystemTime::now(), conn_error.to_string());
+                                // The next poll reconnects, don't spin while the broker is down
+                                sleep(std::time::Duration::from_millis(1000));
                                 continue;
                             }
                         }
                     }
                 }
 
-                if received_messages_counter.load(Ordering::Relaxed) % 10000 == 0 {
+                if received_messages_counter
+                    .load(Ordering::Relaxed)
+                    .is_multiple_of(10000)
+                {
                     println!(
                         "Received {} messages",
                         received_messages_counter.load(Ordering::Relaxed)
                     );
-                    let hashlock = hashes.write();
+                    hashes.record(&mut thread_hashes, Side::Received);
                     #[allow(clippy::expect_used)]
-                    let mut hashes = hashlock.expect("Failed to get write lock");
-                    for hash in thread_hashes.drain(..) {
-                        hashes.push_back(hash);
-                    }
-                    thread_hashes.clear();
+                    ids.lock()
+                        .expect("Failed to get id lock")
+                        .append(&mut thread_ids);
                 }
             }
 
@@ -122,28 +237,102 @@ impl Receiver for MQTT3Receiver {
                 "Received {} messages",
                 received_messages_counter.load(Ordering::Relaxed)
             );
-            let hashlock = hashes.write();
+            hashes.record(&mut thread_hashes, Side::Received);
             #[allow(clippy::expect_used)]
-            let mut hashes = hashlock.expect("Failed to get write lock");
-            for hash in thread_hashes.drain(..) {
-                hashes.push_back(hash);
-            }
-            thread_hashes.clear();
-        });
+            ids.lock()
+                .expect("Failed to get id lock")
+                .append(&mut thread_ids);
+
+            #[allow(clippy::expect_used)]
+            latency
+                .write()
+                .expect("Failed to get write lock")
+                .merge(&mut thread_latency);
+        }));
 
         Ok(())
     }
 
     fn end(&mut self) {
-        self.receiving.swap(false, Ordering::Relaxed);
+        self.receiving.store(false, Ordering::Relaxed);
+        // Each thread hands over its last hashes, latencies and stamps before it returns
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
     }
 
-    fn get_received_messages_hashes(&self) -> VecDeque<String> {
-        #[allow(clippy::expect_used)]
-        self.hashes.read().expect("Failed to get read lock").clone()
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>) {
+        self.hashes = ledger;
+        self.collect_ids = false;
+    }
+
+    fn get_received_messages_hashes(&self) -> Arc<HashLedger> {
+        self.hashes.clone()
+    }
+
+    fn get_probe(&self) -> Probe {
+        let received = self.received_message_cnt.clone();
+        let errors = self.errors.clone();
+        let latency = self.latency.clone();
+        let connection_log = self.connection_log.clone();
+        Arc::new(move || {
+            #[allow(clippy::expect_used)]
+            let reconnects = connection_log
+                .read()
+                .expect("Failed to get read lock")
+                .events()
+                .iter()
+                .filter(|event| event.kind == ConnectionEventKind::Reconnected)
+                .count();
+            Reading {
+                messages: received.load(Ordering::Relaxed),
+                errors: errors.counts(),
+                #[allow(clippy::expect_used)]
+                latency: Some(latency.read().expect("Failed to get read lock").buckets()),
+                reconnects: Some(reconnects as u64),
+            }
+        })
+    }
+
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        self.errors.counts()
     }
 
     fn get_received_messages(&self) -> u64 {
         self.received_message_cnt.load(Ordering::Relaxed)
     }
+
+    fn get_received_counter(&self) -> Arc<AtomicU64> {
+        self.received_message_cnt.clone()
+    }
+
+    fn get_received_message_ids(&self) -> Vec<(String, MessageHash)> {
+        #[allow(clippy::expect_used)]
+        self.ids.lock().expect("Failed to get id lock").clone()
+    }
+
+    fn get_sequence_report(&self) -> SequenceReport {
+        #[allow(clippy::expect_used)]
+        self.sequences
+            .lock()
+            .expect("Failed to get sequence lock")
+            .report()
+    }
+
+    fn get_latency(&self) -> LatencyReport {
+        #[allow(clippy::expect_used)]
+        self.latency
+            .read()
+            .expect("Failed to get read lock")
+            .report()
+    }
+
+    fn get_connection_events(&self) -> Vec<ConnectionEvent> {
+        #[allow(clippy::expect_used)]
+        self.connection_log
+            .read()
+            .expect("Failed to get read lock")
+            .events()
+            .to_vec()
+    }
 }
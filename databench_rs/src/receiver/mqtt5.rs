@@ -0,0 +1,494 @@
+use crate::errors::ErrorCounter;
+use crate::generator::sequence::{extract_sequence, sequence_from_headers};
+use crate::generator::SEND_TIMESTAMP_HEADER;
+use crate::hashes::{hash_message, parse_hex_hash, HashLedger, MessageHash, Side};
+use crate::helper::parse_mqtt_broker;
+use crate::latency::{LatencyRecorder, LatencyReport, MERGE_INTERVAL};
+use crate::metrics::{Probe, Reading};
+use crate::mqtt_config::MqttSecurity;
+use crate::receiver::sequence::{SequenceReport, SequenceTracker};
+use crate::receiver::Receiver;
+use rumqttc::v5::mqttbytes::v5::Publish;
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::{Client, Event, Incoming, MqttOptions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::sleep;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// User property carrying the hex blake3 hash of `<dotted topic><payload>` as published.
+/// The message is matched under it, and counted as corrupted if its content hashes differently.
+pub const HASH_PROPERTY: &str = "databench-hash";
+/// User property carrying the send time in nanoseconds since the unix epoch.
+/// Preferred over the timestamp embedded in the payload.
+pub const SEND_TIMESTAMP_PROPERTY: &str = SEND_TIMESTAMP_HEADER;
+
+pub(crate) struct MQTT5Receiver {
+    broker: String,
+    port: u16,
+    topic: String,
+    receiving: Arc<AtomicBool>,
+    hashes: Arc<HashLedger>,
+    ids: Arc<Mutex<Vec<(String, MessageHash)>>>,
+    /// Only batch verification matches ids, streaming verification leaves them out.
+    collect_ids: bool,
+    received_message_cnt: Arc<AtomicU64>,
+    latency: Arc<RwLock<LatencyRecorder>>,
+    latency_per_topic: bool,
+    sequences: Arc<Mutex<SequenceTracker>>,
+    /// Published hashes of messages that arrived with different content.
+    corrupted: Arc<Mutex<Vec<MessageHash>>>,
+    expiry: Arc<Mutex<ExpiryReport>>,
+    shared_group: Option<String>,
+    connections: usize,
+    security: MqttSecurity,
+    errors: Arc<ErrorCounter>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MQTT5Receiver {
+    /// Subscribes through `$share/<group>/<topic>`, so the broker spreads messages over
+    /// all members of the group instead of sending each one to every client.
+    pub fn with_shared_group(mut self, shared_group: Option<String>) -> Self {
+        self.shared_group = shared_group;
+        self
+    }
+
+    /// Number of client connections subscribing in parallel.
+    /// Only useful together with a shared group, otherwise every message is received once per connection.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    /// TLS, client certificate and username/password settings of the broker.
+    pub fn with_security(mut self, security: MqttSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Also breaks latencies down per `<topic>.<key>`, at the cost of one histogram per
+    /// stream and thread.
+    pub fn with_latency_per_topic(mut self, per_topic: bool) -> Self {
+        self.latency_per_topic = per_topic;
+        self
+    }
+
+    fn subscription(&self) -> String {
+        match &self.shared_group {
+            Some(group) => format!("$share/{}/{}", group, self.topic),
+            None => self.topic.clone(),
+        }
+    }
+}
+
+/// Messages the broker handed over with a message expiry interval.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ExpiryReport {
+    pub with_expiry: u64,
+    /// Lowest interval left when a message arrived, in seconds.
+    pub min_remaining_s: Option<u32>,
+}
+
+impl ExpiryReport {
+    fn record(&mut self, publish: &Publish) {
+        let Some(remaining) = publish
+            .properties
+            .as_ref()
+            .and_then(|p| p.message_expiry_interval)
+        else {
+            return;
+        };
+        // The broker lowers the interval by the time the message was queued
+        self.with_expiry += 1;
+        self.min_remaining_s = Some(self.min_remaining_s.map_or(remaining, |m| m.min(remaining)));
+    }
+
+    fn merge(&mut self, other: &ExpiryReport) {
+        self.with_expiry += other.with_expiry;
+        self.min_remaining_s = match (self.min_remaining_s, other.min_remaining_s) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// The hash to match a received message under and whether its content differs from what was
+/// published, according to [`HASH_PROPERTY`].
+fn received_hash(
+    hasher: &mut blake3::Hasher,
+    topic: &str,
+    publish: &Publish,
+) -> (MessageHash, bool) {
+    let hash = hash_message(hasher, topic, &publish.payload);
+    match user_property(publish, HASH_PROPERTY).and_then(parse_hex_hash) {
+        Some(published) => (published, published != hash),
+        None => (hash, false),
+    }
+}
+
+fn user_property<'a>(publish: &'a Publish, name: &str) -> Option<&'a str> {
+    publish
+        .properties
+        .as_ref()?
+        .user_properties
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+impl Receiver for MQTT5Receiver {
+    fn new(brokers: Vec<String>, topic: String) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let (broker, port) = parse_mqtt_broker(&brokers)?;
+
+        Ok(Self {
+            broker,
+            port,
+            topic,
+            receiving: Arc::new(AtomicBool::new(false)),
+            hashes: Arc::new(HashLedger::new()),
+            ids: Arc::new(Mutex::new(Vec::new())),
+            collect_ids: true,
+            received_message_cnt: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(RwLock::new(LatencyRecorder::new())),
+            latency_per_topic: false,
+            sequences: Arc::new(Mutex::new(SequenceTracker::new())),
+            corrupted: Arc::new(Mutex::new(Vec::new())),
+            expiry: Arc::new(Mutex::new(ExpiryReport::default())),
+            shared_group: None,
+            connections: 1,
+            security: MqttSecurity::default(),
+            errors: Arc::new(ErrorCounter::new()),
+            handles: Vec::new(),
+        })
+    }
+
+    fn begin(&mut self) -> anyhow::Result<()> {
+        // If already receiving, return
+        if self.receiving.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let subscription = self.subscription();
+        let run_id: u32 = rand::random();
+
+        for connection_id in 0..self.connections {
+            let mut mqttoptions = MqttOptions::new(
+                format!("databench-receiver-{:08x}-{}", run_id, connection_id),
+                self.broker.as_str(),
+                self.port,
+            );
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
+            self.security.apply_v5(&mut mqttoptions)?;
+
+            // Subscribing happens on every ConnAck without a session, so it is repeated after
+            // reconnects
+            let (mqtt_client, mut notifications) = Client::new(mqttoptions, 1000);
+            let subscription = subscription.clone();
+
+            let receiving = self.receiving.clone();
+            let hashes = self.hashes.clone();
+            let ids = self.ids.clone();
+            let collect_ids = self.collect_ids;
+            let received_messages_counter = self.received_message_cnt.clone();
+            let latency = self.latency.clone();
+            let latency_per_topic = self.latency_per_topic;
+            let sequences = self.sequences.clone();
+            let all_corrupted = self.corrupted.clone();
+            let all_expiry = self.expiry.clone();
+            let errors = self.errors.clone();
+
+            self.handles.push(thread::spawn(move || {
+                let mut hasher = blake3::Hasher::new();
+                let mut thread_hashes = Vec::new();
+                let mut thread_ids = Vec::new();
+                let mut thread_latency = LatencyRecorder::new().with_per_topic(latency_per_topic);
+                let mut expiry = ExpiryReport::default();
+                let mut corrupted = Vec::new();
+                let mut last_merge = Instant::now();
+
+                while receiving.load(Ordering::Relaxed) {
+                    if last_merge.elapsed() >= MERGE_INTERVAL {
+                        last_merge = Instant::now();
+                        #[allow(clippy::expect_used)]
+                        latency
+                            .write()
+                            .expect("Failed to get write lock")
+                            .merge(&mut thread_latency);
+                    }
+                    let publish = match notifications.recv_timeout(Duration::from_millis(1000)) {
+                        Err(_) => {
+                            sleep(Duration::from_millis(1000));
+                            continue;
+                        }
+                        Ok(Ok(Event::Incoming(Incoming::Publish(publish)))) => publish,
+                        Ok(Ok(Event::Incoming(Incoming::ConnAck(connack)))) => {
+                            if !connack.session_present {
+                                if let Err(e) =
+                                    mqtt_client.subscribe(subscription.as_str(), QoS::AtLeastOnce)
+                                {
+                                    eprintln!("Error subscribing: {:?}", e);
+                                    errors.add("subscribe");
+                                }
+                            }
+                            continue;
+                        }
+                        Ok(Ok(_)) => continue,
+                        Ok(Err(conn_error)) => {
+                            eprintln!("Connection error: {:?}", conn_error);
+                            errors.add("connection");
+                            // The next poll reconnects, don't spin while the broker is down
+                            sleep(Duration::from_millis(1000));
+                            continue;
+                        }
+                    };
+
+                    let received_at = SystemTime::now();
+                    let stream = String::from_utf8_lossy(&publish.topic);
+                    let topic = stream.replace('/', ".");
+
+                    match user_property(&publish, SEND_TIMESTAMP_PROPERTY)
+                        .and_then(|ts| ts.parse::<u128>().ok())
+                    {
+                        Some(sent_ns) => {
+                            thread_latency.record_sent_ns(&topic, sent_ns, received_at)
+                        }
+                        None => thread_latency.record(&topic, &publish.payload, received_at),
+                    }
+                    expiry.record(&publish);
+                    let stamp = publish
+                        .properties
+                        .as_ref()
+                        .and_then(|properties| {
+                            sequence_from_headers(
+                                properties
+                                    .user_properties
+                                    .iter()
+                                    .map(|(key, value)| (key.as_str(), value.as_bytes())),
+                            )
+                        })
+                        .or_else(|| extract_sequence(&publish.payload));
+
+                    let (hash, changed) = received_hash(&mut hasher, &topic, &publish);
+                    if changed {
+                        corrupted.push(hash);
+                    }
+                    if let Some(stamp) = stamp.as_ref().filter(|_| collect_ids) {
+                        thread_ids.push((stamp.id(&topic), hash));
+                    }
+                    #[allow(clippy::expect_used)]
+                    sequences
+                        .lock()
+                        .expect("Failed to get sequence lock")
+                        .record(&stream, &topic, stamp);
+                    thread_hashes.push(hash);
+
+                    let received = received_messages_counter.fetch_add(1, Ordering::Relaxed) + 1;
+                    if received.is_multiple_of(10000) {
+                        println!("Received {} messages", received);
+                    }
+
+                    if thread_hashes.len() >= 10000 {
+                        hashes.record(&mut thread_hashes, Side::Received);
+                        #[allow(clippy::expect_used)]
+                        ids.lock()
+                            .expect("Failed to get id lock")
+                            .append(&mut thread_ids);
+                    }
+                }
+
+                #[allow(clippy::expect_used)]
+                all_expiry
+                    .lock()
+                    .expect("Failed to get expiry lock")
+                    .merge(&expiry);
+                #[allow(clippy::expect_used)]
+                all_corrupted
+                    .lock()
+                    .expect("Failed to get corrupted lock")
+                    .append(&mut corrupted);
+
+                hashes.record(&mut thread_hashes, Side::Received);
+                #[allow(clippy::expect_used)]
+                ids.lock()
+                    .expect("Failed to get id lock")
+                    .append(&mut thread_ids);
+
+                #[allow(clippy::expect_used)]
+                latency
+                    .write()
+                    .expect("Failed to get write lock")
+                    .merge(&mut thread_latency);
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self) {
+        self.receiving.store(false, Ordering::Relaxed);
+        // Each thread hands over its last hashes, latencies and stamps before it returns
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>) {
+        self.hashes = ledger;
+        self.collect_ids = false;
+    }
+
+    fn get_received_messages_hashes(&self) -> Arc<HashLedger> {
+        self.hashes.clone()
+    }
+
+    fn get_probe(&self) -> Probe {
+        let received = self.received_message_cnt.clone();
+        let errors = self.errors.clone();
+        let latency = self.latency.clone();
+        Arc::new(move || Reading {
+            messages: received.load(Ordering::Relaxed),
+            errors: errors.counts(),
+            #[allow(clippy::expect_used)]
+            latency: Some(latency.read().expect("Failed to get read lock").buckets()),
+            reconnects: None,
+        })
+    }
+
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        self.errors.counts()
+    }
+
+    fn get_received_messages(&self) -> u64 {
+        self.received_message_cnt.load(Ordering::Relaxed)
+    }
+
+    fn get_received_counter(&self) -> Arc<AtomicU64> {
+        self.received_message_cnt.clone()
+    }
+
+    fn get_received_message_ids(&self) -> Vec<(String, MessageHash)> {
+        #[allow(clippy::expect_used)]
+        self.ids.lock().expect("Failed to get id lock").clone()
+    }
+
+    fn get_sequence_report(&self) -> SequenceReport {
+        #[allow(clippy::expect_used)]
+        self.sequences
+            .lock()
+            .expect("Failed to get sequence lock")
+            .report()
+    }
+
+    fn get_corrupted_hashes(&self) -> Vec<MessageHash> {
+        #[allow(clippy::expect_used)]
+        self.corrupted
+            .lock()
+            .expect("Failed to get corrupted lock")
+            .clone()
+    }
+
+    fn get_expiry_report(&self) -> Option<ExpiryReport> {
+        #[allow(clippy::expect_used)]
+        Some(
+            self.expiry
+                .lock()
+                .expect("Failed to get expiry lock")
+                .clone(),
+        )
+    }
+
+    fn get_latency(&self) -> LatencyReport {
+        #[allow(clippy::expect_used)]
+        self.latency
+            .read()
+            .expect("Failed to get read lock")
+            .report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashes::hash_message;
+    use crate::receiver::mqtt5::{
+        received_hash, user_property, ExpiryReport, HASH_PROPERTY, SEND_TIMESTAMP_PROPERTY,
+    };
+    use rumqttc::v5::mqttbytes::v5::{Publish, PublishProperties};
+    use rumqttc::v5::mqttbytes::QoS;
+
+    #[test]
+    fn test_publish_properties() {
+        let with_properties = Publish::new(
+            "umh/v1/a",
+            QoS::AtLeastOnce,
+            "{}",
+            Some(PublishProperties {
+                message_expiry_interval: Some(50),
+                user_properties: vec![(SEND_TIMESTAMP_PROPERTY.to_string(), "123".to_string())],
+                ..Default::default()
+            }),
+        );
+        let without_properties = Publish::new("umh/v1/a", QoS::AtLeastOnce, "{}", None);
+
+        assert_eq!(
+            user_property(&with_properties, SEND_TIMESTAMP_PROPERTY),
+            Some("123")
+        );
+        assert_eq!(
+            user_property(&without_properties, SEND_TIMESTAMP_PROPERTY),
+            None
+        );
+
+        let mut expiry = ExpiryReport::default();
+        expiry.record(&with_properties);
+        expiry.record(&without_properties);
+        assert_eq!(expiry.with_expiry, 1);
+        assert_eq!(expiry.min_remaining_s, Some(50));
+
+        let mut merged = ExpiryReport::default();
+        merged.merge(&ExpiryReport::default());
+        merged.merge(&expiry);
+        assert_eq!(merged, expiry);
+    }
+
+    #[test]
+    fn test_received_hash() {
+        let mut hasher = blake3::Hasher::new();
+        let published = hash_message(&mut hasher, "umh.v1.a", b"{}");
+        let with_hash = |payload: &'static str| {
+            Publish::new(
+                "umh/v1/a",
+                QoS::AtLeastOnce,
+                payload,
+                Some(PublishProperties {
+                    user_properties: vec![(
+                        HASH_PROPERTY.to_string(),
+                        format!("{:032x}", published),
+                    )],
+                    ..Default::default()
+                }),
+            )
+        };
+
+        assert_eq!(
+            received_hash(&mut hasher, "umh.v1.a", &with_hash("{}")),
+            (published, false)
+        );
+        // Changed on the way, but still matched as the published message
+        assert_eq!(
+            received_hash(&mut hasher, "umh.v1.a", &with_hash("{\"x\":1}")),
+            (published, true)
+        );
+        let without_hash = Publish::new("umh/v1/a", QoS::AtLeastOnce, "{}", None);
+        assert_eq!(
+            received_hash(&mut hasher, "umh.v1.a", &without_hash),
+            (published, false)
+        );
+    }
+}
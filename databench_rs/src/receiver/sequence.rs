@@ -0,0 +1,195 @@
+use crate::generator::sequence::SequenceStamp;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Ordering statistics for one partition or MQTT topic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SequenceStats {
+    pub messages: u64,
+    /// Sequence numbers that were skipped and never arrived.
+    pub gaps: u64,
+    /// Messages that arrived after a higher sequence number of the same producer and key.
+    pub reordered: u64,
+    /// Largest distance in sequence numbers between a late message and the highest one seen.
+    pub max_reorder_distance: u64,
+    /// Sequence numbers that arrived more than once.
+    pub duplicates: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SequenceReport {
+    pub overall: SequenceStats,
+    /// Keyed by partition (`<topic>/<partition>`) or MQTT topic.
+    pub per_stream: BTreeMap<String, SequenceStats>,
+    /// Messages that carried no sequence stamp.
+    pub without_sequence: u64,
+}
+
+#[derive(Debug, Default)]
+struct KeyState {
+    next: u64,
+    /// Sequence numbers below `next` that have not arrived yet, as ranges from start to end
+    /// (exclusive). A jump in sequence numbers costs one range, not one entry per number.
+    missing: BTreeMap<u64, u64>,
+    /// Sequence numbers in `missing`.
+    missing_count: u64,
+}
+
+impl KeyState {
+    fn skip(&mut self, to: u64) {
+        self.missing.insert(self.next, to);
+        self.missing_count += to - self.next;
+    }
+
+    /// Takes `seq` out of the missing ranges, false if it wasn't missing.
+    fn fill(&mut self, seq: u64) -> bool {
+        let Some((&start, &end)) = self.missing.range(..=seq).next_back() else {
+            return false;
+        };
+        if seq >= end {
+            return false;
+        }
+        self.missing.remove(&start);
+        if start < seq {
+            self.missing.insert(start, seq);
+        }
+        if seq + 1 < end {
+            self.missing.insert(seq + 1, end);
+        }
+        self.missing_count -= 1;
+        true
+    }
+}
+
+/// Follows the sequence numbers of every producer and key as they arrive.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    keys: HashMap<(String, String, String), KeyState>,
+    per_stream: HashMap<String, SequenceStats>,
+    without_sequence: u64,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a message received on `stream` for the re-assembled dotted `topic`.
+    pub fn record(&mut self, stream: &str, topic: &str, stamp: Option<SequenceStamp>) {
+        let Some(stamp) = stamp else {
+            self.without_sequence += 1;
+            return;
+        };
+        let state = self
+            .keys
+            .entry((stream.to_string(), stamp.producer_id, topic.to_string()))
+            .or_default();
+        if !self.per_stream.contains_key(stream) {
+            self.per_stream
+                .insert(stream.to_string(), Default::default());
+        }
+        let Some(stats) = self.per_stream.get_mut(stream) else {
+            return;
+        };
+
+        stats.messages += 1;
+        if stamp.seq >= state.next {
+            if stamp.seq > state.next {
+                state.skip(stamp.seq);
+            }
+            state.next = stamp.seq.saturating_add(1);
+        } else if state.fill(stamp.seq) {
+            stats.reordered += 1;
+            stats.max_reorder_distance = stats.max_reorder_distance.max(state.next - 1 - stamp.seq);
+        } else {
+            stats.duplicates += 1;
+        }
+    }
+
+    pub fn report(&self) -> SequenceReport {
+        let mut per_stream: BTreeMap<String, SequenceStats> = self
+            .per_stream
+            .iter()
+            .map(|(stream, stats)| (stream.clone(), stats.clone()))
+            .collect();
+        for ((stream, _, _), state) in &self.keys {
+            if let Some(stats) = per_stream.get_mut(stream) {
+                stats.gaps += state.missing_count;
+            }
+        }
+
+        let mut overall = SequenceStats::default();
+        for stats in per_stream.values() {
+            overall.messages += stats.messages;
+            overall.gaps += stats.gaps;
+            overall.reordered += stats.reordered;
+            overall.max_reorder_distance =
+                overall.max_reorder_distance.max(stats.max_reorder_distance);
+            overall.duplicates += stats.duplicates;
+        }
+
+        SequenceReport {
+            overall,
+            per_stream,
+            without_sequence: self.without_sequence,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::sequence::SequenceStamp;
+    use crate::receiver::sequence::SequenceTracker;
+
+    #[test]
+    fn test_sequence_tracker() {
+        let stamp = |producer: &str, seq| {
+            Some(SequenceStamp {
+                producer_id: producer.to_string(),
+                seq,
+            })
+        };
+        let mut tracker = SequenceTracker::new();
+        // 2 and 3 arrive late, 5 never arrives, 1 arrives twice
+        for seq in [0, 1, 4, 2, 1, 6, 3] {
+            tracker.record("t/0", "a.b", stamp("p1", seq));
+        }
+        // Same sequence numbers from another producer or key are independent
+        tracker.record("t/0", "a.b", stamp("p2", 0));
+        tracker.record("t/0", "a.c", stamp("p1", 0));
+        tracker.record("t/1", "a.d", stamp("p1", 1));
+        tracker.record("t/1", "a.d", None);
+
+        let report = tracker.report();
+        let t0 = &report.per_stream["t/0"];
+        assert_eq!(t0.messages, 9);
+        assert_eq!(t0.gaps, 1);
+        assert_eq!(t0.reordered, 2);
+        assert_eq!(t0.max_reorder_distance, 3);
+        assert_eq!(t0.duplicates, 1);
+        assert_eq!(report.per_stream["t/1"].gaps, 1);
+        assert_eq!(report.overall.gaps, 2);
+        assert_eq!(report.without_sequence, 1);
+    }
+
+    #[test]
+    fn test_sequence_tracker_jump() {
+        let stamp = |seq| {
+            Some(SequenceStamp {
+                producer_id: "p1".to_string(),
+                seq,
+            })
+        };
+        let mut tracker = SequenceTracker::new();
+        // A corrupted or foreign stamp far ahead must not allocate every skipped number
+        for seq in [0, u64::MAX - 1, 5, 3, 5, 1, u64::MAX] {
+            tracker.record("t/0", "a.b", stamp(seq));
+        }
+
+        let stats = &tracker.report().per_stream["t/0"];
+        assert_eq!(stats.gaps, u64::MAX - 5);
+        assert_eq!(stats.reordered, 3);
+        assert_eq!(stats.max_reorder_distance, u64::MAX - 2);
+        assert_eq!(stats.duplicates, 1);
+    }
+}
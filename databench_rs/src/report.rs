@@ -0,0 +1,358 @@
+use crate::errors::merge;
+use crate::kafka_admin::TopicDescription;
+use crate::kafka_config::redact_arguments;
+use crate::latency::LatencyReport;
+use crate::receiver::commit::CommitReport;
+use crate::receiver::connection::{unix_ms, ConnectionSummary};
+use crate::receiver::group::ConsumerGroupSummary;
+use crate::receiver::mqtt5::ExpiryReport;
+use crate::receiver::sequence::SequenceReport;
+use crate::runner::{PhaseResult, ReceiverKind};
+use crate::scenario::{PhaseKind, Scenario};
+use crate::sender::delivery::DeliveryReport;
+use crate::sender::kafka::TransactionReport;
+use crate::throughput::ThroughputSample;
+use crate::verify::VerificationReport;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseReport {
+    pub name: String,
+    pub kind: PhaseKind,
+    pub sender: Option<SenderReport>,
+    pub receivers: Vec<ReceiverReport>,
+    pub topic: Option<TopicDescription>,
+    /// Milliseconds since the unix epoch.
+    pub started_at_ms: u64,
+    pub ended_at_ms: u64,
+    pub throughput: Vec<ThroughputSample>,
+    /// Errors of the sender and all receivers by category.
+    pub errors: BTreeMap<String, u64>,
+    /// Why the phase failed, `None` if it ran to the end. A failed phase has no results.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SenderReport {
+    pub sent: u64,
+    pub elapsed_s: f64,
+    pub messages_per_second: f64,
+    /// Pass as `--seed` to send the same messages again.
+    pub seed: u64,
+    pub client_config: Option<BTreeMap<String, String>>,
+    pub transactions: Option<TransactionReport>,
+    /// Acknowledged and failed deliveries, `None` for non-Kafka senders.
+    pub delivery: Option<DeliveryReport>,
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiverReport {
+    pub receiver: ReceiverKind,
+    pub topic: String,
+    pub received: u64,
+    pub elapsed_s: f64,
+    pub messages_per_second: f64,
+    pub verification: Option<VerificationReport>,
+    pub received_percent: Option<f64>,
+    pub latency: LatencyReport,
+    pub sequence: SequenceReport,
+    pub connection: ConnectionSummary,
+    pub consumer_group: Option<ConsumerGroupSummary>,
+    pub commits: Option<CommitReport>,
+    pub expiry: Option<ExpiryReport>,
+    pub client_config: Option<BTreeMap<String, String>>,
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl PhaseReport {
+    pub fn new(name: &str, kind: PhaseKind, result: &PhaseResult) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            sender: result.send.as_ref().map(|send| SenderReport {
+                sent: send.sent,
+                elapsed_s: send.elapsed.as_secs_f64(),
+                messages_per_second: send.sent as f64 / send.elapsed.as_secs_f64(),
+                seed: send.seed,
+                client_config: send.client_config.clone(),
+                transactions: send.transactions.clone(),
+                delivery: send.delivery.clone(),
+                errors: send.errors.clone(),
+            }),
+            receivers: result
+                .receivers
+                .iter()
+                .map(|receive| ReceiverReport {
+                    receiver: receive.receiver,
+                    topic: receive.topic.clone(),
+                    received: receive.received,
+                    elapsed_s: receive.elapsed.as_secs_f64(),
+                    messages_per_second: receive.received as f64 / receive.elapsed.as_secs_f64(),
+                    verification: receive.verification.clone(),
+                    received_percent: receive.received_percent(),
+                    latency: receive.latency.clone(),
+                    sequence: receive.sequence.clone(),
+                    connection: receive.connection.clone(),
+                    consumer_group: receive.consumer_group.clone(),
+                    commits: receive.commits.clone(),
+                    expiry: receive.expiry.clone(),
+                    client_config: receive.client_config.clone(),
+                    errors: receive.errors.clone(),
+                })
+                .collect(),
+            topic: result.topic.clone(),
+            started_at_ms: unix_ms(result.started_at),
+            ended_at_ms: unix_ms(result.ended_at),
+            throughput: result.throughput.clone(),
+            errors: phase_errors(result),
+            error: None,
+        }
+    }
+
+    /// A phase that failed with `error` before it had results, counted as a `phase` error.
+    pub fn failed(
+        name: &str,
+        kind: PhaseKind,
+        started_at: SystemTime,
+        error: &anyhow::Error,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            sender: None,
+            receivers: vec![],
+            topic: None,
+            started_at_ms: unix_ms(started_at),
+            ended_at_ms: unix_ms(SystemTime::now()),
+            throughput: vec![],
+            errors: BTreeMap::from([("phase".to_string(), 1)]),
+            error: Some(format!("{:#}", error)),
+        }
+    }
+}
+
+fn phase_errors(result: &PhaseResult) -> BTreeMap<String, u64> {
+    let mut errors = BTreeMap::new();
+    if let Some(send) = &result.send {
+        merge(&mut errors, &send.errors);
+    }
+    for receive in &result.receivers {
+        merge(&mut errors, &receive.errors);
+    }
+    errors
+}
+
+/// Everything about one run in a single artifact, so CI and dashboards can compare runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub version: String,
+    /// Command line the run was started with, values of secret settings redacted.
+    pub arguments: Vec<String>,
+    /// The scenario the run followed, secrets only as references or redacted.
+    pub scenario: Option<Scenario>,
+    pub brokers: RunBrokers,
+    /// Milliseconds since the unix epoch.
+    pub started_at_ms: u64,
+    pub ended_at_ms: u64,
+    pub totals: RunTotals,
+    /// Errors over all phases by category.
+    pub errors: BTreeMap<String, u64>,
+    pub phases: Vec<PhaseReport>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunBrokers {
+    pub kafka: Vec<String>,
+    pub mqtt: Option<String>,
+}
+
+/// Counts summed over all phases, verification counts over all receivers that verified.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunTotals {
+    pub sent: u64,
+    pub received: u64,
+    pub delivered: u64,
+    pub lost: u64,
+    pub duplicated: u64,
+    pub duplicates: u64,
+    pub unexpected: u64,
+    pub corrupted: u64,
+}
+
+const CSV_HEADER: &str = "phase,kind,receiver,topic,sent,received,delivered,lost,duplicates,\
+unexpected,corrupted,sent_per_second,received_per_second,p50_us,p90_us,p99_us,p999_us,max_us,errors,\
+error";
+
+impl RunReport {
+    pub fn new(brokers: RunBrokers, phases: Vec<PhaseReport>) -> Self {
+        let mut totals = RunTotals::default();
+        let mut errors = BTreeMap::new();
+        for phase in &phases {
+            totals.sent += phase.sender.as_ref().map(|s| s.sent).unwrap_or_default();
+            for receiver in &phase.receivers {
+                totals.received += receiver.received;
+                if let Some(verification) = &receiver.verification {
+                    totals.delivered += verification.delivered;
+                    totals.lost += verification.lost;
+                    totals.duplicated += verification.duplicated;
+                    totals.duplicates += verification.duplicates;
+                    totals.unexpected += verification.unexpected;
+                    totals.corrupted += verification.corrupted;
+                }
+            }
+            merge(&mut errors, &phase.errors);
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arguments: redact_arguments(std::env::args()),
+            scenario: None,
+            brokers,
+            started_at_ms: phases
+                .iter()
+                .map(|p| p.started_at_ms)
+                .min()
+                .unwrap_or_default(),
+            ended_at_ms: phases
+                .iter()
+                .map(|p| p.ended_at_ms)
+                .max()
+                .unwrap_or_default(),
+            totals,
+            errors,
+            phases,
+        }
+    }
+
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenario = Some(scenario);
+        self
+    }
+
+    /// One row per phase and receiver, a phase without receivers gets one row of its own.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for phase in &self.phases {
+            let sent = phase.sender.as_ref();
+            let errors: u64 = phase.errors.values().sum();
+            let mut row = |receiver: Option<&ReceiverReport>| {
+                let verification = receiver.and_then(|r| r.verification.as_ref());
+                let latency = receiver.map(|r| &r.latency.overall);
+                let fields = [
+                    csv_field(&phase.name),
+                    format!("{:?}", phase.kind).to_lowercase(),
+                    receiver
+                        .map(|r| r.receiver.name().to_string())
+                        .unwrap_or_default(),
+                    receiver.map(|r| csv_field(&r.topic)).unwrap_or_default(),
+                    sent.map(|s| s.sent.to_string()).unwrap_or_default(),
+                    receiver.map(|r| r.received.to_string()).unwrap_or_default(),
+                    verification
+                        .map(|v| v.delivered.to_string())
+                        .unwrap_or_default(),
+                    verification.map(|v| v.lost.to_string()).unwrap_or_default(),
+                    verification
+                        .map(|v| v.duplicates.to_string())
+                        .unwrap_or_default(),
+                    verification
+                        .map(|v| v.unexpected.to_string())
+                        .unwrap_or_default(),
+                    verification
+                        .map(|v| v.corrupted.to_string())
+                        .unwrap_or_default(),
+                    sent.map(|s| format!("{:.1}", s.messages_per_second))
+                        .unwrap_or_default(),
+                    receiver
+                        .map(|r| format!("{:.1}", r.messages_per_second))
+                        .unwrap_or_default(),
+                    latency.map(|l| l.p50_us.to_string()).unwrap_or_default(),
+                    latency.map(|l| l.p90_us.to_string()).unwrap_or_default(),
+                    latency.map(|l| l.p99_us.to_string()).unwrap_or_default(),
+                    latency.map(|l| l.p999_us.to_string()).unwrap_or_default(),
+                    latency.map(|l| l.max_us.to_string()).unwrap_or_default(),
+                    errors.to_string(),
+                    phase.error.as_deref().map(csv_field).unwrap_or_default(),
+                ];
+                csv.push_str(&fields.join(","));
+                csv.push('\n');
+            };
+            if phase.receivers.is_empty() {
+                row(None);
+            }
+            for receiver in &phase.receivers {
+                row(Some(receiver));
+            }
+        }
+        csv
+    }
+}
+
+/// Quotes a field that contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{csv_field, PhaseReport, RunBrokers, RunReport, SenderReport};
+    use crate::scenario::PhaseKind;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_run_report() {
+        let phase = |name: &str, sent: u64, started_at_ms: u64| PhaseReport {
+            name: name.to_string(),
+            kind: PhaseKind::Steady,
+            sender: Some(SenderReport {
+                sent,
+                elapsed_s: 1.0,
+                messages_per_second: sent as f64,
+                seed: 42,
+                client_config: None,
+                transactions: None,
+                delivery: None,
+                errors: BTreeMap::from([("send".to_string(), 1)]),
+            }),
+            receivers: vec![],
+            topic: None,
+            started_at_ms,
+            ended_at_ms: started_at_ms + 1000,
+            throughput: vec![],
+            errors: BTreeMap::from([("send".to_string(), 1)]),
+            error: None,
+        };
+        let failed = PhaseReport::failed(
+            "cooldown",
+            PhaseKind::Cooldown,
+            SystemTime::UNIX_EPOCH,
+            &anyhow::anyhow!("No Kafka brokers"),
+        );
+        let report = RunReport::new(
+            RunBrokers::default(),
+            vec![
+                phase("warmup", 10, 5000),
+                phase("steady, long", 20, 7000),
+                failed,
+            ],
+        );
+        assert_eq!(report.totals.sent, 30);
+        assert_eq!(report.errors["send"], 2);
+        assert_eq!(report.errors["phase"], 1);
+        assert_eq!(report.started_at_ms, 0);
+
+        let csv = report.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[2].starts_with("\"steady, long\",steady,,,20,"));
+        assert!(rows[2].ends_with(",1,"));
+        assert!(rows[3].ends_with(",1,No Kafka brokers"));
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+    }
+}
@@ -0,0 +1,868 @@
+use crate::generator::chernobyl::Chernobyl;
+use crate::generator::{Clock, MetadataLocation};
+use crate::hashes::{HashLedger, MessageHash};
+use crate::kafka_admin::{KafkaAdmin, TopicDescription, TopicSpec};
+use crate::kafka_config::{KafkaClientSettings, KafkaSecurity};
+use crate::latency::LatencyReport;
+use crate::metrics::Metrics;
+use crate::mqtt_config::MqttSecurity;
+use crate::receiver::commit::{CommitReport, CommitStrategy};
+use crate::receiver::connection::{self, ConnectionSummary};
+use crate::receiver::group::ConsumerGroupSummary;
+use crate::receiver::kafka::KafkaReceiver;
+use crate::receiver::mqtt::MQTT3Receiver;
+use crate::receiver::mqtt5::{ExpiryReport, MQTT5Receiver};
+use crate::receiver::sequence::SequenceReport;
+use crate::receiver::Receiver;
+use crate::sender::delivery::DeliveryReport;
+use crate::sender::kafka::{KafkaSender, TransactionReport, Transactions};
+use crate::sender::mqtt::MQTTSender;
+use crate::sender::rate::RateLimit;
+use crate::sender::Sender;
+use crate::throughput::{ThroughputSample, ThroughputSampler};
+use crate::verify::{VerificationReport, Verifier};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_KAFKA_TOPIC: &str = "umh.v1.chernobylnuclearpowerplant";
+pub const DEFAULT_MQTT_TOPIC: &str = "umh/v1/chernobylnuclearpowerplant/#";
+
+/// Time given to the receivers to subscribe before sending.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+/// Interval of the throughput time series.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratorKind {
+    Chernobyl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SenderKind {
+    Kafka,
+    Mqtt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverKind {
+    Kafka,
+    Mqtt,
+    Mqtt5,
+}
+
+impl SenderKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SenderKind::Kafka => "kafka",
+            SenderKind::Mqtt => "mqtt",
+        }
+    }
+}
+
+impl ReceiverKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReceiverKind::Kafka => "kafka",
+            ReceiverKind::Mqtt => "mqtt",
+            ReceiverKind::Mqtt5 => "mqtt5",
+        }
+    }
+
+    pub fn default_topic(&self) -> &'static str {
+        match self {
+            ReceiverKind::Kafka => DEFAULT_KAFKA_TOPIC,
+            ReceiverKind::Mqtt | ReceiverKind::Mqtt5 => DEFAULT_MQTT_TOPIC,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SendOptions {
+    pub sender: SenderKind,
+    pub brokers: Vec<String>,
+    pub generator: GeneratorKind,
+    pub split_point: usize,
+    pub topics: usize,
+    /// Generator seed, a random one is picked and reported if `None`.
+    pub seed: Option<u64>,
+    /// Send as fast as possible if `None`.
+    pub rate: Option<RateLimit>,
+    /// Stamp messages with a producer id and sequence number.
+    pub sequence_numbers: bool,
+    /// Where the send timestamp and sequence stamp are carried, headers need Kafka.
+    pub metadata: MetadataLocation,
+    /// Clock of the payload timestamps, a logical clock needs the metadata in headers.
+    pub clock: Clock,
+    pub kafka: KafkaSendOptions,
+    pub mqtt: MqttSendOptions,
+}
+
+/// Settings only used by the Kafka sender.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaSendOptions {
+    /// Producer threads, each with its own generator and producer.
+    pub threads: usize,
+    pub client: KafkaClientSettings,
+    /// Send transactionally if set.
+    pub transactions: Option<Transactions>,
+}
+
+impl Default for KafkaSendOptions {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            client: KafkaClientSettings::default(),
+            transactions: None,
+        }
+    }
+}
+
+/// Settings only used by the MQTT sender.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttSendOptions {
+    pub qos: u8,
+    pub retain: bool,
+    pub connections: usize,
+    pub security: MqttSecurity,
+}
+
+impl Default for MqttSendOptions {
+    fn default() -> Self {
+        Self {
+            qos: 1,
+            retain: false,
+            connections: 1,
+            security: MqttSecurity::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiveOptions {
+    pub receiver: ReceiverKind,
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// Report latencies per `<topic>.<key>` besides overall.
+    pub latency_per_topic: bool,
+    pub kafka: KafkaReceiveOptions,
+    pub mqtt: MqttReceiveOptions,
+}
+
+/// Settings only used by the Kafka receiver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaReceiveOptions {
+    /// Consumer group, unique per run if not set.
+    pub group_id: Option<String>,
+    /// Consumers in the group, each on its own thread.
+    pub consumers: usize,
+    pub client: KafkaClientSettings,
+    pub commit: CommitStrategy,
+    /// Kill and restart the first consumer this long after the receiver started, the others
+    /// spread over the same time again. Needs a commit strategy other than auto.
+    pub restart_after: Option<Duration>,
+}
+
+impl Default for KafkaReceiveOptions {
+    fn default() -> Self {
+        Self {
+            group_id: None,
+            consumers: 1,
+            client: KafkaClientSettings::default(),
+            commit: CommitStrategy::default(),
+            restart_after: None,
+        }
+    }
+}
+
+/// Settings only used by the MQTT receivers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttReceiveOptions {
+    /// MQTT5 only: subscribe through `$share/<group>/` if set.
+    pub shared_group: Option<String>,
+    /// MQTT5 only: number of client connections.
+    pub connections: usize,
+    /// MQTT3 only: fixed client id, unique per run if not set.
+    pub client_id: Option<String>,
+    /// MQTT3 only: `false` keeps the session and subscription across reconnects.
+    pub clean_session: bool,
+    pub security: MqttSecurity,
+}
+
+impl Default for MqttReceiveOptions {
+    fn default() -> Self {
+        Self {
+            shared_group: None,
+            connections: 1,
+            client_id: None,
+            clean_session: true,
+            security: MqttSecurity::default(),
+        }
+    }
+}
+
+/// A single timed run: the receivers are started first, then the sender (if any) sends
+/// for `duration`, after which the receivers get up to `drain` to catch up.
+#[derive(Debug, Clone)]
+pub struct PhaseOptions {
+    pub send: Option<SendOptions>,
+    pub receivers: Vec<ReceiveOptions>,
+    pub duration: Duration,
+    pub drain: Duration,
+    /// Reconcile sent and received hashes in one shared ledger while running, so memory is
+    /// bounded by the messages in flight. Needs a sender and exactly one receiver.
+    pub streaming_verification: bool,
+    /// Kafka topic to create before the phase, describe and clean up after it.
+    pub topic: Option<TopicOptions>,
+    /// Exports the phase's senders and receivers while it runs.
+    pub metrics: Option<Arc<Metrics>>,
+}
+
+/// A Kafka topic managed through the admin API around a phase.
+#[derive(Debug, Clone)]
+pub struct TopicOptions {
+    pub brokers: Vec<String>,
+    pub security: KafkaSecurity,
+    pub spec: TopicSpec,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendResult {
+    pub sent: u64,
+    pub elapsed: Duration,
+    /// Seed the generators were derived from, to reproduce the run.
+    pub seed: u64,
+    pub hashes: Arc<HashLedger>,
+    /// Sequence stamp ids and hashes, empty without sequence numbers or when verifying while
+    /// streaming.
+    pub ids: Vec<(String, MessageHash)>,
+    /// Effective librdkafka settings, `None` for non-Kafka senders.
+    pub client_config: Option<BTreeMap<String, String>>,
+    /// Hashes of messages in aborted transactions, `None` unless sending transactionally.
+    pub aborted: Option<Arc<HashLedger>>,
+    pub transactions: Option<TransactionReport>,
+    /// Hashes of messages the brokers never accepted, `None` if delivery is not reported.
+    pub failed: Option<Arc<HashLedger>>,
+    pub delivery: Option<DeliveryReport>,
+    /// Errors by category, e.g. `send` or `flush`.
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiveResult {
+    pub receiver: ReceiverKind,
+    pub topic: String,
+    pub received: u64,
+    pub elapsed: Duration,
+    pub hashes: Arc<HashLedger>,
+    /// Comparison with the sent messages, `None` if nothing was sent.
+    pub verification: Option<VerificationReport>,
+    pub latency: LatencyReport,
+    pub sequence: SequenceReport,
+    pub connection: ConnectionSummary,
+    /// Kafka only: partition assignment and rebalances.
+    pub consumer_group: Option<ConsumerGroupSummary>,
+    /// Kafka only: offset commits, restarts and redelivered messages.
+    pub commits: Option<CommitReport>,
+    /// MQTT5 only: messages delivered with a message expiry interval.
+    pub expiry: Option<ExpiryReport>,
+    /// Effective librdkafka settings, `None` for non-Kafka receivers.
+    pub client_config: Option<BTreeMap<String, String>>,
+    /// Errors by category, e.g. `poll` or `commit`.
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl ReceiveResult {
+    pub fn received_percent(&self) -> Option<f64> {
+        self.verification
+            .as_ref()
+            .map(VerificationReport::delivered_percent)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseResult {
+    pub send: Option<SendResult>,
+    pub receivers: Vec<ReceiveResult>,
+    /// The managed topic as described at the end of the phase.
+    pub topic: Option<TopicDescription>,
+    pub started_at: SystemTime,
+    pub ended_at: SystemTime,
+    /// Sent and received counts once per second.
+    pub throughput: Vec<ThroughputSample>,
+}
+
+/// Sends messages for `duration` and returns what was sent.
+pub fn send(
+    options: &SendOptions,
+    duration: Duration,
+    topic: Option<TopicOptions>,
+) -> Result<SendResult> {
+    let phase = PhaseOptions {
+        send: Some(options.clone()),
+        receivers: vec![],
+        duration,
+        drain: Duration::ZERO,
+        streaming_verification: false,
+        topic,
+        metrics: None,
+    };
+    run_phase(&phase)?
+        .send
+        .ok_or_else(|| anyhow::anyhow!("Phase did not send"))
+}
+
+/// Receives messages for `duration` and returns what was received.
+pub fn receive(
+    options: &ReceiveOptions,
+    duration: Duration,
+    topic: Option<TopicOptions>,
+) -> Result<ReceiveResult> {
+    let phase = PhaseOptions {
+        send: None,
+        receivers: vec![options.clone()],
+        duration,
+        drain: Duration::ZERO,
+        streaming_verification: false,
+        topic,
+        metrics: None,
+    };
+    run_phase(&phase)?
+        .receivers
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Phase did not receive"))
+}
+
+/// Starts the receiver, sends for `duration`, then waits up to `drain` for the receiver
+/// to see as many messages as were sent and compares both sides.
+pub fn roundtrip(
+    send: &SendOptions,
+    receive: &ReceiveOptions,
+    duration: Duration,
+    drain: Duration,
+    streaming_verification: bool,
+    topic: Option<TopicOptions>,
+) -> Result<PhaseResult> {
+    run_phase(&PhaseOptions {
+        send: Some(send.clone()),
+        receivers: vec![receive.clone()],
+        duration,
+        drain,
+        streaming_verification,
+        topic,
+        metrics: None,
+    })
+}
+
+pub fn run_phase(options: &PhaseOptions) -> Result<PhaseResult> {
+    validate(options)?;
+    let started_at = SystemTime::now();
+    // Declared before the receivers, so the topic is only deleted once their threads are joined
+    let managed_topic = match &options.topic {
+        Some(topic) => {
+            let admin = KafkaAdmin::new(&topic.brokers, &topic.security)?;
+            admin.create_topic(&topic.spec)?;
+            Some(ManagedTopic {
+                admin,
+                spec: &topic.spec,
+            })
+        }
+        None => None,
+    };
+
+    let mut receivers = RunningReceivers(
+        options
+            .receivers
+            .iter()
+            .map(new_receiver)
+            .collect::<Result<Vec<_>>>()?,
+    );
+
+    let shared_ledger = if options.streaming_verification {
+        let ledger = Arc::new(HashLedger::new());
+        for receiver in receivers.iter_mut() {
+            receiver.set_hash_ledger(ledger.clone());
+        }
+        Some(ledger)
+    } else {
+        None
+    };
+
+    if let Some(metrics) = &options.metrics {
+        metrics.clear();
+        for (receiver, receive) in receivers.iter().zip(options.receivers.iter()) {
+            metrics.register_receiver(
+                receive.receiver.name(),
+                &receive.topic,
+                receiver.get_probe(),
+            );
+        }
+    }
+    let sampler = ThroughputSampler::start(
+        SAMPLE_INTERVAL,
+        receivers.iter().map(|r| r.get_received_counter()).collect(),
+    );
+    let recv_start = Instant::now();
+    for receiver in receivers.iter_mut() {
+        receiver.begin()?;
+    }
+
+    let send_result = match &options.send {
+        Some(send) => {
+            if !receivers.is_empty() {
+                // Give the receivers time to connect and subscribe
+                thread::sleep(SETTLE_TIME);
+            }
+            Some(run_sender(
+                send,
+                options.duration,
+                shared_ledger.clone(),
+                &sampler,
+                options.metrics.as_deref(),
+            )?)
+        }
+        None => {
+            thread::sleep(options.duration);
+            None
+        }
+    };
+
+    if let Some(send_result) = &send_result {
+        let wait_start = Instant::now();
+        while wait_start.elapsed() < options.drain {
+            if receivers
+                .iter()
+                .all(|r| r.get_received_messages() >= send_result.sent)
+            {
+                println!("Received all messages");
+                break;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+    let recv_elapsed = recv_start.elapsed();
+    for receiver in receivers.iter_mut() {
+        receiver.end();
+    }
+    let throughput = sampler.stop();
+
+    let receive_results = receivers
+        .iter()
+        .zip(options.receivers.iter())
+        .map(|(receiver, receive)| {
+            let received = receiver.get_received_messages();
+            println!(
+                "Received {} ({}/s) messages in {:?} from {}",
+                received,
+                received as f64 / recv_elapsed.as_secs_f64(),
+                recv_elapsed,
+                receive.topic
+            );
+            let hashes = receiver.get_received_messages_hashes();
+            let latency = receiver.get_latency();
+            let sequence = receiver.get_sequence_report();
+            println!(
+                "Latency p50 {}us, p90 {}us, p99 {}us, p99.9 {}us, max {}us over {} messages",
+                latency.overall.p50_us,
+                latency.overall.p90_us,
+                latency.overall.p99_us,
+                latency.overall.p999_us,
+                latency.overall.max_us,
+                latency.overall.count
+            );
+            if sequence.overall.messages > 0 {
+                println!(
+                    "Sequence gaps {}, reordered {} (max distance {}), duplicates {}",
+                    sequence.overall.gaps,
+                    sequence.overall.reordered,
+                    sequence.overall.max_reorder_distance,
+                    sequence.overall.duplicates
+                );
+            }
+            let connection = connection::summarize(&receiver.get_connection_events());
+            if connection.disconnects > 0 {
+                println!(
+                    "Disconnected {} times, reconnected {} times, down for {}ms (longest {}ms)",
+                    connection.disconnects,
+                    connection.reconnects,
+                    connection.downtime_ms,
+                    connection.longest_outage_ms
+                );
+            }
+            let consumer_group = receiver.get_consumer_group();
+            if let Some(group) = &consumer_group {
+                println!(
+                    "Consumer group {} with {} consumers: {} assignments, {} revocations, {} errors",
+                    group.group_id,
+                    group.consumers,
+                    group.assignments,
+                    group.revocations,
+                    group.errors
+                );
+            }
+            let commits = receiver.get_commit_report();
+            if let Some(commits) = &commits {
+                println!(
+                    "Committed offsets {} times ({} errors), {} restarts, {} messages redelivered",
+                    commits.commits, commits.commit_errors, commits.restarts, commits.redelivered
+                );
+            }
+            let expiry = receiver.get_expiry_report();
+            if let Some(expiry) = expiry.as_ref().filter(|expiry| expiry.with_expiry > 0) {
+                println!(
+                    "{} messages had an expiry interval, lowest remaining {}s",
+                    expiry.with_expiry,
+                    expiry.min_remaining_s.unwrap_or_default()
+                );
+            }
+            let verification = send_result.as_ref().map(|send_result| match &shared_ledger {
+                Some(ledger) => VerificationReport::from_reconciled(
+                    ledger,
+                    send_result.aborted.as_deref(),
+                    send_result.failed.as_deref(),
+                    send_result.sent,
+                    received,
+                )
+                .with_corrupted(receiver.get_corrupted_hashes().len() as u64),
+                None => {
+                    let mut verifier = Verifier::new();
+                    verifier.extend_sent(&send_result.hashes);
+                    verifier.extend_received(&hashes);
+                    verifier.extend_sent_ids(&send_result.ids);
+                    verifier.extend_received_ids(receiver.get_received_message_ids());
+                    verifier.extend_corrupted(&receiver.get_corrupted_hashes());
+                    if let Some(aborted) = &send_result.aborted {
+                        verifier.extend_aborted(aborted);
+                    }
+                    if let Some(failed) = &send_result.failed {
+                        verifier.extend_failed(failed);
+                    }
+                    verifier.report()
+                }
+            });
+            let result = ReceiveResult {
+                receiver: receive.receiver,
+                topic: receive.topic.clone(),
+                received,
+                elapsed: recv_elapsed,
+                hashes,
+                verification,
+                latency,
+                sequence,
+                connection,
+                consumer_group,
+                commits,
+                expiry,
+                client_config: receiver.get_client_config(),
+                errors: receiver.get_errors(),
+            };
+            if let Some(verification) = &result.verification {
+                println!(
+                    "Received {}% of messages [{} of {}], {} lost, {} duplicates, {} unexpected, {} corrupted",
+                    verification.delivered_percent(),
+                    verification.delivered,
+                    verification.sent,
+                    verification.lost,
+                    verification.duplicates,
+                    verification.unexpected,
+                    verification.corrupted
+                );
+                if verification.aborted_received > 0 {
+                    println!(
+                        "Received {} messages of aborted transactions",
+                        verification.aborted_received
+                    );
+                }
+                if verification.failed_received > 0 {
+                    println!(
+                        "Received {} messages whose delivery was reported as failed",
+                        verification.failed_received
+                    );
+                }
+            }
+            result
+        })
+        .collect();
+
+    let topic = managed_topic.as_ref().and_then(ManagedTopic::describe);
+
+    Ok(PhaseResult {
+        send: send_result,
+        receivers: receive_results,
+        topic,
+        started_at,
+        ended_at: SystemTime::now(),
+        throughput,
+    })
+}
+
+/// Receivers of a running phase, ended when dropped so an early error doesn't leave them
+/// running. Ending joins their threads, so no consumer is left polling afterwards.
+struct RunningReceivers(Vec<Box<dyn Receiver>>);
+
+impl Deref for RunningReceivers {
+    type Target = Vec<Box<dyn Receiver>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for RunningReceivers {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for RunningReceivers {
+    fn drop(&mut self) {
+        for receiver in self.0.iter_mut() {
+            receiver.end();
+        }
+    }
+}
+
+/// The topic created for a phase, cleaned up according to `after_run` when dropped, whether
+/// the phase succeeded or not.
+struct ManagedTopic<'a> {
+    admin: KafkaAdmin,
+    spec: &'a TopicSpec,
+}
+
+impl ManagedTopic<'_> {
+    fn describe(&self) -> Option<TopicDescription> {
+        match self.admin.describe_topic(&self.spec.name) {
+            Ok(description) => {
+                println!(
+                    "Topic {} has {} partitions, {} under-replicated",
+                    description.name,
+                    description.partitions.len(),
+                    description.under_replicated()
+                );
+                Some(description)
+            }
+            Err(e) => {
+                eprintln!("Failed to describe topic {}: {}", self.spec.name, e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ManagedTopic<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.admin.clean_up(self.spec) {
+            eprintln!("Failed to clean up topic {}: {}", self.spec.name, e);
+        }
+    }
+}
+
+/// Rejects combinations of sender, receivers, clock and metadata that can't work, before a
+/// topic is created or anything connects.
+pub fn validate(options: &PhaseOptions) -> Result<()> {
+    if options.streaming_verification && (options.send.is_none() || options.receivers.len() != 1) {
+        return Err(anyhow::anyhow!(
+            "Streaming verification needs a sender and exactly one receiver"
+        ));
+    }
+    // librdkafka commits when an auto committing consumer is closed, even when it is killed
+    if options.receivers.iter().any(|receive| {
+        receive.receiver == ReceiverKind::Kafka
+            && receive.kafka.commit.auto_commit()
+            && receive.kafka.restart_after.is_some()
+    }) {
+        return Err(anyhow::anyhow!(
+            "Restarting consumers needs a commit strategy other than auto"
+        ));
+    }
+    let Some(send) = &options.send else {
+        return Ok(());
+    };
+    if let Some(transactions) = &send.kafka.transactions {
+        if !(0.0..=1.0).contains(&transactions.abort_ratio) {
+            return Err(anyhow::anyhow!(
+                "The abort ratio must be between 0 and 1, got {}",
+                transactions.abort_ratio
+            ));
+        }
+    }
+    if send.sender == SenderKind::Mqtt && send.metadata == MetadataLocation::Headers {
+        return Err(anyhow::anyhow!(
+            "MQTT senders can't carry metadata in headers"
+        ));
+    }
+    if send.clock == Clock::Logical {
+        // Latencies are measured from the payload timestamps without headers
+        if send.metadata != MetadataLocation::Headers {
+            return Err(anyhow::anyhow!(
+                "A logical clock needs the metadata in headers to measure latencies"
+            ));
+        }
+        // MQTT receivers fall back to the payload timestamps, MQTT senders and bridges don't set
+        // the send timestamp property
+        if let Some(receive) = options
+            .receivers
+            .iter()
+            .find(|receive| receive.receiver != ReceiverKind::Kafka)
+        {
+            return Err(anyhow::anyhow!(
+                "A logical clock can't be used with {} receivers, they measure latencies from the payload",
+                receive.receiver.name()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn run_sender(
+    options: &SendOptions,
+    duration: Duration,
+    ledger: Option<Arc<HashLedger>>,
+    sampler: &ThroughputSampler,
+    metrics: Option<&Metrics>,
+) -> Result<SendResult> {
+    match options.sender {
+        SenderKind::Kafka => drive_sender(
+            KafkaSender::new(options.brokers.clone())?
+                .with_rate_limit(options.rate.clone())
+                .with_sequence_numbers(options.sequence_numbers)
+                .with_metadata_location(options.metadata)
+                .with_clock(options.clock)
+                .with_threads(options.kafka.threads)
+                .with_transactions(options.kafka.transactions.clone())
+                .with_client_settings(options.kafka.client.clone()),
+            options,
+            duration,
+            ledger,
+            sampler,
+            metrics,
+        ),
+        SenderKind::Mqtt => {
+            let qos = rumqttc::qos(options.mqtt.qos)
+                .map_err(|e| anyhow::anyhow!("Invalid QoS {}: {:?}", options.mqtt.qos, e))?;
+            drive_sender(
+                MQTTSender::new(options.brokers.clone())?
+                    .with_rate_limit(options.rate.clone())
+                    .with_sequence_numbers(options.sequence_numbers)
+                    .with_qos(qos)
+                    .with_retain(options.mqtt.retain)
+                    .with_connections(options.mqtt.connections)
+                    .with_security(options.mqtt.security.clone()),
+                options,
+                duration,
+                ledger,
+                sampler,
+                metrics,
+            )
+        }
+    }
+}
+
+fn drive_sender<S: Sender>(
+    mut sender: S,
+    options: &SendOptions,
+    duration: Duration,
+    ledger: Option<Arc<HashLedger>>,
+    sampler: &ThroughputSampler,
+    metrics: Option<&Metrics>,
+) -> Result<SendResult> {
+    if let Some(ledger) = ledger {
+        sender.set_hash_ledger(ledger);
+    }
+    let seed = options.seed.unwrap_or_else(rand::random);
+    println!("Generator seed: {}", seed);
+    let now = Instant::now();
+
+    if let Err(e) = begin_sender(&mut sender, options, seed) {
+        // Stop the threads that were started before the failure
+        sender.end();
+        return Err(e);
+    }
+    sampler.track_sent(sender.get_sent_counter());
+    if let Some(metrics) = metrics {
+        metrics.register_sender(options.sender.name(), sender.get_probe());
+    }
+    thread::sleep(duration);
+    let elapsed = now.elapsed();
+    sender.end();
+
+    let sent = sender.get_sent_messages();
+    println!(
+        "Sent {} ({}/s) messages in {:?}",
+        sent,
+        sent as f64 / elapsed.as_secs_f64(),
+        elapsed
+    );
+    let transactions = sender.get_transaction_report();
+    if let Some(transactions) = &transactions {
+        println!(
+            "Committed {} transactions, aborted {} with {} messages",
+            transactions.committed, transactions.aborted, transactions.aborted_messages
+        );
+    }
+    let delivery = sender.get_delivery_report();
+    if let Some(delivery) = delivery.as_ref().filter(|delivery| delivery.failed > 0) {
+        println!(
+            "Failed to deliver {} messages: {:?}",
+            delivery.failed, delivery.failed_by_category
+        );
+    }
+
+    Ok(SendResult {
+        sent,
+        elapsed,
+        seed,
+        hashes: sender.get_sent_message_hashes(),
+        ids: sender.get_sent_message_ids(),
+        client_config: sender.get_client_config(),
+        aborted: sender.get_aborted_message_hashes(),
+        transactions,
+        failed: sender.get_failed_message_hashes(),
+        delivery,
+        errors: sender.get_errors(),
+    })
+}
+
+fn begin_sender<S: Sender>(sender: &mut S, options: &SendOptions, seed: u64) -> Result<()> {
+    match options.generator {
+        GeneratorKind::Chernobyl => {
+            sender.begin::<Chernobyl>(options.split_point, options.topics, seed)
+        }
+    }
+}
+
+fn new_receiver(options: &ReceiveOptions) -> Result<Box<dyn Receiver>> {
+    Ok(match options.receiver {
+        ReceiverKind::Kafka => Box::new(
+            KafkaReceiver::new(options.brokers.clone(), options.topic.clone())?
+                .with_group_id(options.kafka.group_id.clone())
+                .with_consumers(options.kafka.consumers)
+                .with_commit_strategy(options.kafka.commit)
+                .with_restart_after(options.kafka.restart_after)
+                .with_client_settings(options.kafka.client.clone())
+                .with_latency_per_topic(options.latency_per_topic),
+        ),
+        ReceiverKind::Mqtt => Box::new(
+            MQTT3Receiver::new(options.brokers.clone(), options.topic.clone())?
+                .with_client_id(options.mqtt.client_id.clone())
+                .with_clean_session(options.mqtt.clean_session)
+                .with_security(options.mqtt.security.clone())
+                .with_latency_per_topic(options.latency_per_topic),
+        ),
+        ReceiverKind::Mqtt5 => Box::new(
+            MQTT5Receiver::new(options.brokers.clone(), options.topic.clone())?
+                .with_shared_group(options.mqtt.shared_group.clone())
+                .with_connections(options.mqtt.connections)
+                .with_security(options.mqtt.security.clone())
+                .with_latency_per_topic(options.latency_per_topic),
+        ),
+    })
+}
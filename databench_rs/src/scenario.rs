@@ -0,0 +1,668 @@
+use crate::generator::{Clock, MetadataLocation};
+use crate::helper::kafka_brokers_from_env;
+use crate::kafka_admin::TopicSpec;
+use crate::kafka_config::{serialize_redacted, KafkaClientSettings, KafkaPreset, KafkaSecurity};
+use crate::metrics::Metrics;
+use crate::mqtt_config::MqttSecurity;
+use crate::receiver::commit::CommitStrategy;
+use crate::report::{PhaseReport, RunBrokers};
+use crate::runner::{
+    self, GeneratorKind, KafkaReceiveOptions, KafkaSendOptions, MqttReceiveOptions,
+    MqttSendOptions, PhaseOptions, ReceiveOptions, ReceiverKind, SendOptions, SenderKind,
+    TopicOptions,
+};
+use crate::sender::kafka::Transactions;
+use crate::sender::rate::RateLimit;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// A benchmark definition made of phases that are run one after another.
+///
+/// Scenarios are read from TOML or YAML, see `scenarios/` for examples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Falls back to `KAFKA_BROKER_URL_N` and `MQTT_BROKER_URL` if not set.
+    #[serde(default)]
+    pub brokers: Brokers,
+    pub phases: Vec<Phase>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Brokers {
+    #[serde(default)]
+    pub kafka: Vec<String>,
+    #[serde(default)]
+    pub mqtt: Option<String>,
+    /// TLS and SASL for every Kafka client, secrets given as `env:<VARIABLE>` or `file:<path>`.
+    #[serde(default)]
+    pub kafka_security: KafkaSecurity,
+    /// TLS and username/password for every MQTT client, secrets as for Kafka.
+    #[serde(default)]
+    pub mqtt_security: MqttSecurity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseKind {
+    Warmup,
+    Ramp,
+    Steady,
+    Cooldown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    pub name: String,
+    pub kind: PhaseKind,
+    /// Seconds to send for, or to receive for if there is no sender.
+    pub duration: u64,
+    /// Maximum seconds to wait for the receivers after sending stopped.
+    #[serde(default = "default_drain")]
+    pub drain: u64,
+    /// Reconcile hashes while running instead of keeping all of them, see
+    /// [`PhaseOptions::streaming_verification`].
+    #[serde(default)]
+    pub streaming_verification: bool,
+    /// Kafka topic to (re)create before the phase and clean up after it.
+    #[serde(default)]
+    pub topic: Option<TopicSpec>,
+    #[serde(default)]
+    pub sender: Option<SenderSpec>,
+    #[serde(default)]
+    pub receivers: Vec<ReceiverSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SenderSpec {
+    #[serde(default = "default_sender")]
+    pub kind: SenderKind,
+    #[serde(default = "default_generator")]
+    pub generator: GeneratorKind,
+    #[serde(default = "default_split_point")]
+    pub split_point: usize,
+    #[serde(default = "default_topics")]
+    pub topics: usize,
+    /// Generator seed, random if not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Clock of the payload timestamps, `logical` needs `metadata = "headers"`.
+    #[serde(default)]
+    pub clock: Clock,
+    /// Target rate and load profile, unlimited if not set.
+    #[serde(default)]
+    pub rate: Option<RateLimit>,
+    /// Stamp messages with a producer id and sequence number.
+    #[serde(default)]
+    pub sequence_numbers: bool,
+    /// Kafka only: where the send timestamp and sequence stamp are carried.
+    #[serde(default)]
+    pub metadata: MetadataLocation,
+    /// Kafka only: number of producer threads.
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    /// Kafka only: named set of producer settings.
+    #[serde(default)]
+    pub kafka_preset: Option<KafkaPreset>,
+    /// Kafka only: librdkafka producer settings, applied after the preset.
+    #[serde(default, serialize_with = "serialize_redacted")]
+    pub kafka_config: BTreeMap<String, String>,
+    /// Kafka only: send in transactions of this size, aborting some of them.
+    #[serde(default)]
+    pub transactions: Option<Transactions>,
+    /// MQTT only: QoS 0, 1 or 2.
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    /// MQTT only: publish with the retain flag.
+    #[serde(default)]
+    pub retain: bool,
+    /// MQTT only: number of client connections publishing in parallel.
+    #[serde(default = "default_connections")]
+    pub connections: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReceiverSpec {
+    pub kind: ReceiverKind,
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Report latencies per `<topic>.<key>` besides overall.
+    #[serde(default)]
+    pub latency_per_topic: bool,
+    /// Kafka only: consumer group, unique per run if not set.
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// Kafka only: number of consumers in the group.
+    #[serde(default = "default_consumers")]
+    pub consumers: usize,
+    /// Kafka only: named set of consumer settings.
+    #[serde(default)]
+    pub kafka_preset: Option<KafkaPreset>,
+    /// Kafka only: librdkafka consumer settings, applied after the preset.
+    #[serde(default, serialize_with = "serialize_redacted")]
+    pub kafka_config: BTreeMap<String, String>,
+    /// Kafka only: when offsets are committed, e.g. `{ mode = "sync", every = 1000 }`.
+    #[serde(default)]
+    pub commit: CommitStrategy,
+    /// Kafka only: seconds after which the first consumer is killed and restarted, the others
+    /// follow over the same time again. Needs a commit strategy other than auto.
+    #[serde(default)]
+    pub restart_after: Option<u64>,
+    /// MQTT5 only: shared subscription group.
+    #[serde(default)]
+    pub shared_group: Option<String>,
+    /// MQTT5 only: number of client connections subscribing in parallel.
+    #[serde(default = "default_receiver_connections")]
+    pub connections: usize,
+    /// MQTT3 only: fixed client id, unique per run if not set.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// MQTT3 only: set to `false` for a persistent session.
+    #[serde(default = "default_clean_session")]
+    pub clean_session: bool,
+}
+
+fn default_drain() -> u64 {
+    30
+}
+
+fn default_sender() -> SenderKind {
+    SenderKind::Kafka
+}
+
+fn default_generator() -> GeneratorKind {
+    GeneratorKind::Chernobyl
+}
+
+fn default_split_point() -> usize {
+    3
+}
+
+fn default_topics() -> usize {
+    100
+}
+
+fn default_qos() -> u8 {
+    MqttSendOptions::default().qos
+}
+
+fn default_threads() -> usize {
+    KafkaSendOptions::default().threads
+}
+
+fn default_connections() -> usize {
+    MqttSendOptions::default().connections
+}
+
+fn default_consumers() -> usize {
+    KafkaReceiveOptions::default().consumers
+}
+
+fn default_receiver_connections() -> usize {
+    MqttReceiveOptions::default().connections
+}
+
+fn default_clean_session() -> bool {
+    MqttReceiveOptions::default().clean_session
+}
+
+impl Scenario {
+    /// Loads a scenario, picking the format from the file extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Err(anyhow::anyhow!(
+                "Unknown scenario format for {}, expected .toml, .yaml or .yml",
+                path.display()
+            )),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    fn kafka_brokers(&self) -> Result<Vec<String>> {
+        let brokers = if self.brokers.kafka.is_empty() {
+            kafka_brokers_from_env()
+        } else {
+            self.brokers.kafka.clone()
+        };
+        if brokers.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No Kafka brokers in scenario or KAFKA_BROKER_URL_0"
+            ));
+        }
+        Ok(brokers)
+    }
+
+    fn mqtt_broker(&self) -> Result<String> {
+        self.brokers
+            .mqtt
+            .clone()
+            .or_else(|| std::env::var("MQTT_BROKER_URL").ok())
+            .ok_or_else(|| anyhow::anyhow!("No MQTT broker in scenario or MQTT_BROKER_URL"))
+    }
+
+    /// Brokers the run goes to, after falling back to the environment.
+    pub fn resolved_brokers(&self) -> RunBrokers {
+        RunBrokers {
+            kafka: self.kafka_brokers().unwrap_or_default(),
+            mqtt: self.mqtt_broker().ok(),
+        }
+    }
+
+    pub fn phase_options(&self, phase: &Phase) -> Result<PhaseOptions> {
+        let send = match &phase.sender {
+            Some(sender) => Some(SendOptions {
+                sender: sender.kind,
+                brokers: match sender.kind {
+                    SenderKind::Kafka => self.kafka_brokers()?,
+                    SenderKind::Mqtt => vec![self.mqtt_broker()?],
+                },
+                generator: sender.generator,
+                split_point: sender.split_point,
+                topics: sender.topics,
+                seed: sender.seed,
+                rate: sender.rate.clone(),
+                sequence_numbers: sender.sequence_numbers,
+                metadata: sender.metadata,
+                clock: sender.clock,
+                kafka: KafkaSendOptions {
+                    threads: sender.threads,
+                    client: KafkaClientSettings {
+                        preset: sender.kafka_preset,
+                        security: self.brokers.kafka_security.clone(),
+                        overrides: sender.kafka_config.clone(),
+                    },
+                    transactions: sender.transactions.clone(),
+                },
+                mqtt: MqttSendOptions {
+                    qos: sender.qos,
+                    retain: sender.retain,
+                    connections: sender.connections,
+                    security: self.brokers.mqtt_security.clone(),
+                },
+            }),
+            None => None,
+        };
+        let receivers = phase
+            .receivers
+            .iter()
+            .map(|receiver| {
+                Ok(ReceiveOptions {
+                    receiver: receiver.kind,
+                    brokers: match receiver.kind {
+                        ReceiverKind::Kafka => self.kafka_brokers()?,
+                        ReceiverKind::Mqtt | ReceiverKind::Mqtt5 => vec![self.mqtt_broker()?],
+                    },
+                    topic: receiver
+                        .topic
+                        .clone()
+                        .unwrap_or_else(|| receiver.kind.default_topic().to_string()),
+                    latency_per_topic: receiver.latency_per_topic,
+                    kafka: KafkaReceiveOptions {
+                        group_id: receiver.group_id.clone(),
+                        consumers: receiver.consumers,
+                        client: KafkaClientSettings {
+                            preset: receiver.kafka_preset,
+                            security: self.brokers.kafka_security.clone(),
+                            overrides: receiver.kafka_config.clone(),
+                        },
+                        commit: receiver.commit,
+                        restart_after: receiver.restart_after.map(Duration::from_secs),
+                    },
+                    mqtt: MqttReceiveOptions {
+                        shared_group: receiver.shared_group.clone(),
+                        connections: receiver.connections,
+                        client_id: receiver.client_id.clone(),
+                        clean_session: receiver.clean_session,
+                        security: self.brokers.mqtt_security.clone(),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PhaseOptions {
+            send,
+            receivers,
+            duration: Duration::from_secs(phase.duration),
+            drain: Duration::from_secs(phase.drain),
+            streaming_verification: phase.streaming_verification,
+            topic: match &phase.topic {
+                Some(spec) => Some(TopicOptions {
+                    brokers: self.kafka_brokers()?,
+                    security: self.brokers.kafka_security.clone(),
+                    spec: spec.clone(),
+                }),
+                None => None,
+            },
+            metrics: None,
+        })
+    }
+
+    /// Runs all phases in order and returns one report per phase. Each phase is exported to
+    /// `metrics` while it runs and handed to `on_phase` as soon as it ends. A failed phase is
+    /// reported with its error and ends the run, as the phases after it build on it.
+    pub fn run(
+        &self,
+        metrics: Option<Arc<Metrics>>,
+        mut on_phase: impl FnMut(&PhaseReport),
+    ) -> Result<Vec<PhaseReport>> {
+        // Resolve and validate all phases up front so a broken phase doesn't fail a run halfway
+        let options = self
+            .phases
+            .iter()
+            .map(|phase| {
+                let options = self.phase_options(phase)?;
+                runner::validate(&options)
+                    .map_err(|e| anyhow::anyhow!("Phase {}: {}", phase.name, e))?;
+                Ok(PhaseOptions {
+                    metrics: metrics.clone(),
+                    ..options
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut reports = Vec::with_capacity(self.phases.len());
+        for (phase, options) in self.phases.iter().zip(options.iter()) {
+            println!("Starting phase {} ({:?})", phase.name, phase.kind);
+            let started_at = SystemTime::now();
+            let report = match runner::run_phase(options) {
+                Ok(result) => PhaseReport::new(&phase.name, phase.kind, &result),
+                Err(e) => {
+                    eprintln!("Phase {} failed: {:#}", phase.name, e);
+                    PhaseReport::failed(&phase.name, phase.kind, started_at, &e)
+                }
+            };
+            on_phase(&report);
+            let failed = report.error.is_some();
+            reports.push(report);
+            if failed {
+                break;
+            }
+        }
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kafka_admin::TopicCleanup;
+    use crate::kafka_config::{KafkaPreset, SaslMechanism};
+    use crate::receiver::commit::CommitStrategy;
+    use crate::runner::{
+        GeneratorKind, ReceiverKind, SenderKind, DEFAULT_KAFKA_TOPIC, DEFAULT_MQTT_TOPIC,
+    };
+    use crate::scenario::{PhaseKind, Scenario};
+    use crate::secret::Secret;
+    use crate::sender::rate::{RateLimit, RateUnit};
+    use std::time::Duration;
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_load_example_scenarios() {
+        let toml = Scenario::from_toml(include_str!("../scenarios/kafka_to_kafka.toml")).unwrap();
+        assert_eq!(toml.phases.len(), 4);
+        assert_eq!(toml.phases[0].kind, PhaseKind::Warmup);
+        let sender = toml.phases[2].sender.as_ref().unwrap();
+        assert_eq!(sender.kind, SenderKind::Kafka);
+        assert_eq!(sender.generator, GeneratorKind::Chernobyl);
+        assert_eq!(sender.split_point, 3);
+        assert_eq!(sender.threads, 4);
+        assert_eq!(
+            sender.rate,
+            Some(RateLimit::constant(5000.0, RateUnit::Messages))
+        );
+        let ramp = toml.phases[1]
+            .sender
+            .as_ref()
+            .unwrap()
+            .rate
+            .as_ref()
+            .unwrap();
+        assert_eq!(ramp.profile.rate_at(Duration::from_secs(65)), 3000.0);
+
+        let yaml = Scenario::from_yaml(include_str!("../scenarios/kafka_to_mqtt.yaml")).unwrap();
+        assert_eq!(yaml.phases[1].receivers.len(), 2);
+        assert_eq!(yaml.phases[1].receivers[1].kind, ReceiverKind::Mqtt);
+
+        let mqtt = Scenario::from_yaml(include_str!("../scenarios/mqtt_to_kafka.yaml")).unwrap();
+        let options = mqtt.phase_options(&mqtt.phases[0]).unwrap();
+        let send = options.send.unwrap();
+        assert_eq!(send.sender, SenderKind::Mqtt);
+        assert_eq!(send.brokers, vec!["10.99.112.33:1883"]);
+        assert_eq!(send.mqtt.connections, 4);
+        assert!(!send.mqtt.retain);
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_phase_options() {
+        let scenario = Scenario::from_yaml(
+            r#"
+brokers:
+  kafka: ["localhost:9092"]
+  mqtt: "localhost:1883"
+  kafka_security:
+    sasl_mechanism: scram-sha-512
+    sasl_username: env:KAFKA_USERNAME
+    sasl_password: file:/var/run/secrets/kafka/password
+  mqtt_security:
+    ca_location: /etc/mqtt/ca.crt
+    username: env:MQTT_USERNAME
+    password: env:MQTT_PASSWORD
+phases:
+  - name: drain
+    kind: cooldown
+    duration: 10
+    receivers:
+      - kind: mqtt
+        clean_session: false
+      - kind: mqtt5
+        shared_group: bench
+        connections: 3
+      - kind: kafka
+        group_id: bench
+        consumers: 6
+        kafka_preset: exactly-once
+        kafka_config:
+          fetch.max.bytes: "52428800"
+          ssl.key.password: hunter2
+        commit:
+          mode: sync
+          every: 500
+        restart_after: 5
+"#,
+        )
+        .unwrap();
+        let options = scenario.phase_options(&scenario.phases[0]).unwrap();
+        assert!(options.send.is_none());
+        assert_eq!(options.duration, Duration::from_secs(10));
+        assert_eq!(options.drain, Duration::from_secs(30));
+        assert_eq!(options.receivers[0].brokers, vec!["localhost:1883"]);
+        assert_eq!(options.receivers[0].topic, DEFAULT_MQTT_TOPIC);
+        assert!(!options.receivers[0].mqtt.clean_session);
+        assert!(options.receivers[0].mqtt.client_id.is_none());
+        assert!(options.receivers[0].mqtt.security.uses_tls());
+        assert_eq!(
+            options.receivers[1].mqtt.security.username,
+            Some(Secret::Env("MQTT_USERNAME".to_string()))
+        );
+        assert_eq!(options.receivers[1].topic, DEFAULT_MQTT_TOPIC);
+        assert!(options.receivers[1].mqtt.clean_session);
+        assert_eq!(
+            options.receivers[1].mqtt.shared_group.as_deref(),
+            Some("bench")
+        );
+        assert_eq!(options.receivers[1].mqtt.connections, 3);
+        assert_eq!(options.receivers[2].brokers, vec!["localhost:9092"]);
+        assert_eq!(
+            options.receivers[2].kafka.group_id.as_deref(),
+            Some("bench")
+        );
+        assert_eq!(options.receivers[2].kafka.consumers, 6);
+        assert_eq!(
+            options.receivers[2].kafka.commit,
+            CommitStrategy::Sync { every: 500 }
+        );
+        assert_eq!(
+            options.receivers[2].kafka.restart_after,
+            Some(Duration::from_secs(5))
+        );
+        let client = &options.receivers[2].kafka.client;
+        assert_eq!(client.preset, Some(KafkaPreset::ExactlyOnce));
+        assert_eq!(client.overrides["fetch.max.bytes"], "52428800");
+        assert_eq!(client.overrides["ssl.key.password"], "hunter2");
+        // The report only gets the scenario with its secret settings redacted
+        let reported = serde_json::to_value(&scenario).unwrap();
+        let reported = &reported["phases"][0]["receivers"][2]["kafka_config"];
+        assert_eq!(reported["ssl.key.password"], "[redacted]");
+        assert_eq!(reported["fetch.max.bytes"], "52428800");
+        assert_eq!(
+            client.security.sasl_mechanism,
+            Some(SaslMechanism::ScramSha512)
+        );
+        assert_eq!(
+            client.security.sasl_password,
+            Some(Secret::File("/var/run/secrets/kafka/password".into()))
+        );
+
+        let transactional = Scenario::from_toml(
+            r#"
+brokers = { kafka = ["localhost:9092"] }
+
+[[phases]]
+name = "eos"
+kind = "steady"
+duration = 60
+topic = { partitions = 6, replication_factor = 3, min_insync_replicas = 2, after_run = "delete" }
+sender = { kind = "kafka", transactions = { size = 100, abort_ratio = 0.1 } }
+receivers = [{ kind = "kafka" }]
+"#,
+        )
+        .unwrap();
+        let options = transactional
+            .phase_options(&transactional.phases[0])
+            .unwrap();
+        let topic = options.topic.unwrap();
+        assert_eq!(topic.brokers, vec!["localhost:9092"]);
+        assert_eq!(topic.spec.name, DEFAULT_KAFKA_TOPIC);
+        assert_eq!(topic.spec.partitions, 6);
+        assert_eq!(topic.spec.topic_config()["min.insync.replicas"], "2");
+        assert_eq!(topic.spec.after_run, TopicCleanup::Delete);
+        let transactions = options.send.unwrap().kafka.transactions.unwrap();
+        assert_eq!(transactions.size, 100);
+        assert_eq!(transactions.abort_ratio, 0.1);
+
+        assert!(
+            Scenario::from_toml("phases = [{ name = \"x\", kind = \"soak\", duration = 1 }]")
+                .is_err()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_failed_phase_ends_run() {
+        let scenario = Scenario::from_toml(
+            r#"
+brokers = { kafka = ["127.0.0.1:1"], mqtt = "localhost" }
+
+[[phases]]
+name = "broken"
+kind = "warmup"
+duration = 1
+sender = { kind = "mqtt" }
+
+[[phases]]
+name = "never"
+kind = "steady"
+duration = 1
+receivers = [{ kind = "kafka" }]
+"#,
+        )
+        .unwrap();
+        let mut emitted = Vec::new();
+        let reports = scenario
+            .run(None, |report| emitted.push(report.name.clone()))
+            .unwrap();
+        assert_eq!(emitted, vec!["broken"]);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("<address>:<port>"));
+        assert_eq!(reports[0].errors["phase"], 1);
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_invalid_phase_fails_before_run() {
+        let scenario = Scenario::from_toml(
+            r#"
+brokers = { kafka = ["127.0.0.1:1"], mqtt = "127.0.0.1:1" }
+
+[[phases]]
+name = "fine"
+kind = "warmup"
+duration = 1
+receivers = [{ kind = "kafka" }]
+
+[[phases]]
+name = "logical"
+kind = "steady"
+duration = 1
+sender = { kind = "kafka", clock = "logical", metadata = "headers" }
+receivers = [{ kind = "mqtt" }]
+"#,
+        )
+        .unwrap();
+        let mut emitted = Vec::new();
+        let error = scenario
+            .run(None, |report| emitted.push(report.name.clone()))
+            .unwrap_err();
+        assert!(emitted.is_empty());
+        assert!(error
+            .to_string()
+            .starts_with("Phase logical: A logical clock"));
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_abort_ratio_is_validated() {
+        let scenario = Scenario::from_toml(
+            r#"
+brokers = { kafka = ["127.0.0.1:1"], mqtt = "127.0.0.1:1" }
+
+[[phases]]
+name = "aborts"
+kind = "steady"
+duration = 1
+sender = { kind = "kafka", transactions = { size = 10, abort_ratio = 1.5 } }
+receivers = [{ kind = "kafka" }]
+"#,
+        )
+        .unwrap();
+        let error = scenario.run(None, |_| {}).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Phase aborts: The abort ratio must be between 0 and 1"));
+    }
+}
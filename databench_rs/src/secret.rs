@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Reference to a credential that is read when a client connects, so it never has to appear
+/// on the command line or in a scenario file. Written as `env:<VARIABLE>` or `file:<path>`,
+/// e.g. a Kubernetes secret mounted into the pod. Serialized as the reference, never the value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Secret {
+    Env(String),
+    File(PathBuf),
+}
+
+impl Secret {
+    pub fn resolve(&self) -> anyhow::Result<String> {
+        match self {
+            Secret::Env(name) => std::env::var(name)
+                .map_err(|e| anyhow::anyhow!("Failed to read secret from ${}: {}", name, e)),
+            Secret::File(path) => std::fs::read_to_string(path)
+                // Mounted secrets often end with a newline
+                .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|e| {
+                    anyhow::anyhow!("Failed to read secret from {}: {}", path.display(), e)
+                }),
+        }
+    }
+}
+
+impl FromStr for Secret {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("env", name)) if !name.is_empty() => Ok(Secret::Env(name.to_string())),
+            Some(("file", path)) if !path.is_empty() => Ok(Secret::File(PathBuf::from(path))),
+            _ => Err(anyhow::anyhow!(
+                "Secrets must be given as env:<VARIABLE> or file:<path>"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Env(name) => write!(f, "env:{}", name),
+            Secret::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+impl From<Secret> for String {
+    fn from(secret: Secret) -> Self {
+        secret.to_string()
+    }
+}
+
+impl TryFrom<String> for Secret {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secret::Secret;
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_resolve_secret() {
+        let path = std::env::temp_dir().join(format!("databench-secret-{}", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+        let secret: Secret = format!("file:{}", path.display()).parse().unwrap();
+        assert_eq!(secret.resolve().unwrap(), "hunter2");
+        std::fs::remove_file(&path).unwrap();
+        assert!(secret.resolve().is_err());
+
+        let secret: Secret = "env:DATABENCH_TEST_SECRET_UNSET".parse().unwrap();
+        assert!(secret.resolve().is_err());
+        assert!("hunter2".parse::<Secret>().is_err());
+        assert!("env:".parse::<Secret>().is_err());
+        assert_eq!(secret.to_string(), "env:DATABENCH_TEST_SECRET_UNSET");
+    }
+}
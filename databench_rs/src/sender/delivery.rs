@@ -0,0 +1,153 @@
+use crate::errors::ErrorCounter;
+use crate::hashes::{HashLedger, MessageHash, Side};
+use rdkafka::error::KafkaError;
+use rdkafka::message::DeliveryResult;
+use rdkafka::producer::ProducerContext;
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::ClientContext;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Handed to librdkafka with every message and back to the context with its delivery report.
+#[derive(Debug)]
+pub struct Delivery {
+    pub hash: MessageHash,
+    /// Sequence stamp id, if the message carries one.
+    pub id: Option<String>,
+}
+
+/// Messages the brokers acknowledged and those they never accepted, by reason.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeliveryReport {
+    pub delivered: u64,
+    pub failed: u64,
+    pub failed_by_category: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+struct Delivered {
+    hashes: Vec<MessageHash>,
+    ids: Vec<(String, MessageHash)>,
+}
+
+/// Producer context that only counts a message as sent once its delivery report succeeds.
+/// Failed deliveries go to a ledger of their own, so a produce failure is not mistaken for a
+/// message lost between Kafka and the receiver.
+///
+/// Delivery reports are served by `poll` and `flush` on the sending thread, so the delivered
+/// buffer is never contended.
+pub struct DeliveryContext {
+    /// Whether delivery decides what counts as sent. A transactional producer leaves that to
+    /// the outcome of the transaction.
+    track: bool,
+    delivered: Mutex<Delivered>,
+    sent_messages_counter: Arc<AtomicU64>,
+    failed_hashes: Arc<HashLedger>,
+    failures: Arc<ErrorCounter>,
+}
+
+impl DeliveryContext {
+    pub fn new(
+        track: bool,
+        sent_messages_counter: Arc<AtomicU64>,
+        failed_hashes: Arc<HashLedger>,
+        failures: Arc<ErrorCounter>,
+    ) -> Self {
+        Self {
+            track,
+            delivered: Mutex::new(Delivered::default()),
+            sent_messages_counter,
+            failed_hashes,
+            failures,
+        }
+    }
+
+    /// Moves the hashes and ids delivered since the last call into the given buffers.
+    pub fn take_delivered(
+        &self,
+        hashes: &mut Vec<MessageHash>,
+        ids: &mut Vec<(String, MessageHash)>,
+    ) {
+        #[allow(clippy::expect_used)]
+        let mut delivered = self.delivered.lock().expect("Failed to get delivery lock");
+        hashes.append(&mut delivered.hashes);
+        ids.append(&mut delivered.ids);
+    }
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = Box<Delivery>;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, delivery: Self::DeliveryOpaque) {
+        if !self.track {
+            return;
+        }
+        match delivery_result {
+            Ok(_) => {
+                self.sent_messages_counter.fetch_add(1, Ordering::Relaxed);
+                #[allow(clippy::expect_used)]
+                let mut delivered = self.delivered.lock().expect("Failed to get delivery lock");
+                delivered.hashes.push(delivery.hash);
+                if let Some(id) = delivery.id {
+                    delivered.ids.push((id, delivery.hash));
+                }
+            }
+            Err((error, _)) => {
+                self.failures.add(failure_category(error));
+                self.failed_hashes
+                    .record(&mut vec![delivery.hash], Side::Sent);
+            }
+        }
+    }
+}
+
+/// Groups delivery errors by what they say about the run.
+pub fn failure_category(error: &KafkaError) -> &'static str {
+    let code = match error {
+        KafkaError::MessageProduction(code) => *code,
+        _ => return "other",
+    };
+    match code {
+        RDKafkaErrorCode::MessageTimedOut => "timed_out",
+        RDKafkaErrorCode::MessageSizeTooLarge | RDKafkaErrorCode::InvalidMessageSize => "too_large",
+        RDKafkaErrorCode::PurgeQueue | RDKafkaErrorCode::PurgeInflight => "purged",
+        RDKafkaErrorCode::NotEnoughReplicas | RDKafkaErrorCode::NotEnoughReplicasAfterAppend => {
+            "not_enough_replicas"
+        }
+        RDKafkaErrorCode::UnknownTopic
+        | RDKafkaErrorCode::UnknownPartition
+        | RDKafkaErrorCode::UnknownTopicOrPartition => "unknown_topic",
+        RDKafkaErrorCode::TopicAuthorizationFailed
+        | RDKafkaErrorCode::ClusterAuthorizationFailed => "not_authorized",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sender::delivery::failure_category;
+    use rdkafka::error::KafkaError;
+    use rdkafka::types::RDKafkaErrorCode;
+
+    #[test]
+    fn test_failure_category() {
+        let production = |code| KafkaError::MessageProduction(code);
+        assert_eq!(
+            failure_category(&production(RDKafkaErrorCode::MessageTimedOut)),
+            "timed_out"
+        );
+        assert_eq!(
+            failure_category(&production(RDKafkaErrorCode::NotEnoughReplicasAfterAppend)),
+            "not_enough_replicas"
+        );
+        assert_eq!(
+            failure_category(&production(RDKafkaErrorCode::BrokerNotAvailable)),
+            "other"
+        );
+        assert_eq!(failure_category(&KafkaError::Canceled), "other");
+    }
+}
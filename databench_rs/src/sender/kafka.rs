@@ -1,23 +1,143 @@
 use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use rdkafka::error::KafkaError;
-use rdkafka::error::RDKafkaErrorCode::OperationTimedOut;
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
 use rdkafka::types::RDKafkaErrorCode::QueueFull;
-use rdkafka::ClientConfig;
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
-use std::thread;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
 
-use crate::generator::Generator;
+use crate::errors::ErrorCounter;
+use crate::generator::sequence::Sequencer;
+use crate::generator::{stream_seed, Clock, Generator, MetadataLocation, SEND_TIMESTAMP_HEADER};
+use crate::hashes::{hash_message, HashLedger, MessageHash, Side};
+use crate::kafka_config::{client_config, redact, KafkaClientSettings};
+use crate::metrics::{Probe, Reading};
+use crate::sender::delivery::{Delivery, DeliveryContext, DeliveryReport};
+use crate::sender::rate::{RateLimit, TokenBucket};
 use crate::sender::Sender;
 use rdkafka::util::Timeout;
+use serde::{Deserialize, Serialize};
+
+/// Time allowed to initialize, commit or abort a transaction.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends in transactions of `size` messages and aborts a random `abort_ratio` of them, drawn from
+/// the generator seed so a seeded run aborts the same transactions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transactions {
+    pub size: usize,
+    #[serde(default)]
+    pub abort_ratio: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TransactionReport {
+    pub committed: u64,
+    pub aborted: u64,
+    /// Messages sent in aborted transactions, not included in the sent count.
+    pub aborted_messages: u64,
+}
+
+#[derive(Debug, Default)]
+struct TransactionCounters {
+    committed: AtomicU64,
+    aborted: AtomicU64,
+    aborted_messages: AtomicU64,
+    /// Transactions that failed to begin, commit or abort.
+    failures: AtomicU64,
+}
+
+/// Messages of the transaction that is currently open.
+#[derive(Default)]
+struct OpenTransaction {
+    hashes: Vec<MessageHash>,
+    ids: Vec<(String, MessageHash)>,
+    messages: u64,
+}
 
 pub struct KafkaSender {
     sending: Arc<AtomicBool>,
     brokers: Vec<String>,
-    hashes: Arc<RwLock<VecDeque<String>>>,
+    hashes: Arc<HashLedger>,
     send_message_cnt: Arc<AtomicU64>,
+    rate_limit: Option<RateLimit>,
+    sequence_numbers: bool,
+    metadata: MetadataLocation,
+    clock: Clock,
+    ids: Arc<Mutex<Vec<(String, MessageHash)>>>,
+    /// Only batch verification matches ids, streaming verification leaves them out.
+    collect_ids: bool,
+    threads: usize,
+    settings: KafkaClientSettings,
+    transactions: Option<Transactions>,
+    transaction_counters: Arc<TransactionCounters>,
+    aborted_hashes: Arc<HashLedger>,
+    failed_hashes: Arc<HashLedger>,
+    delivery_failures: Arc<ErrorCounter>,
+    errors: Arc<ErrorCounter>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl KafkaSender {
+    /// Paces sending to the given rate, shared across all threads, instead of sending as fast
+    /// as the producers accept.
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Preset and librdkafka overrides applied on top of the defaults.
+    pub fn with_client_settings(mut self, settings: KafkaClientSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// The librdkafka settings every producer is created with, including resolved secrets.
+    pub fn client_config(&self) -> Result<BTreeMap<String, String>> {
+        self.settings.producer_config(&[
+            ("bootstrap.servers", &self.brokers.join(",")),
+            ("message.timeout.ms", "50000"),
+            ("batch.num.messages", "10000"),
+        ])
+    }
+
+    /// Number of threads sending in parallel, each with its own generator and producer.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stamps every message with a producer id (one per thread) and a per topic/key sequence
+    /// number.
+    pub fn with_sequence_numbers(mut self, sequence_numbers: bool) -> Self {
+        self.sequence_numbers = sequence_numbers;
+        self
+    }
+
+    /// Sends through a transactional producer. Only messages of committed transactions count
+    /// as sent, those of aborted ones are kept apart so receivers can be checked for them.
+    pub fn with_transactions(mut self, transactions: Option<Transactions>) -> Self {
+        self.transactions = transactions;
+        self
+    }
+
+    /// Carries the sequence stamp and a send timestamp in Kafka headers instead of the payload.
+    pub fn with_metadata_location(mut self, metadata: MetadataLocation) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Clock of the payload timestamps. The send timestamp header always has the wall time.
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
 }
 
 impl Sender for KafkaSender {
@@ -25,161 +145,432 @@ impl Sender for KafkaSender {
         Ok(Self {
             brokers,
             sending: Arc::new(AtomicBool::new(false)),
-            hashes: Arc::new(RwLock::new(VecDeque::new())),
+            hashes: Arc::new(HashLedger::new()),
             send_message_cnt: Arc::new(AtomicU64::new(0)),
+            rate_limit: None,
+            sequence_numbers: false,
+            metadata: MetadataLocation::Payload,
+            clock: Clock::Wall,
+            ids: Arc::new(Mutex::new(Vec::new())),
+            collect_ids: true,
+            threads: 1,
+            settings: KafkaClientSettings::default(),
+            transactions: None,
+            transaction_counters: Arc::new(TransactionCounters::default()),
+            aborted_hashes: Arc::new(HashLedger::new()),
+            failed_hashes: Arc::new(HashLedger::new()),
+            delivery_failures: Arc::new(ErrorCounter::new()),
+            errors: Arc::new(ErrorCounter::new()),
+            handles: Vec::new(),
         })
     }
 
-    fn begin<T: Generator>(&mut self, split_point: usize, topics: usize) -> Result<()> {
+    fn begin<T: Generator>(&mut self, split_point: usize, topics: usize, seed: u64) -> Result<()> {
         // If already sending, return
         if self.sending.swap(true, Ordering::Relaxed) {
             return Ok(());
         }
 
-        let sending = self.sending.clone();
+        let bucket = self
+            .rate_limit
+            .clone()
+            .map(|limit| Arc::new(TokenBucket::new(limit)));
+        let run_id: u32 = rand::random();
+        let config = self.client_config()?;
 
-        // Init kafka producer
-        let producer: BaseProducer = ClientConfig::new()
-            .set("bootstrap.servers", self.brokers.join(","))
-            .set("message.timeout.ms", "50000")
-            .set("batch.num.messages", "10000")
-            .create()?;
+        for thread_id in 0..self.threads {
+            // Init kafka producer
+            let mut thread_config = config.clone();
+            if self.transactions.is_some() {
+                thread_config.insert(
+                    "transactional.id".to_string(),
+                    format!("databench-{:08x}-{}", run_id, thread_id),
+                );
+            }
+            // Without transactions a message counts as sent once its delivery report is in
+            let context = DeliveryContext::new(
+                self.transactions.is_none(),
+                self.send_message_cnt.clone(),
+                self.failed_hashes.clone(),
+                self.delivery_failures.clone(),
+            );
+            let producer: BaseProducer<DeliveryContext> =
+                client_config(&thread_config).create_with_context(context)?;
+            if self.transactions.is_some() {
+                producer.init_transactions(Timeout::After(TRANSACTION_TIMEOUT))?;
+            }
 
-        let hashes = self.hashes.clone();
-        let sent_messages_counter = self.send_message_cnt.clone();
+            let sending = self.sending.clone();
+            let hashes = self.hashes.clone();
+            let ids = self.ids.clone();
+            let collect_ids = self.collect_ids;
+            let metadata = self.metadata;
+            let sent_messages_counter = self.send_message_cnt.clone();
+            let bucket = bucket.clone();
+            let transactions = self.transactions.clone();
+            let transaction_counters = self.transaction_counters.clone();
+            let aborted_hashes = self.aborted_hashes.clone();
+            let errors = self.errors.clone();
+            let mut sequencer = self
+                .sequence_numbers
+                .then(|| Sequencer::new(format!("{:08x}-{}", run_id, thread_id)));
 
-        thread::spawn(move || {
-            #[allow(clippy::expect_used)]
-            let generator = T::new(split_point, topics).expect("Failed to create generator");
-            let mut hasher = blake3::Hasher::new();
-            let mut thread_hashes = VecDeque::new();
-            let now = std::time::Instant::now();
-            while sending.load(Ordering::Relaxed) {
-                let message = generator.get_message();
-                match message {
-                    Ok(msg) => {
-                        hasher.reset();
+            let generator_seed = stream_seed(seed, thread_id as u64);
+            let clock = self.clock;
+            self.handles.push(thread::spawn(move || {
+                #[allow(clippy::expect_used)]
+                let mut generator = T::new(split_point, topics, generator_seed)
+                    .expect("Failed to create generator")
+                    .with_clock(clock);
+                let mut hasher = blake3::Hasher::new();
+                let mut thread_hashes = Vec::new();
+                let mut thread_ids = Vec::new();
+                let now = std::time::Instant::now();
+                let mut last_payload_len = 0;
+                let mut thread_sent = 0;
+                let mut thread_aborted = Vec::new();
+                let mut open_transaction: Option<OpenTransaction> = None;
+                let mut abort_rng = ChaCha8Rng::seed_from_u64(stream_seed(generator_seed, 0));
+                while sending.load(Ordering::Relaxed) {
+                    // Wait before generating, so the embedded send timestamp excludes the wait.
+                    // In bytes mode the previous payload size stands in for the next one.
+                    if let Some(bucket) = &bucket {
+                        let cost = bucket.limit().cost(last_payload_len);
+                        if !bucket.acquire(cost, &sending) {
+                            break;
+                        }
+                    }
 
-                        // Re-assemble original topic by concatenating topic and key with a dot
-                        let topic = format!("{}.{}", msg.topic, msg.key);
+                    let message = generator.get_message();
+                    match message {
+                        Ok(mut msg) => {
+                            if let Some(sequencer) = sequencer.as_mut() {
+                                sequencer.stamp(&mut msg, metadata);
+                            }
+                            last_payload_len = msg.value.len();
 
-                        hasher.update(topic.as_bytes());
-                        hasher.update(&msg.value);
+                            // Re-assemble original topic by concatenating topic and key with a dot
+                            let topic = format!("{}.{}", msg.topic, msg.key);
+                            let hash = hash_message(&mut hasher, &topic, &msg.value);
+                            let id = msg
+                                .sequence
+                                .as_ref()
+                                .filter(|_| collect_ids)
+                                .map(|stamp| stamp.id(&topic));
+                            let delivery = Box::new(Delivery {
+                                hash,
+                                id: id.clone(),
+                            });
 
-                        let raw_hash = hasher.finalize();
-                        let hash = hex::encode(raw_hash.as_bytes());
+                            if transactions.is_some() && open_transaction.is_none() {
+                                if let Err(e) = producer.begin_transaction() {
+                                    eprintln!("Error beginning transaction: {:?}", e);
+                                    transaction_counters
+                                        .failures
+                                        .fetch_add(1, Ordering::Relaxed);
+                                    break;
+                                }
+                                open_transaction = Some(OpenTransaction::default());
+                            }
 
-                        thread_hashes.push_back(hash.clone());
+                            if metadata == MetadataLocation::Headers {
+                                let sent_ns = SystemTime::now()
+                                    .duration_since(SystemTime::UNIX_EPOCH)
+                                    .map(|d| d.as_nanos())
+                                    .unwrap_or_default();
+                                msg.headers.push((
+                                    SEND_TIMESTAMP_HEADER.to_string(),
+                                    sent_ns.to_string().into_bytes(),
+                                ));
+                            }
 
-                        // Send the message
-                        match producer
-                            .send(BaseRecord::to(&msg.topic).payload(&msg.value).key(&msg.key))
-                        {
-                            Ok(_) => {
-                                sent_messages_counter.fetch_add(1, Ordering::Relaxed);
+                            // Send the message
+                            let mut record = BaseRecord::with_opaque_to(&msg.topic, delivery)
+                                .payload(&msg.value)
+                                .key(&msg.key);
+                            if !msg.headers.is_empty() {
+                                record = record.headers(owned_headers(&msg.headers));
                             }
-                            Err(produce_feedback) => {
-                                match produce_feedback.0 {
-                                    KafkaError::MessageProduction(produce_rdkafka_error) => {
-                                        if produce_rdkafka_error == QueueFull {
-                                            flush(&producer, 0);
-                                            continue;
-                                        }
+                            let result = loop {
+                                match producer.send(record) {
+                                    // Wait for deliveries to make room, then retry the same
+                                    // message so its stamp and hash stay valid
+                                    Err((KafkaError::MessageProduction(QueueFull), returned))
+                                        if sending.load(Ordering::Relaxed) =>
+                                    {
+                                        producer.poll(Duration::from_millis(100));
+                                        record = returned;
+                                    }
+                                    result => break result,
+                                }
+                            };
+                            // Serve the delivery reports of earlier messages
+                            producer.poll(Duration::ZERO);
+                            match result {
+                                Ok(_) => match open_transaction.as_mut() {
+                                    // Counted once the transaction is committed
+                                    Some(open) => {
+                                        open.hashes.push(hash);
+                                        open.ids.extend(id.map(|id| (id, hash)));
+                                        open.messages += 1;
+                                    }
+                                    // Counted by the delivery report
+                                    None => thread_sent += 1,
+                                },
+                                Err((error, _)) => {
+                                    // Never enqueued, so the next message takes its number
+                                    if let Some(sequencer) = sequencer.as_mut() {
+                                        sequencer.rewind(&msg);
                                     }
-                                    _ => {
-                                        eprintln!("Error sending message: {:?}", produce_feedback);
-                                        continue;
+                                    // A full queue only gets here when sending stops
+                                    if !matches!(error, KafkaError::MessageProduction(QueueFull)) {
+                                        eprintln!("Error sending message: {:?}", error);
+                                        errors.add("send");
                                     }
+                                    continue;
                                 }
+                            };
+                        }
+                        Err(err) => {
+                            eprintln!("Error generating message: {:?}", err);
+                            errors.add("generate");
+                            continue;
+                        }
+                    }
 
-                                continue;
+                    if let (Some(transactions), Some(open)) = (&transactions, &open_transaction) {
+                        if open.messages >= transactions.size as u64 {
+                            if let Some(open) = open_transaction.take() {
+                                let abort = abort_rng.gen::<f64>() < transactions.abort_ratio;
+                                thread_sent += settle_transaction(
+                                    &producer,
+                                    open,
+                                    abort,
+                                    &transaction_counters,
+                                    &sent_messages_counter,
+                                    (&mut thread_hashes, &mut thread_ids),
+                                    &mut thread_aborted,
+                                );
                             }
-                        };
-                    }
-                    Err(err) => {
-                        eprintln!("Error generating message: {:?}", err);
-                        continue;
+                        }
                     }
-                }
 
-                if sent_messages_counter.load(Ordering::Relaxed) % 10000 == 0 {
-                    match producer.flush(Timeout::After(std::time::Duration::from_millis(1000))) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            eprintln!("Error flushing producer: {:?}", e);
-                        }
-                    };
-                    println!(
-                        "Produced {} ({}/s) messages",
-                        sent_messages_counter.load(Ordering::Relaxed),
-                        sent_messages_counter.load(Ordering::Relaxed) as f64
-                            / now.elapsed().as_secs_f64()
-                    );
+                    if thread_sent >= 10000 {
+                        thread_sent = 0;
+                        match producer.flush(Timeout::After(std::time::Duration::from_millis(1000)))
+                        {
+                            Ok(_) => {}
+                            Err(e) => {
+                                eprintln!("Error flushing producer: {:?}", e);
+                                errors.add("flush");
+                            }
+                        };
+                        producer
+                            .context()
+                            .take_delivered(&mut thread_hashes, &mut thread_ids);
+                        println!(
+                            "Produced {} ({}/s) messages",
+                            sent_messages_counter.load(Ordering::Relaxed),
+                            sent_messages_counter.load(Ordering::Relaxed) as f64
+                                / now.elapsed().as_secs_f64()
+                        );
 
-                    // Drop the lock as soon as possible
-                    let hashlock = hashes.write();
-                    #[allow(clippy::expect_used)]
-                    let mut hashes = hashlock.expect("Failed to get write lock");
-                    for hash in thread_hashes.drain(..) {
-                        hashes.push_back(hash);
+                        hashes.record(&mut thread_hashes, Side::Sent);
+                        aborted_hashes.record(&mut thread_aborted, Side::Sent);
+                        append_ids(&ids, &mut thread_ids);
                     }
-                    thread_hashes.clear();
                 }
-            }
-            match producer.flush(Timeout::After(std::time::Duration::from_millis(10000))) {
-                Ok(_) => {}
-                Err(e) => {
-                    eprintln!("Error flushing producer: {:?}", e)
+                // Commit whatever was sent in the last transaction
+                if let Some(open) = open_transaction.take() {
+                    settle_transaction(
+                        &producer,
+                        open,
+                        false,
+                        &transaction_counters,
+                        &sent_messages_counter,
+                        (&mut thread_hashes, &mut thread_ids),
+                        &mut thread_aborted,
+                    );
                 }
-            };
+                match producer.flush(Timeout::After(std::time::Duration::from_millis(10000))) {
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("Error flushing producer: {:?}", e);
+                        errors.add("flush");
+                    }
+                };
+                producer
+                    .context()
+                    .take_delivered(&mut thread_hashes, &mut thread_ids);
 
-            // Drop the lock as soon as possible
-            {
-                let hashlock = hashes.write();
-                #[allow(clippy::expect_used)]
-                let mut hashes = hashlock.expect("Failed to get write lock");
-                for hash in thread_hashes.drain(..) {
-                    hashes.push_back(hash);
-                }
-                thread_hashes.clear();
-            }
-        });
+                hashes.record(&mut thread_hashes, Side::Sent);
+                aborted_hashes.record(&mut thread_aborted, Side::Sent);
+                append_ids(&ids, &mut thread_ids);
+            }));
+        }
 
         Ok(())
     }
 
     fn end(&mut self) {
         self.sending.store(false, Ordering::Relaxed);
+        // Each thread commits or flushes what is in flight before handing over its hashes
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
     }
 
-    fn get_sent_message_hashes(&self) -> VecDeque<String> {
-        #[allow(clippy::expect_used)]
-        self.hashes.read().expect("Failed to get read lock").clone()
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>) {
+        self.hashes = ledger;
+        self.collect_ids = false;
+    }
+
+    fn get_sent_message_hashes(&self) -> Arc<HashLedger> {
+        self.hashes.clone()
     }
 
     fn get_sent_messages(&self) -> u64 {
         self.send_message_cnt.load(Ordering::Relaxed)
     }
+
+    fn get_sent_counter(&self) -> Arc<AtomicU64> {
+        self.send_message_cnt.clone()
+    }
+
+    fn get_aborted_message_hashes(&self) -> Option<Arc<HashLedger>> {
+        self.transactions
+            .as_ref()
+            .map(|_| self.aborted_hashes.clone())
+    }
+
+    fn get_transaction_report(&self) -> Option<TransactionReport> {
+        self.transactions.as_ref().map(|_| TransactionReport {
+            committed: self.transaction_counters.committed.load(Ordering::Relaxed),
+            aborted: self.transaction_counters.aborted.load(Ordering::Relaxed),
+            aborted_messages: self
+                .transaction_counters
+                .aborted_messages
+                .load(Ordering::Relaxed),
+        })
+    }
+
+    fn get_failed_message_hashes(&self) -> Option<Arc<HashLedger>> {
+        Some(self.failed_hashes.clone())
+    }
+
+    fn get_delivery_report(&self) -> Option<DeliveryReport> {
+        let failed_by_category = self.delivery_failures.counts();
+        Some(DeliveryReport {
+            delivered: self.send_message_cnt.load(Ordering::Relaxed),
+            failed: failed_by_category.values().sum(),
+            failed_by_category,
+        })
+    }
+
+    fn get_probe(&self) -> Probe {
+        let sent = self.send_message_cnt.clone();
+        let errors = self.errors.clone();
+        let delivery_failures = self.delivery_failures.clone();
+        let transaction_counters = self.transaction_counters.clone();
+        Arc::new(move || Reading {
+            messages: sent.load(Ordering::Relaxed),
+            errors: sender_errors(&errors, &delivery_failures, &transaction_counters),
+            ..Default::default()
+        })
+    }
+
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        sender_errors(
+            &self.errors,
+            &self.delivery_failures,
+            &self.transaction_counters,
+        )
+    }
+
+    fn get_client_config(&self) -> Option<BTreeMap<String, String>> {
+        self.client_config().ok().map(|config| redact(&config))
+    }
+
+    fn get_sent_message_ids(&self) -> Vec<(String, MessageHash)> {
+        #[allow(clippy::expect_used)]
+        self.ids.lock().expect("Failed to get id lock").clone()
+    }
 }
 
-fn flush(producer: &BaseProducer, mut depth: i32) {
-    if depth > 10 {
-        depth = 10;
-    }
-    eprintln!("Queue full, waiting");
-    match producer.flush(Timeout::After(std::time::Duration::from_millis(
-        (1000 * (depth + 1)) as u64,
-    ))) {
-        Ok(_) => {}
-        Err(flush_error) => match flush_error {
-            KafkaError::Flush(flush_rd_kafka_error) => {
-                if flush_rd_kafka_error == OperationTimedOut {
-                    flush(producer, depth + 1);
-                }
+fn sender_errors(
+    errors: &ErrorCounter,
+    delivery_failures: &ErrorCounter,
+    transaction_counters: &TransactionCounters,
+) -> BTreeMap<String, u64> {
+    let mut errors = errors.counts();
+    let failed: u64 = delivery_failures.counts().values().sum();
+    if failed > 0 {
+        errors.insert("delivery".to_string(), failed);
+    }
+    let failures = transaction_counters.failures.load(Ordering::Relaxed);
+    if failures > 0 {
+        errors.insert("transaction".to_string(), failures);
+    }
+    errors
+}
+
+/// Commits or aborts `open` and moves its hashes to the committed or aborted side. A failed
+/// commit is aborted. Returns the number of messages that now count as sent.
+fn settle_transaction(
+    producer: &BaseProducer<DeliveryContext>,
+    open: OpenTransaction,
+    abort: bool,
+    counters: &TransactionCounters,
+    sent_messages_counter: &AtomicU64,
+    (committed_hashes, committed_ids): (&mut Vec<MessageHash>, &mut Vec<(String, MessageHash)>),
+    aborted_hashes: &mut Vec<MessageHash>,
+) -> u64 {
+    let timeout = Timeout::After(TRANSACTION_TIMEOUT);
+    if !abort {
+        match producer.commit_transaction(timeout) {
+            Ok(()) => {
+                counters.committed.fetch_add(1, Ordering::Relaxed);
+                sent_messages_counter.fetch_add(open.messages, Ordering::Relaxed);
+                committed_hashes.extend(open.hashes);
+                committed_ids.extend(open.ids);
+                return open.messages;
             }
-            _ => {
-                eprintln!("Error flushing producer: {:?}", flush_error);
+            Err(e) => {
+                eprintln!("Error committing transaction, aborting it: {:?}", e);
+                counters.failures.fetch_add(1, Ordering::Relaxed);
             }
-        },
+        }
     }
+    if let Err(e) = producer.abort_transaction(timeout) {
+        eprintln!("Error aborting transaction: {:?}", e);
+        counters.failures.fetch_add(1, Ordering::Relaxed);
+    }
+    counters.aborted.fetch_add(1, Ordering::Relaxed);
+    counters
+        .aborted_messages
+        .fetch_add(open.messages, Ordering::Relaxed);
+    aborted_hashes.extend(open.hashes);
+    0
+}
+
+fn owned_headers(headers: &[(String, Vec<u8>)]) -> OwnedHeaders {
+    headers.iter().fold(
+        OwnedHeaders::new_with_capacity(headers.len()),
+        |owned, (key, value)| {
+            owned.insert(Header {
+                key,
+                value: Some(value),
+            })
+        },
+    )
+}
+
+fn append_ids(
+    ids: &Mutex<Vec<(String, MessageHash)>>,
+    thread_ids: &mut Vec<(String, MessageHash)>,
+) {
+    #[allow(clippy::expect_used)]
+    ids.lock()
+        .expect("Failed to get id lock")
+        .append(thread_ids);
 }
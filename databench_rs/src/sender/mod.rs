@@ -1,15 +1,62 @@
 use crate::generator::Generator;
+use crate::hashes::{HashLedger, MessageHash};
+use crate::metrics::Probe;
+use crate::sender::delivery::DeliveryReport;
+use crate::sender::kafka::TransactionReport;
 use anyhow::Result;
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 
 pub(crate) trait Sender {
     fn new(brokers: Vec<String>) -> Result<Self>
     where
         Self: Sized;
-    fn begin<T: Generator>(&mut self, split_point: usize, topics: usize) -> Result<()>;
+    /// Starts sending from one generator per thread or connection, each seeded with its own
+    /// stream of `seed`.
+    fn begin<T: Generator>(&mut self, split_point: usize, topics: usize, seed: u64) -> Result<()>;
+    /// Stops sending and returns once every thread has flushed or committed its last
+    /// messages and handed over their hashes.
     fn end(&mut self);
-    fn get_sent_message_hashes(&self) -> VecDeque<String>;
+    /// Records sent hashes into `ledger` instead of the sender's own, e.g. to share it with
+    /// a receiver for streaming verification. Must be called before `begin`. Streaming
+    /// verification only matches hashes, so no sequence stamp ids are kept from then on.
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>);
+    fn get_sent_message_hashes(&self) -> Arc<HashLedger>;
     fn get_sent_messages(&self) -> u64;
+    /// The live counter behind `get_sent_messages`, e.g. to sample throughput.
+    fn get_sent_counter(&self) -> Arc<AtomicU64>;
+    /// Live counts and errors for the metrics endpoint.
+    fn get_probe(&self) -> Probe;
+    /// Hashes of messages sent in aborted transactions, `None` if not sending transactionally.
+    fn get_aborted_message_hashes(&self) -> Option<Arc<HashLedger>> {
+        None
+    }
+    fn get_transaction_report(&self) -> Option<TransactionReport> {
+        None
+    }
+    /// Hashes of messages the brokers never accepted, `None` if delivery is not reported.
+    fn get_failed_message_hashes(&self) -> Option<Arc<HashLedger>> {
+        None
+    }
+    fn get_delivery_report(&self) -> Option<DeliveryReport> {
+        None
+    }
+    /// Errors while sending by category, e.g. `send` or `flush`.
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        BTreeMap::new()
+    }
+    /// Effective librdkafka settings with secrets redacted, `None` for non-Kafka senders.
+    fn get_client_config(&self) -> Option<BTreeMap<String, String>> {
+        None
+    }
+    /// Sequence stamp ids and hashes of the sent messages, empty if not tracked.
+    fn get_sent_message_ids(&self) -> Vec<(String, MessageHash)> {
+        Vec::new()
+    }
 }
 
+pub mod delivery;
 pub mod kafka;
+pub mod mqtt;
+pub mod rate;
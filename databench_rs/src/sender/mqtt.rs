@@ -0,0 +1,287 @@
+use anyhow::Result;
+use rumqttc::{Client, Event, MqttOptions, Outgoing, QoS};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::errors::ErrorCounter;
+use crate::generator::sequence::Sequencer;
+use crate::generator::{stream_seed, Generator, MetadataLocation};
+use crate::hashes::{hash_message, HashLedger, MessageHash, Side};
+use crate::helper::parse_mqtt_broker;
+use crate::metrics::{Probe, Reading};
+use crate::mqtt_config::MqttSecurity;
+use crate::sender::rate::{RateLimit, TokenBucket};
+use crate::sender::Sender;
+
+/// Publishes generator messages to `<topic>/<key>` with `.` mapped to `/`.
+pub struct MQTTSender {
+    broker: String,
+    port: u16,
+    sending: Arc<AtomicBool>,
+    hashes: Arc<HashLedger>,
+    ids: Arc<Mutex<Vec<(String, MessageHash)>>>,
+    /// Only batch verification matches ids, streaming verification leaves them out.
+    collect_ids: bool,
+    send_message_cnt: Arc<AtomicU64>,
+    rate_limit: Option<RateLimit>,
+    qos: QoS,
+    retain: bool,
+    connections: usize,
+    sequence_numbers: bool,
+    security: MqttSecurity,
+    errors: Arc<ErrorCounter>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MQTTSender {
+    /// Paces sending to the given rate, shared across all connections.
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    /// Number of client connections publishing in parallel, each with its own generator.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    /// TLS, client certificate and username/password settings of the broker.
+    pub fn with_security(mut self, security: MqttSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Stamps every message with a producer id (one per connection) and a per topic/key
+    /// sequence number.
+    pub fn with_sequence_numbers(mut self, sequence_numbers: bool) -> Self {
+        self.sequence_numbers = sequence_numbers;
+        self
+    }
+}
+
+impl Sender for MQTTSender {
+    fn new(brokers: Vec<String>) -> Result<Self> {
+        let (broker, port) = parse_mqtt_broker(&brokers)?;
+        Ok(Self {
+            broker,
+            port,
+            sending: Arc::new(AtomicBool::new(false)),
+            hashes: Arc::new(HashLedger::new()),
+            ids: Arc::new(Mutex::new(Vec::new())),
+            collect_ids: true,
+            send_message_cnt: Arc::new(AtomicU64::new(0)),
+            rate_limit: None,
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            connections: 1,
+            sequence_numbers: false,
+            security: MqttSecurity::default(),
+            errors: Arc::new(ErrorCounter::new()),
+            handles: Vec::new(),
+        })
+    }
+
+    fn begin<T: Generator>(&mut self, split_point: usize, topics: usize, seed: u64) -> Result<()> {
+        // If already sending, return
+        if self.sending.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let bucket = self
+            .rate_limit
+            .clone()
+            .map(|limit| Arc::new(TokenBucket::new(limit)));
+        let run_id: u32 = rand::random();
+
+        for connection_id in 0..self.connections {
+            let mut mqttoptions = MqttOptions::new(
+                format!("databench-sender-{:08x}-{}", run_id, connection_id),
+                self.broker.as_str(),
+                self.port,
+            );
+            mqttoptions.set_keep_alive(Duration::from_secs(30));
+            self.security.apply(&mut mqttoptions)?;
+            let (mut mqtt_client, mut connection) = Client::new(mqttoptions, 1000);
+
+            let sending = self.sending.clone();
+            let hashes = self.hashes.clone();
+            let ids = self.ids.clone();
+            let collect_ids = self.collect_ids;
+            let sent_messages_counter = self.send_message_cnt.clone();
+            let bucket = bucket.clone();
+            let (qos, retain) = (self.qos, self.retain);
+            let mut sequencer = self
+                .sequence_numbers
+                .then(|| Sequencer::new(format!("{:08x}-{}", run_id, connection_id)));
+
+            // Drive the connection until our disconnect went out
+            let connection_sending = self.sending.clone();
+            let connection_errors = self.errors.clone();
+            let errors = self.errors.clone();
+            self.handles.push(thread::spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                        Ok(_) => {}
+                        Err(conn_error) => {
+                            eprintln!("Connection error: {:?}", conn_error);
+                            if !connection_sending.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            connection_errors.add("connection");
+                            thread::sleep(Duration::from_millis(1000));
+                        }
+                    }
+                }
+            }));
+
+            let generator_seed = stream_seed(seed, connection_id as u64);
+            self.handles.push(thread::spawn(move || {
+                #[allow(clippy::expect_used)]
+                let mut generator = T::new(split_point, topics, generator_seed)
+                    .expect("Failed to create generator");
+                let mut hasher = blake3::Hasher::new();
+                let mut thread_hashes = Vec::new();
+                let mut thread_ids = Vec::new();
+                let mut last_payload_len = 0;
+                let now = std::time::Instant::now();
+
+                while sending.load(Ordering::Relaxed) {
+                    // Wait before generating, so the embedded send timestamp excludes the wait
+                    if let Some(bucket) = &bucket {
+                        let cost = bucket.limit().cost(last_payload_len);
+                        if !bucket.acquire(cost, &sending) {
+                            break;
+                        }
+                    }
+
+                    let mut msg = match generator.get_message() {
+                        Ok(msg) => msg,
+                        Err(err) => {
+                            eprintln!("Error generating message: {:?}", err);
+                            errors.add("generate");
+                            continue;
+                        }
+                    };
+                    if let Some(sequencer) = sequencer.as_mut() {
+                        sequencer.stamp(&mut msg, MetadataLocation::Payload);
+                    }
+                    last_payload_len = msg.value.len();
+
+                    // Re-assemble original topic by concatenating topic and key with a dot
+                    let topic = format!("{}.{}", msg.topic, msg.key);
+
+                    let hash = hash_message(&mut hasher, &topic, &msg.value);
+                    let id = msg
+                        .sequence
+                        .as_ref()
+                        .filter(|_| collect_ids)
+                        .map(|stamp| stamp.id(&topic));
+
+                    match mqtt_client.publish(topic.replace('.', "/"), qos, retain, msg.value) {
+                        Ok(_) => {
+                            thread_hashes.push(hash);
+                            thread_ids.extend(id.map(|id| (id, hash)));
+                            let sent = sent_messages_counter.fetch_add(1, Ordering::Relaxed) + 1;
+                            if sent.is_multiple_of(10000) {
+                                println!(
+                                    "Produced {} ({}/s) messages",
+                                    sent,
+                                    sent as f64 / now.elapsed().as_secs_f64()
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Error publishing message: {:?}", e);
+                            errors.add("publish");
+                            continue;
+                        }
+                    }
+
+                    if thread_hashes.len() >= 10000 {
+                        hashes.record(&mut thread_hashes, Side::Sent);
+                        append_ids(&ids, &mut thread_ids);
+                    }
+                }
+
+                // Queued after all publishes, so they still go out before the connection closes
+                if let Err(e) = mqtt_client.disconnect() {
+                    eprintln!("Error disconnecting: {:?}", e);
+                }
+
+                hashes.record(&mut thread_hashes, Side::Sent);
+                append_ids(&ids, &mut thread_ids);
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self) {
+        self.sending.store(false, Ordering::Relaxed);
+        // Publishers hand over their hashes, connections wait for the disconnect to go out
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+
+    fn set_hash_ledger(&mut self, ledger: Arc<HashLedger>) {
+        self.hashes = ledger;
+        self.collect_ids = false;
+    }
+
+    fn get_sent_message_hashes(&self) -> Arc<HashLedger> {
+        self.hashes.clone()
+    }
+
+    fn get_probe(&self) -> Probe {
+        let sent = self.send_message_cnt.clone();
+        let errors = self.errors.clone();
+        Arc::new(move || Reading {
+            messages: sent.load(Ordering::Relaxed),
+            errors: errors.counts(),
+            ..Default::default()
+        })
+    }
+
+    fn get_errors(&self) -> BTreeMap<String, u64> {
+        self.errors.counts()
+    }
+
+    fn get_sent_messages(&self) -> u64 {
+        self.send_message_cnt.load(Ordering::Relaxed)
+    }
+
+    fn get_sent_counter(&self) -> Arc<AtomicU64> {
+        self.send_message_cnt.clone()
+    }
+
+    fn get_sent_message_ids(&self) -> Vec<(String, MessageHash)> {
+        #[allow(clippy::expect_used)]
+        self.ids.lock().expect("Failed to get id lock").clone()
+    }
+}
+
+fn append_ids(
+    ids: &Mutex<Vec<(String, MessageHash)>>,
+    thread_ids: &mut Vec<(String, MessageHash)>,
+) {
+    #[allow(clippy::expect_used)]
+    ids.lock()
+        .expect("Failed to get id lock")
+        .append(thread_ids);
+}
@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How much burst the token bucket allows, as a fraction of one second of the current rate.
+const BURST_WINDOW_S: f64 = 0.01;
+/// Upper bound for a single sleep, so rate changes and `end()` are picked up quickly.
+const MAX_SLEEP: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateUnit {
+    /// Messages per second
+    #[default]
+    Messages,
+    /// Payload bytes per second
+    Bytes,
+}
+
+/// Target rate over time. All rates are in [`RateUnit`] per second and all times in seconds
+/// since sending began.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "profile", rename_all = "snake_case")]
+pub enum LoadProfile {
+    Constant {
+        rate: f64,
+    },
+    /// Linear ramp from `from` to `to` over `over` seconds, then constant at `to`.
+    Ramp {
+        from: f64,
+        to: f64,
+        over: f64,
+    },
+    /// Starts at `from` and adds `step` every `every` seconds, optionally capped at `max`.
+    Step {
+        from: f64,
+        step: f64,
+        every: f64,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// `mean + amplitude * sin(2πt / period)`, never below zero.
+    Sine {
+        mean: f64,
+        amplitude: f64,
+        period: f64,
+    },
+    /// `rate` for the first `duty_cycle` (0..=1) of every `period`, `idle` for the rest.
+    Burst {
+        rate: f64,
+        period: f64,
+        duty_cycle: f64,
+        #[serde(default)]
+        idle: f64,
+    },
+}
+
+impl LoadProfile {
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        let t = elapsed.as_secs_f64();
+        let rate = match *self {
+            LoadProfile::Constant { rate } => rate,
+            LoadProfile::Ramp { from, to, over } => {
+                if over <= 0.0 {
+                    to
+                } else {
+                    from + (to - from) * (t / over).min(1.0)
+                }
+            }
+            LoadProfile::Step {
+                from,
+                step,
+                every,
+                max,
+            } => {
+                let steps = if every <= 0.0 {
+                    0.0
+                } else {
+                    (t / every).floor()
+                };
+                let rate = from + step * steps;
+                match max {
+                    Some(max) => rate.min(max),
+                    None => rate,
+                }
+            }
+            LoadProfile::Sine {
+                mean,
+                amplitude,
+                period,
+            } => {
+                if period <= 0.0 {
+                    mean
+                } else {
+                    mean + amplitude * (2.0 * PI * t / period).sin()
+                }
+            }
+            LoadProfile::Burst {
+                rate,
+                period,
+                duty_cycle,
+                idle,
+            } => {
+                if period <= 0.0 || (t % period) / period < duty_cycle {
+                    rate
+                } else {
+                    idle
+                }
+            }
+        };
+        rate.max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    #[serde(default)]
+    pub unit: RateUnit,
+    #[serde(flatten)]
+    pub profile: LoadProfile,
+}
+
+impl RateLimit {
+    pub fn constant(rate: f64, unit: RateUnit) -> Self {
+        Self {
+            unit,
+            profile: LoadProfile::Constant { rate },
+        }
+    }
+
+    /// Cost of a message with the given payload in this limit's unit.
+    pub fn cost(&self, payload_len: usize) -> f64 {
+        match self.unit {
+            RateUnit::Messages => 1.0,
+            RateUnit::Bytes => payload_len as f64,
+        }
+    }
+}
+
+/// Token bucket that refills at the rate its [`LoadProfile`] gives for the current time.
+/// Shared by all sending threads, which only hold its lock to take tokens, not while waiting.
+pub struct TokenBucket {
+    limit: RateLimit,
+    start: Instant,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    last_refill: Instant,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            limit,
+            start: now,
+            state: Mutex::new(BucketState {
+                last_refill: now,
+                tokens: 0.0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Blocks until `cost` tokens are available and takes them.
+    /// Returns `false` without taking tokens if `running` was cleared while waiting.
+    pub fn acquire(&self, cost: f64, running: &AtomicBool) -> bool {
+        loop {
+            if !running.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            let wait = {
+                #[allow(clippy::expect_used)]
+                let mut state = self.state.lock().expect("Failed to get rate limit lock");
+                let now = Instant::now();
+                let rate = self.limit.profile.rate_at(now - self.start);
+                let capacity = (rate * BURST_WINDOW_S).max(cost);
+                state.tokens =
+                    (state.tokens + rate * (now - state.last_refill).as_secs_f64()).min(capacity);
+                state.last_refill = now;
+
+                if state.tokens >= cost {
+                    state.tokens -= cost;
+                    return true;
+                }
+
+                if rate > 0.0 {
+                    Duration::from_secs_f64((cost - state.tokens) / rate).min(MAX_SLEEP)
+                } else {
+                    MAX_SLEEP
+                }
+            };
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sender::rate::{LoadProfile, RateLimit, RateUnit, TokenBucket};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_load_profiles() {
+        let secs = Duration::from_secs_f64;
+
+        let ramp = LoadProfile::Ramp {
+            from: 100.0,
+            to: 200.0,
+            over: 10.0,
+        };
+        assert_eq!(ramp.rate_at(secs(0.0)), 100.0);
+        assert_eq!(ramp.rate_at(secs(5.0)), 150.0);
+        assert_eq!(ramp.rate_at(secs(60.0)), 200.0);
+
+        let step = LoadProfile::Step {
+            from: 10.0,
+            step: 10.0,
+            every: 2.0,
+            max: Some(30.0),
+        };
+        assert_eq!(step.rate_at(secs(1.9)), 10.0);
+        assert_eq!(step.rate_at(secs(2.0)), 20.0);
+        assert_eq!(step.rate_at(secs(100.0)), 30.0);
+
+        let sine = LoadProfile::Sine {
+            mean: 100.0,
+            amplitude: 150.0,
+            period: 4.0,
+        };
+        assert!((sine.rate_at(secs(1.0)) - 250.0).abs() < 1e-9);
+        assert_eq!(sine.rate_at(secs(3.0)), 0.0);
+
+        let burst = LoadProfile::Burst {
+            rate: 1000.0,
+            period: 10.0,
+            duty_cycle: 0.2,
+            idle: 0.0,
+        };
+        assert_eq!(burst.rate_at(secs(1.0)), 1000.0);
+        assert_eq!(burst.rate_at(secs(3.0)), 0.0);
+        assert_eq!(burst.rate_at(secs(11.0)), 1000.0);
+    }
+
+    #[test]
+    fn test_token_bucket_paces() {
+        let running = AtomicBool::new(true);
+        let bucket = TokenBucket::new(RateLimit::constant(5000.0, RateUnit::Messages));
+        let now = Instant::now();
+        for _ in 0..500 {
+            assert!(bucket.acquire(1.0, &running));
+        }
+        let elapsed = now.elapsed();
+        assert!(elapsed >= Duration::from_millis(90), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_token_bucket_stops() {
+        let running = AtomicBool::new(false);
+        let bucket = TokenBucket::new(RateLimit::constant(0.0, RateUnit::Bytes));
+        assert!(!bucket.acquire(100.0, &running));
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_token_bucket_waits_unlocked() {
+        let bucket = Arc::new(TokenBucket::new(RateLimit::constant(
+            1000.0,
+            RateUnit::Messages,
+        )));
+        let running = Arc::new(AtomicBool::new(true));
+        let waiting = {
+            let bucket = bucket.clone();
+            let running = running.clone();
+            thread::spawn(move || bucket.acquire(10_000.0, &running))
+        };
+        thread::sleep(Duration::from_millis(20));
+
+        // A thread waiting for a large cost doesn't keep others from the bucket
+        let start = Instant::now();
+        assert!(bucket.acquire(1.0, &AtomicBool::new(true)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        running.store(false, Ordering::Relaxed);
+        assert!(!waiting.join().unwrap());
+    }
+}
@@ -0,0 +1,361 @@
+/// TLS to Kafka goes through a proxy that terminates TLS in front of librdkafka's mock cluster,
+/// which only listens in plaintext. The proxy's certificate comes from a CA made up by the test.
+///
+/// The mock cluster advertises its plaintext listener, so the proxy points the broker address in
+/// its responses at itself and every connection after the bootstrap stays encrypted too. SASL is
+/// not covered, the mock cluster doesn't authenticate clients.
+#[cfg(test)]
+mod tests {
+    use crate::generator::{Clock, MetadataLocation};
+    use crate::kafka_config::{client_config, KafkaClientSettings, KafkaSecurity};
+    use crate::runner::{
+        self, GeneratorKind, KafkaReceiveOptions, KafkaSendOptions, MqttReceiveOptions,
+        MqttSendOptions, ReceiveOptions, ReceiverKind, SendOptions, SenderKind,
+        DEFAULT_KAFKA_TOPIC,
+    };
+    use crate::sender::rate::{RateLimit, RateUnit};
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::{BigNum, MsbOption};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{SslAcceptor, SslMethod, SslStream};
+    use openssl::x509::extension::{BasicConstraints, KeyUsage, SubjectAlternativeName};
+    use openssl::x509::{X509Name, X509};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::{BaseProducer, DefaultProducerContext, Producer};
+    use std::fs;
+    use std::io::{self, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const TOPIC: &str = "databench_tls";
+
+    /// A certificate and its key.
+    struct Identity {
+        certificate: X509,
+        key: PKey<Private>,
+    }
+
+    #[allow(clippy::expect_used)]
+    fn name(common_name: &str) -> X509Name {
+        let mut name = X509Name::builder().expect("Failed to create name");
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .expect("Failed to set common name");
+        name.build()
+    }
+
+    /// Issues a certificate for `common_name`, signed by `issuer` or self-signed as a CA.
+    #[allow(clippy::expect_used)]
+    fn issue(common_name: &str, issuer: Option<&Identity>) -> Identity {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("Failed to get curve");
+        let key = PKey::from_ec_key(EcKey::generate(&group).expect("Failed to generate key"))
+            .expect("Failed to wrap key");
+        let mut serial = BigNum::new().expect("Failed to create serial");
+        serial
+            .rand(64, MsbOption::MAYBE_ZERO, false)
+            .expect("Failed to generate serial");
+
+        let mut builder = X509::builder().expect("Failed to create certificate");
+        builder.set_version(2).expect("Failed to set version");
+        builder
+            .set_serial_number(&serial.to_asn1_integer().expect("Failed to convert serial"))
+            .expect("Failed to set serial");
+        builder
+            .set_subject_name(&name(common_name))
+            .expect("Failed to set subject");
+        match issuer {
+            Some(ca) => builder.set_issuer_name(ca.certificate.subject_name()),
+            None => builder.set_issuer_name(&name(common_name)),
+        }
+        .expect("Failed to set issuer");
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).expect("Failed to get time"))
+            .expect("Failed to set start");
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).expect("Failed to get time"))
+            .expect("Failed to set end");
+        builder.set_pubkey(&key).expect("Failed to set key");
+
+        match issuer {
+            None => {
+                let constraints = BasicConstraints::new().critical().ca().build();
+                let usage = KeyUsage::new().critical().key_cert_sign().build();
+                builder
+                    .append_extension(constraints.expect("Failed to build constraints"))
+                    .expect("Failed to add constraints");
+                builder
+                    .append_extension(usage.expect("Failed to build key usage"))
+                    .expect("Failed to add key usage");
+            }
+            Some(ca) => {
+                let names = SubjectAlternativeName::new()
+                    .dns("localhost")
+                    .ip("127.0.0.1")
+                    .build(&builder.x509v3_context(Some(&ca.certificate), None));
+                builder
+                    .append_extension(names.expect("Failed to build names"))
+                    .expect("Failed to add names");
+            }
+        }
+        let signing_key = issuer.map_or(&key, |ca| &ca.key);
+        builder
+            .sign(signing_key, MessageDigest::sha256())
+            .expect("Failed to sign certificate");
+
+        Identity {
+            certificate: builder.build(),
+            key,
+        }
+    }
+
+    /// Accepts TLS connections and forwards them in plaintext to `upstream`, a single broker.
+    /// Returns the address it listens on.
+    #[allow(clippy::expect_used)]
+    fn start_tls_proxy(server: &Identity, upstream: String) -> String {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())
+            .expect("Failed to create acceptor");
+        acceptor
+            .set_private_key(&server.key)
+            .expect("Failed to set key");
+        acceptor
+            .set_certificate(&server.certificate)
+            .expect("Failed to set certificate");
+        let acceptor = Arc::new(acceptor.build());
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind proxy");
+        let address = listener.local_addr().expect("Failed to get proxy address");
+        let advertised = Arc::new((
+            broker_address(&upstream).expect("Invalid upstream address"),
+            broker_address(&address.to_string()).expect("Invalid proxy address"),
+        ));
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let acceptor = acceptor.clone();
+                let upstream = upstream.clone();
+                let advertised = advertised.clone();
+                thread::spawn(move || {
+                    // Clients that reject the certificate end up here as a failed handshake
+                    let Ok(tls) = acceptor.accept(client) else {
+                        return;
+                    };
+                    if let Ok(broker) = TcpStream::connect(&upstream) {
+                        let (from, to) = advertised.as_ref();
+                        let _ = forward(tls, broker, from, to);
+                    }
+                });
+            }
+        });
+        address.to_string()
+    }
+
+    /// `host:port` as a broker appears in metadata and coordinator responses: the host
+    /// followed by the port as a big endian int32.
+    fn broker_address(address: &str) -> Option<Vec<u8>> {
+        let (host, port) = address.rsplit_once(':')?;
+        let mut bytes = host.as_bytes().to_vec();
+        bytes.extend_from_slice(&port.parse::<i32>().ok()?.to_be_bytes());
+        Some(bytes)
+    }
+
+    /// Takes the first complete size-prefixed response off `buffered`.
+    fn take_response(buffered: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let size = buffered.get(..4)?;
+        let size = 4 + u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        (buffered.len() >= size).then(|| buffered.drain(..size).collect())
+    }
+
+    /// Replaces every `from` in `response` with `to` of the same length.
+    fn rewrite(mut response: Vec<u8>, from: &[u8], to: &[u8]) -> Vec<u8> {
+        let mut i = 0;
+        while i + from.len() <= response.len() {
+            if response[i..i + from.len()] == *from {
+                response[i..i + from.len()].copy_from_slice(to);
+                i += from.len();
+            } else {
+                i += 1;
+            }
+        }
+        response
+    }
+
+    /// Copies both ways until either side closes, with the broker address `from` in responses
+    /// replaced by the proxy address `to`. A TLS stream can't be split across threads, so both
+    /// sockets get a short read timeout and are served in turns.
+    fn forward(
+        mut tls: SslStream<TcpStream>,
+        mut broker: TcpStream,
+        from: &[u8],
+        to: &[u8],
+    ) -> io::Result<()> {
+        let timeout = Some(Duration::from_millis(5));
+        tls.get_ref().set_read_timeout(timeout)?;
+        broker.set_read_timeout(timeout)?;
+        let mut buffer = [0; 16 * 1024];
+        // Responses are rewritten whole, an address may span two reads
+        let mut responses = Vec::new();
+        let idle = |e: &io::Error| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )
+        };
+        loop {
+            match tls.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => broker.write_all(&buffer[..n])?,
+                Err(e) if idle(&e) => {}
+                Err(e) => return Err(e),
+            }
+            match broker.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    responses.extend_from_slice(&buffer[..n]);
+                    while let Some(response) = take_response(&mut responses) {
+                        tls.write_all(&rewrite(response, from, to))?;
+                    }
+                }
+                Err(e) if idle(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fetches the topic's metadata over TLS, with `ca_location` to verify the proxy.
+    fn fetch_partitions(proxy: &str, ca_location: Option<PathBuf>) -> anyhow::Result<usize> {
+        let settings = KafkaClientSettings {
+            security: KafkaSecurity {
+                ca_location,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = settings.producer_config(&[("bootstrap.servers", proxy)])?;
+        let producer: BaseProducer = client_config(&config).create()?;
+        let metadata = producer
+            .client()
+            .fetch_metadata(Some(TOPIC), Duration::from_secs(10))?;
+        Ok(metadata
+            .topics()
+            .first()
+            .map_or(0, |topic| topic.partitions().len()))
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_kafka_tls_with_self_signed_ca() {
+        let cluster: MockCluster<'static, DefaultProducerContext> =
+            MockCluster::new(1).expect("Failed to start mock cluster");
+        cluster
+            .create_topic(TOPIC, 3, 1)
+            .expect("Failed to create topic");
+
+        let ca = issue("databench test CA", None);
+        let server = issue("localhost", Some(&ca));
+        let directory = std::env::temp_dir().join(format!("databench-tls-{}", process::id()));
+        fs::create_dir_all(&directory).expect("Failed to create certificate directory");
+        let ca_location = directory.join("ca.pem");
+        fs::write(
+            &ca_location,
+            ca.certificate.to_pem().expect("Failed to encode CA"),
+        )
+        .expect("Failed to write CA");
+        let proxy = start_tls_proxy(&server, cluster.bootstrap_servers());
+
+        let partitions = fetch_partitions(&proxy, Some(ca_location));
+        // Without the CA the proxy's certificate can't be verified
+        let untrusted = fetch_partitions(&proxy, None);
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(partitions.expect("Failed to fetch metadata over TLS"), 3);
+        assert!(untrusted.is_err());
+    }
+
+    fn tls_settings(ca_location: &Path) -> KafkaClientSettings {
+        KafkaClientSettings {
+            security: KafkaSecurity {
+                ca_location: Some(ca_location.to_path_buf()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_kafka_tls_roundtrip() {
+        let cluster: MockCluster<'static, DefaultProducerContext> =
+            MockCluster::new(1).expect("Failed to start mock cluster");
+        cluster
+            .create_topic(DEFAULT_KAFKA_TOPIC, 3, 1)
+            .expect("Failed to create topic");
+
+        let ca = issue("databench test CA", None);
+        let server = issue("localhost", Some(&ca));
+        let directory =
+            std::env::temp_dir().join(format!("databench-tls-roundtrip-{}", process::id()));
+        fs::create_dir_all(&directory).expect("Failed to create certificate directory");
+        let ca_location = directory.join("ca.pem");
+        fs::write(
+            &ca_location,
+            ca.certificate.to_pem().expect("Failed to encode CA"),
+        )
+        .expect("Failed to write CA");
+        let proxy = start_tls_proxy(&server, cluster.bootstrap_servers());
+
+        let send = SendOptions {
+            sender: SenderKind::Kafka,
+            brokers: vec![proxy.clone()],
+            generator: GeneratorKind::Chernobyl,
+            split_point: 3,
+            topics: 10,
+            seed: None,
+            rate: Some(RateLimit::constant(200.0, RateUnit::Messages)),
+            sequence_numbers: true,
+            metadata: MetadataLocation::Payload,
+            clock: Clock::Wall,
+            kafka: KafkaSendOptions {
+                client: tls_settings(&ca_location),
+                ..Default::default()
+            },
+            mqtt: MqttSendOptions::default(),
+        };
+        let receive = ReceiveOptions {
+            receiver: ReceiverKind::Kafka,
+            brokers: vec![proxy],
+            topic: DEFAULT_KAFKA_TOPIC.to_string(),
+            latency_per_topic: false,
+            kafka: KafkaReceiveOptions {
+                client: tls_settings(&ca_location),
+                ..Default::default()
+            },
+            mqtt: MqttReceiveOptions::default(),
+        };
+        let result = runner::roundtrip(
+            &send,
+            &receive,
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            false,
+            None,
+        );
+        let _ = fs::remove_dir_all(&directory);
+
+        let result = result.expect("Roundtrip over TLS failed");
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.unexpected, 0);
+        assert_eq!(verification.corrupted, 0);
+    }
+}
@@ -1,19 +1,17 @@
 #[cfg(test)]
 mod tests {
     use crate::generator::chernobyl::Chernobyl;
+    use crate::hashes::HashLedger;
     use crate::receiver::kafka::KafkaReceiver;
     use crate::receiver::Receiver;
     use crate::sender::kafka::KafkaSender;
     use crate::sender::Sender;
-    use crate::tests::Status;
-    use futures::executor;
-    use rdkafka::admin::AdminClient;
-    use rdkafka::client::DefaultClientContext;
-    use rdkafka::ClientConfig;
-    use std::collections::{HashMap, VecDeque};
+    use crate::verify::Verifier;
+    use std::sync::Arc;
     use std::thread;
 
     #[test]
+    #[ignore = "needs the lab cluster at 10.99.112.33-35, see mock_cluster for a hermetic run"]
     #[allow(clippy::expect_used)]
     fn test_kafka_sender_kafka_receiver() {
         let brokers = vec![
@@ -24,23 +22,15 @@ mod tests {
 
         // Remove topic (using admin client)
         /*
-        let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
-            .set("bootstrap.servers", brokers.join(","))
-            .create()
-            .expect("Failed to create admin client");
-
-        println!("Deleting topic");
-        executor::block_on(async {
-            admin_client
-                .delete_topics(&["umh.v1.chernobylnuclearpowerplant"], &Default::default())
-                .await
-                .expect("Failed to delete topic");
-        });
-*/
+        KafkaAdmin::new(&brokers, &KafkaSecurity::default())
+            .expect("Failed to create admin client")
+            .delete_topic("umh.v1.chernobylnuclearpowerplant")
+            .expect("Failed to delete topic");
+        */
         #[allow(unused_assignments)]
-        let mut send_hashes: VecDeque<String> = VecDeque::new();
+        let mut send_hashes = Arc::new(HashLedger::new());
         #[allow(unused_assignments)]
-        let mut recv_hashes: VecDeque<String> = VecDeque::new();
+        let mut recv_hashes = Arc::new(HashLedger::new());
         #[allow(unused_assignments)]
         let mut sent_cnt = 0;
         // Sender
@@ -51,7 +41,7 @@ mod tests {
             let now = std::time::Instant::now();
 
             sender
-                .begin::<Chernobyl>(3, 100)
+                .begin::<Chernobyl>(3, 100, rand::random())
                 .expect("Failed to begin sending");
             thread::sleep(std::time::Duration::from_secs(seconds));
             sender.end();
@@ -97,49 +87,14 @@ mod tests {
             recv_hashes = receiver.get_received_messages_hashes();
         }
 
-        let mut message_map: HashMap<String, Status> = HashMap::new();
-
-        for hash in send_hashes.drain(..) {
-            message_map.insert(hash, Status::Sent);
-        }
-
-        for hash in recv_hashes.drain(..) {
-            match message_map.entry(hash) {
-                std::collections::hash_map::Entry::Occupied(mut entry) => {
-                    entry.insert(Status::SentAndReceived);
-                }
-                std::collections::hash_map::Entry::Vacant(entry) => {
-                    entry.insert(Status::Received);
-                }
-            }
-        }
-
-        let mut not_received = 0;
-        let mut received = 0;
-        for (_hash, status) in message_map.iter() {
-            match status {
-                Status::Sent => {
-                    not_received += 1;
-                }
-                Status::Received => {
-                    // These are from other tests
-                }
-                Status::SentAndReceived => {
-                    received += 1;
-                }
-            }
-        }
-
-        let mut received_percent = 0.0;
-        if received > 0 {
-            received_percent = received as f64 / (received + not_received) as f64;
-        }
+        let mut verifier = Verifier::new();
+        verifier.extend_sent(&send_hashes);
+        verifier.extend_received(&recv_hashes);
 
+        let report = verifier.report();
         println!(
-            "Received {}% of messages [{} of {}]",
-            received_percent * 100.0,
-            received,
-            received + not_received
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to serialize report")
         );
     }
 }
@@ -1,21 +1,20 @@
 #[cfg(test)]
 mod tests {
     use crate::generator::chernobyl::Chernobyl;
+    use crate::hashes::HashLedger;
 
+    use crate::kafka_admin::KafkaAdmin;
+    use crate::kafka_config::KafkaSecurity;
     use crate::receiver::mqtt::MQTT3Receiver;
     use crate::receiver::Receiver;
     use crate::sender::kafka::KafkaSender;
     use crate::sender::Sender;
-    use crate::tests::Status;
-    use futures::executor;
-    use rdkafka::admin::AdminClient;
-    use rdkafka::client::DefaultClientContext;
-    use rdkafka::ClientConfig;
-    use std::collections::{HashMap, VecDeque};
+    use crate::verify::Verifier;
     use std::sync::{Arc, RwLock};
     use std::thread;
 
     #[test]
+    #[ignore = "needs the lab cluster at 10.99.112.33-35"]
     #[allow(clippy::expect_used)]
     fn test_kafka_sender_mqtt_receiver() {
         let kafka_brokers = vec![
@@ -25,23 +24,15 @@ mod tests {
         ];
         let mqtt_brokers = vec!["10.99.112.33:1883".to_string()];
         // Remove topic (using admin client)
-        let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
-            .set("bootstrap.servers", kafka_brokers.join(","))
-            .create()
-            .expect("Failed to create admin client");
-
-        println!("Deleting topic");
-        executor::block_on(async {
-            admin_client
-                .delete_topics(&["umh.v1.chernobylnuclearpowerplant"], &Default::default())
-                .await
-                .expect("Failed to delete topic");
-        });
+        KafkaAdmin::new(&kafka_brokers, &KafkaSecurity::default())
+            .expect("Failed to create admin client")
+            .delete_topic("umh.v1.chernobylnuclearpowerplant")
+            .expect("Failed to delete topic");
 
         #[allow(unused_assignments)]
-        let send_hashes: Arc<RwLock<VecDeque<String>>> = Arc::new(RwLock::new(VecDeque::new()));
+        let send_hashes: Arc<RwLock<Arc<HashLedger>>> = Arc::new(RwLock::new(Arc::default()));
         #[allow(unused_assignments)]
-        let recv_hashes: Arc<RwLock<VecDeque<String>>> = Arc::new(RwLock::new(VecDeque::new()));
+        let recv_hashes: Arc<RwLock<Arc<HashLedger>>> = Arc::new(RwLock::new(Arc::default()));
 
         let recv_hash_recv_thread = recv_hashes.clone();
         let send_hashes_send_thread = send_hashes.clone();
@@ -58,61 +49,18 @@ mod tests {
         sender_thread.join().expect("Failed to join sender thread");
         recv_thread.join().expect("Failed to join receiver thread");
 
-        let mut message_map: HashMap<String, Status> = HashMap::new();
-
-        for hash in send_hashes
-            .write()
-            .expect("Failed to get read lock")
-            .drain(..)
-        {
-            message_map.insert(hash, Status::Sent);
-        }
-
-        for hash in recv_hashes
-            .write()
-            .expect("Failed to get read lock")
-            .drain(..)
-        {
-            match message_map.entry(hash) {
-                std::collections::hash_map::Entry::Occupied(mut entry) => {
-                    entry.insert(Status::SentAndReceived);
-                }
-                std::collections::hash_map::Entry::Vacant(entry) => {
-                    entry.insert(Status::Received);
-                }
-            }
-        }
-
-        let mut not_received = 0;
-        let mut received = 0;
-        for (_hash, status) in message_map.iter() {
-            match status {
-                Status::Sent => {
-                    not_received += 1;
-                }
-                Status::Received => {
-                    // These are from other tests
-                }
-                Status::SentAndReceived => {
-                    received += 1;
-                }
-            }
-        }
-
-        let mut received_percent = 0.0;
-        if received > 0 {
-            received_percent = received as f64 / (received + not_received) as f64;
-        }
+        let mut verifier = Verifier::new();
+        verifier.extend_sent(&send_hashes.read().expect("Failed to get read lock"));
+        verifier.extend_received(&recv_hashes.read().expect("Failed to get read lock"));
 
+        let report = verifier.report();
         println!(
-            "Received {}% of messages [{} of {}]",
-            received_percent * 100.0,
-            received,
-            received + not_received
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to serialize report")
         );
     }
 
-    fn mqtt_recv(mqtt_brokers: Vec<String>, recv_hashes: Arc<RwLock<VecDeque<String>>>)
+    fn mqtt_recv(mqtt_brokers: Vec<String>, recv_hashes: Arc<RwLock<Arc<HashLedger>>>)
     // Receiver
     {
         println!("Starting MQTT receiver");
@@ -134,12 +82,10 @@ mod tests {
 
         #[allow(clippy::expect_used)]
         let mut write_lock = recv_hashes.write().expect("Failed to get write lock");
-        for v in receiver.get_received_messages_hashes() {
-            write_lock.push_back(v);
-        }
+        *write_lock = receiver.get_received_messages_hashes();
     }
 
-    fn kafka_sender(kafka_brokers: Vec<String>, send_hashes: Arc<RwLock<VecDeque<String>>>) {
+    fn kafka_sender(kafka_brokers: Vec<String>, send_hashes: Arc<RwLock<Arc<HashLedger>>>) {
         #[allow(clippy::expect_used)]
         let mut sender = KafkaSender::new(kafka_brokers.clone()).expect("Failed to create sender");
         let seconds = 5;
@@ -148,7 +94,7 @@ mod tests {
 
         #[allow(clippy::expect_used)]
         sender
-            .begin::<Chernobyl>(3, 100)
+            .begin::<Chernobyl>(3, 100, rand::random())
             .expect("Failed to begin sending");
         thread::sleep(std::time::Duration::from_secs(seconds));
         sender.end();
@@ -161,9 +107,7 @@ mod tests {
         {
             #[allow(clippy::expect_used)]
             let mut send_hashes_lock = send_hashes.write().expect("Failed to get write lock");
-            for v in sender.get_sent_message_hashes() {
-                send_hashes_lock.push_back(v);
-            }
+            *send_hashes_lock = sender.get_sent_message_hashes();
         }
 
         #[allow(clippy::expect_used)]
@@ -0,0 +1,319 @@
+/// Kafka to Kafka runs against librdkafka's in-process mock cluster, so they need no lab.
+#[cfg(test)]
+mod tests {
+    use crate::generator::{Clock, MetadataLocation};
+    use crate::kafka_config::KafkaClientSettings;
+    use crate::receiver::commit::CommitStrategy;
+    use crate::runner::{
+        self, GeneratorKind, KafkaReceiveOptions, KafkaSendOptions, MqttReceiveOptions,
+        MqttSendOptions, ReceiveOptions, ReceiverKind, SendOptions, SenderKind,
+        DEFAULT_KAFKA_TOPIC,
+    };
+    use crate::sender::rate::{RateLimit, RateUnit};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::DefaultProducerContext;
+    use rdkafka::types::{RDKafkaApiKey, RDKafkaRespErr};
+    use std::time::Duration;
+
+    const BROKERS: i32 = 3;
+
+    #[allow(clippy::expect_used)]
+    fn mock_cluster() -> MockCluster<'static, DefaultProducerContext> {
+        let cluster = MockCluster::new(BROKERS).expect("Failed to start mock cluster");
+        cluster
+            .create_topic(DEFAULT_KAFKA_TOPIC, 3, BROKERS)
+            .expect("Failed to create topic");
+        cluster
+    }
+
+    /// A slow Kafka sender, so a run stays small, that gives up on a message after 2s.
+    fn send_options(cluster: &MockCluster<'static, DefaultProducerContext>) -> SendOptions {
+        SendOptions {
+            sender: SenderKind::Kafka,
+            brokers: vec![cluster.bootstrap_servers()],
+            generator: GeneratorKind::Chernobyl,
+            split_point: 3,
+            topics: 10,
+            seed: None,
+            rate: Some(RateLimit::constant(500.0, RateUnit::Messages)),
+            sequence_numbers: false,
+            metadata: MetadataLocation::Payload,
+            clock: Clock::Wall,
+            kafka: KafkaSendOptions {
+                client: KafkaClientSettings {
+                    overrides: [("message.timeout.ms".to_string(), "2000".to_string())].into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            mqtt: MqttSendOptions::default(),
+        }
+    }
+
+    fn receive_options(cluster: &MockCluster<'static, DefaultProducerContext>) -> ReceiveOptions {
+        ReceiveOptions {
+            receiver: ReceiverKind::Kafka,
+            brokers: vec![cluster.bootstrap_servers()],
+            topic: DEFAULT_KAFKA_TOPIC.to_string(),
+            latency_per_topic: false,
+            kafka: KafkaReceiveOptions::default(),
+            mqtt: MqttReceiveOptions::default(),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_roundtrip() {
+        let cluster = mock_cluster();
+        let result = runner::roundtrip(
+            &send_options(&cluster),
+            &receive_options(&cluster),
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        let delivery = send.delivery.expect("Kafka sender reports deliveries");
+        assert_eq!(delivery.delivered, send.sent);
+        assert_eq!(delivery.failed, 0);
+
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.unexpected, 0);
+        assert_eq!(verification.corrupted, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_streaming_verification() {
+        let cluster = mock_cluster();
+        let mut send = send_options(&cluster);
+        send.sequence_numbers = true;
+        let result = runner::roundtrip(
+            &send,
+            &receive_options(&cluster),
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            true,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        // Only hashes are matched while streaming, so no ids pile up for the whole run
+        let send = result.send.expect("Phase did not send");
+        assert!(send.ids.is_empty());
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_logical_clock() {
+        let cluster = mock_cluster();
+        let mut send = send_options(&cluster);
+        send.clock = Clock::Logical;
+        send.seed = Some(7);
+        send.kafka.threads = 2;
+        let receive = receive_options(&cluster);
+
+        // Latencies would be measured from the logical timestamps in the payload
+        let payload = runner::roundtrip(
+            &send,
+            &receive,
+            Duration::from_secs(1),
+            Duration::from_secs(20),
+            false,
+            None,
+        );
+        assert!(payload.is_err());
+
+        send.metadata = MetadataLocation::Headers;
+        // MQTT receivers would too, even with the send timestamp in headers
+        let mqtt = ReceiveOptions {
+            receiver: ReceiverKind::Mqtt5,
+            brokers: vec!["127.0.0.1:1883".to_string()],
+            ..receive.clone()
+        };
+        let error = runner::roundtrip(
+            &send,
+            &mqtt,
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+            false,
+            None,
+        )
+        .expect_err("Logical clock with an MQTT receiver was accepted");
+        assert!(error.to_string().contains("mqtt5 receivers"));
+
+        let result = runner::roundtrip(
+            &send,
+            &receive,
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        let receiver = &result.receivers[0];
+        let verification = receiver.verification.clone().expect("Phase did not verify");
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.unexpected, 0);
+        // Measured from the wall-clock header, not years since the logical epoch
+        assert_eq!(receiver.latency.overall.count, send.sent);
+        assert!(receiver.latency.overall.max_us < 60_000_000);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_produce_errors() {
+        let cluster = mock_cluster();
+        // The first produce requests are rejected for good, later ones go through
+        cluster.request_errors(
+            RDKafkaApiKey::Produce,
+            &[RDKafkaRespErr::RD_KAFKA_RESP_ERR_MSG_SIZE_TOO_LARGE; 5],
+        );
+        let result = runner::roundtrip(
+            &send_options(&cluster),
+            &receive_options(&cluster),
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        let delivery = send.delivery.expect("Kafka sender reports deliveries");
+        assert!(delivery.failed > 0);
+        assert_eq!(delivery.failed_by_category["too_large"], delivery.failed);
+        assert_eq!(send.errors["delivery"], delivery.failed);
+        assert!(send.sent > 0);
+
+        // Rejected messages were never sent, so they are not lost either
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.sent, send.sent);
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_brokers_down() {
+        let cluster = mock_cluster();
+        for broker in 1..=BROKERS {
+            cluster.broker_down(broker).expect("Failed to stop broker");
+        }
+        let send = runner::send(&send_options(&cluster), Duration::from_secs(2), None)
+            .expect("Send failed");
+
+        let delivery = send.delivery.expect("Kafka sender reports deliveries");
+        assert_eq!(send.sent, 0);
+        assert!(delivery.failed > 0);
+        assert_eq!(delivery.failed_by_category["timed_out"], delivery.failed);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_queue_full() {
+        let cluster = mock_cluster();
+        // An unpaced sender fills a queue this small all the time
+        let mut send = send_options(&cluster);
+        send.rate = None;
+        send.sequence_numbers = true;
+        send.kafka
+            .client
+            .overrides
+            .insert("queue.buffering.max.messages".to_string(), "10".to_string());
+        let result = runner::roundtrip(
+            &send,
+            &receive_options(&cluster),
+            Duration::from_secs(3),
+            Duration::from_secs(20),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        assert!(send.errors.is_empty());
+
+        // Messages that found the queue full were retried, not dropped after being stamped
+        let receiver = &result.receivers[0];
+        let verification = receiver.verification.clone().expect("Phase did not verify");
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.corrupted, 0);
+        assert_eq!(receiver.sequence.overall.messages, send.sent);
+        assert_eq!(receiver.sequence.overall.gaps, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mock_cluster_restart_redelivers() {
+        let cluster = mock_cluster();
+        // Receivers start 5s before the sender, so this kills the consumer 2s into sending and
+        // before its next commit
+        let mut receive = receive_options(&cluster);
+        receive.kafka.restart_after = Some(Duration::from_secs(7));
+        // Auto commit would commit when the consumer is killed
+        assert!(runner::roundtrip(
+            &send_options(&cluster),
+            &receive,
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+            false,
+            None,
+        )
+        .is_err());
+        receive.kafka.commit = CommitStrategy::Async { interval_ms: 5000 };
+        // The mock coordinator only lets the new consumer in once the session timeout is over
+        receive
+            .kafka
+            .client
+            .overrides
+            .insert("session.timeout.ms".to_string(), "6000".to_string());
+        let result = runner::roundtrip(
+            &send_options(&cluster),
+            &receive,
+            Duration::from_secs(4),
+            Duration::from_secs(20),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        let receiver = &result.receivers[0];
+        let commits = receiver
+            .commits
+            .clone()
+            .expect("Kafka receiver reports commits");
+        assert_eq!(commits.restarts, 1);
+        assert!(commits.redelivered > 0);
+
+        let verification = receiver.verification.clone().expect("Phase did not verify");
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.delivered, send.sent);
+        assert!(verification.duplicates > 0);
+    }
+}
@@ -1,9 +1,5 @@
 pub mod kafka_to_kafka;
+pub mod kafka_tls;
 pub mod kafka_to_mqtt;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum Status {
-    Sent,
-    Received,
-    SentAndReceived,
-}
+pub mod mock_cluster;
+pub mod mqtt_broker;
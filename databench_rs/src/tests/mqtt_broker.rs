@@ -0,0 +1,557 @@
+/// MQTT runs against a rumqttd broker started by the tests, Kafka to MQTT goes through a
+/// stand-in bridge from librdkafka's mock cluster, so neither needs the lab.
+#[cfg(test)]
+mod tests {
+    use crate::generator::chernobyl::Chernobyl;
+    use crate::generator::{Clock, MetadataLocation};
+    use crate::receiver::mqtt::MQTT3Receiver;
+    use crate::receiver::mqtt5::MQTT5Receiver;
+    use crate::receiver::Receiver;
+    use crate::runner::{
+        self, GeneratorKind, KafkaReceiveOptions, KafkaSendOptions, MqttReceiveOptions,
+        MqttSendOptions, PhaseResult, ReceiveOptions, ReceiverKind, SendOptions, SenderKind,
+        DEFAULT_KAFKA_TOPIC, DEFAULT_MQTT_TOPIC,
+    };
+    use crate::sender::mqtt::MQTTSender;
+    use crate::sender::rate::{RateLimit, RateUnit};
+    use crate::sender::Sender;
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::DefaultProducerContext;
+    use rdkafka::{ClientConfig, Message};
+    use rumqttc::{Event, Incoming, QoS};
+    use rumqttd::{Broker, Config, ConnectionSettings, ConsoleSettings, ServerSettings};
+    use std::collections::{HashMap, HashSet};
+    use std::io;
+    use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+
+    /// rumqttd in the test process, behind listeners on ports that stay the same across
+    /// restarts.
+    ///
+    /// rumqttd can't be shut down, so the listeners forward every connection to the current
+    /// broker. A restart closes the forwarded connections and starts a fresh broker on new
+    /// ports, the old one idles until the tests end. Its sessions, subscriptions and retained
+    /// messages stay behind with it, like in a real broker restart.
+    pub struct EmbeddedBroker {
+        address: SocketAddr,
+        address_v5: SocketAddr,
+        current: Arc<Mutex<Option<Upstream>>>,
+    }
+
+    /// The broker connections are forwarded to, `None` while stopped.
+    struct Upstream {
+        address: SocketAddr,
+        address_v5: SocketAddr,
+        /// Both ends of every forwarded connection, closed on stop.
+        sockets: Vec<TcpStream>,
+    }
+
+    impl EmbeddedBroker {
+        #[allow(clippy::expect_used)]
+        pub fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind broker");
+            let listener_v5 = TcpListener::bind("127.0.0.1:0").expect("Failed to bind broker");
+            let mut broker = Self {
+                address: listener.local_addr().expect("Failed to get broker address"),
+                address_v5: listener_v5
+                    .local_addr()
+                    .expect("Failed to get broker address"),
+                current: Arc::new(Mutex::new(None)),
+            };
+            for (listener, v5) in [(listener, false), (listener_v5, true)] {
+                let current = broker.current.clone();
+                thread::spawn(move || forward_connections(listener, &current, v5));
+            }
+            broker.restart();
+            broker
+        }
+
+        /// `host:port` of the MQTT 3.1.1 listener, for the `--brokers` option.
+        pub fn address(&self) -> String {
+            self.address.to_string()
+        }
+
+        /// `host:port` of the MQTT 5 listener.
+        pub fn address_v5(&self) -> String {
+            self.address_v5.to_string()
+        }
+
+        /// Drops every connection and all broker state, new connections are closed right away.
+        #[allow(clippy::expect_used)]
+        pub fn stop(&mut self) {
+            let upstream = self
+                .current
+                .lock()
+                .expect("Failed to get broker lock")
+                .take();
+            for socket in upstream.into_iter().flat_map(|upstream| upstream.sockets) {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+        }
+
+        /// Starts a fresh broker and waits until it accepts clients.
+        #[allow(clippy::expect_used)]
+        pub fn restart(&mut self) {
+            self.stop();
+            let (address, address_v5) = (free_address(), free_address());
+            let config = broker_config(address, address_v5);
+            thread::spawn(move || {
+                if let Err(e) = Broker::new(config).start() {
+                    eprintln!("Embedded broker failed: {:?}", e);
+                }
+            });
+
+            let start = Instant::now();
+            while TcpStream::connect(address).is_err() || TcpStream::connect(address_v5).is_err() {
+                assert!(
+                    start.elapsed() < Duration::from_secs(10),
+                    "Broker did not come up"
+                );
+                thread::sleep(Duration::from_millis(50));
+            }
+            *self.current.lock().expect("Failed to get broker lock") = Some(Upstream {
+                address,
+                address_v5,
+                sockets: Vec::new(),
+            });
+        }
+
+        /// Waits until something is published to `filter`, e.g. once a sender is running.
+        #[allow(clippy::expect_used)]
+        pub fn wait_for_publish(&self, filter: &str, timeout: Duration) {
+            let options = rumqttc::MqttOptions::new(
+                "databench-test-observer",
+                self.address.ip().to_string(),
+                self.address.port(),
+            );
+            let (mut client, mut connection) = rumqttc::Client::new(options, 10);
+            client
+                .subscribe(filter, QoS::AtMostOnce)
+                .expect("Failed to subscribe observer");
+            let deadline = Instant::now() + timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match connection
+                    .recv_timeout(remaining)
+                    .expect("Nothing was published in time")
+                {
+                    Ok(Event::Incoming(Incoming::Publish(_))) => break,
+                    Ok(_) => {}
+                    // Don't spin while the broker is down
+                    Err(_) => thread::sleep(Duration::from_millis(100)),
+                }
+            }
+        }
+    }
+
+    impl Drop for EmbeddedBroker {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
+
+    /// Forwards every client accepted on `listener` to the current broker.
+    #[allow(clippy::expect_used)]
+    fn forward_connections(listener: TcpListener, current: &Mutex<Option<Upstream>>, v5: bool) {
+        for client in listener.incoming().flatten() {
+            let mut current = current.lock().expect("Failed to get broker lock");
+            // While stopped the client is dropped, so its connection fails like a refused one
+            let Some(upstream) = current.as_mut() else {
+                continue;
+            };
+            let target = if v5 {
+                upstream.address_v5
+            } else {
+                upstream.address
+            };
+            let Ok(broker) = TcpStream::connect(target) else {
+                continue;
+            };
+            let (Ok(client_out), Ok(broker_out), Ok(client_end), Ok(broker_end)) = (
+                client.try_clone(),
+                broker.try_clone(),
+                client.try_clone(),
+                broker.try_clone(),
+            ) else {
+                continue;
+            };
+            upstream.sockets.extend([client_end, broker_end]);
+            thread::spawn(move || pump(client, broker_out));
+            thread::spawn(move || pump(broker, client_out));
+        }
+    }
+
+    /// Copies `from` to `to` until either side closes, then closes both.
+    fn pump(mut from: TcpStream, mut to: TcpStream) {
+        let _ = io::copy(&mut from, &mut to);
+        let _ = from.shutdown(Shutdown::Both);
+        let _ = to.shutdown(Shutdown::Both);
+    }
+
+    #[allow(clippy::expect_used)]
+    fn free_address() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Failed to find a free port")
+    }
+
+    fn server_settings(name: &str, listen: SocketAddr) -> ServerSettings {
+        ServerSettings {
+            name: name.to_string(),
+            listen,
+            tls: None,
+            next_connection_delay_ms: 0,
+            connections: ConnectionSettings {
+                connection_timeout_ms: 5000,
+                max_payload_size: 1024 * 1024,
+                max_inflight_count: 1000,
+                auth: None,
+                dynamic_filters: true,
+            },
+        }
+    }
+
+    fn broker_config(address: SocketAddr, address_v5: SocketAddr) -> Config {
+        let mut console = ConsoleSettings::default();
+        console.listen = free_address().to_string();
+        let mut config = Config {
+            v4: HashMap::from([("1".to_string(), server_settings("v4", address))]),
+            v5: Some(HashMap::from([(
+                "1".to_string(),
+                server_settings("v5", address_v5),
+            )])),
+            console,
+            ..Default::default()
+        };
+        config.router.max_connections = 100;
+        config.router.max_outgoing_packet_count = 1000;
+        config.router.max_segment_size = 100 * 1024 * 1024;
+        config.router.max_segment_count = 10;
+        config
+    }
+
+    /// Republishes the benchmark topic to MQTT under `<topic>/<key>`, like the UMH bridge.
+    struct Bridge {
+        running: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Bridge {
+        #[allow(clippy::expect_used)]
+        fn start(kafka_brokers: &str, mqtt: &EmbeddedBroker) -> Self {
+            let consumer: BaseConsumer = ClientConfig::new()
+                .set("bootstrap.servers", kafka_brokers)
+                .set("group.id", "databench-test-bridge")
+                .set("auto.offset.reset", "earliest")
+                .create()
+                .expect("Failed to create bridge consumer");
+            consumer
+                .subscribe(&[DEFAULT_KAFKA_TOPIC])
+                .expect("Failed to subscribe bridge");
+
+            let address = mqtt.address;
+            let options = rumqttc::MqttOptions::new(
+                "databench-test-bridge",
+                address.ip().to_string(),
+                address.port(),
+            );
+            let (mut client, mut connection) = rumqttc::Client::new(options, 1000);
+            thread::spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("Bridge connection error: {:?}", e);
+                            thread::sleep(Duration::from_millis(1000));
+                        }
+                    }
+                }
+            });
+
+            let running = Arc::new(AtomicBool::new(true));
+            let bridge_running = running.clone();
+            let thread = thread::spawn(move || {
+                while bridge_running.load(Ordering::Relaxed) {
+                    let message = match consumer.poll(Duration::from_millis(100)) {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => {
+                            eprintln!("Bridge failed to consume: {:?}", e);
+                            continue;
+                        }
+                        None => continue,
+                    };
+                    let key = String::from_utf8_lossy(message.key().unwrap_or_default());
+                    let topic = format!("{}.{}", message.topic(), key).replace('.', "/");
+                    let payload = message.payload().unwrap_or_default().to_vec();
+                    if let Err(e) = client.publish(topic, rumqttc::QoS::AtLeastOnce, false, payload)
+                    {
+                        eprintln!("Bridge failed to publish: {:?}", e);
+                    }
+                }
+                let _ = client.disconnect();
+            });
+            Self {
+                running,
+                thread: Some(thread),
+            }
+        }
+    }
+
+    impl Drop for Bridge {
+        fn drop(&mut self) {
+            self.running.store(false, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn send_options(sender: SenderKind, brokers: String) -> SendOptions {
+        SendOptions {
+            sender,
+            brokers: vec![brokers],
+            generator: GeneratorKind::Chernobyl,
+            split_point: 3,
+            topics: 10,
+            seed: None,
+            rate: Some(RateLimit::constant(500.0, RateUnit::Messages)),
+            sequence_numbers: false,
+            metadata: MetadataLocation::Payload,
+            clock: Clock::Wall,
+            kafka: KafkaSendOptions::default(),
+            mqtt: MqttSendOptions::default(),
+        }
+    }
+
+    fn receive_options(receiver: ReceiverKind, brokers: String) -> ReceiveOptions {
+        ReceiveOptions {
+            receiver,
+            brokers: vec![brokers],
+            topic: DEFAULT_MQTT_TOPIC.to_string(),
+            latency_per_topic: false,
+            kafka: KafkaReceiveOptions::default(),
+            mqtt: MqttReceiveOptions::default(),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_embedded_broker_roundtrip() {
+        let mqtt = EmbeddedBroker::start();
+        let result = runner::roundtrip(
+            &send_options(SenderKind::Mqtt, mqtt.address()),
+            &receive_options(ReceiverKind::Mqtt, mqtt.address()),
+            Duration::from_secs(3),
+            Duration::from_secs(5),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        let receiver = &result.receivers[0];
+        assert_eq!(receiver.connection.disconnects, 0);
+        let verification = receiver.verification.clone().expect("Phase did not verify");
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_embedded_broker_mqtt5_roundtrip() {
+        let mqtt = EmbeddedBroker::start();
+        let result = runner::roundtrip(
+            &send_options(SenderKind::Mqtt, mqtt.address()),
+            &receive_options(ReceiverKind::Mqtt5, mqtt.address_v5()),
+            Duration::from_secs(3),
+            Duration::from_secs(5),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_kafka_bridge_mqtt() {
+        let cluster: MockCluster<'static, DefaultProducerContext> =
+            MockCluster::new(1).expect("Failed to start mock cluster");
+        cluster
+            .create_topic(DEFAULT_KAFKA_TOPIC, 1, 1)
+            .expect("Failed to create topic");
+        let mqtt = EmbeddedBroker::start();
+        let _bridge = Bridge::start(&cluster.bootstrap_servers(), &mqtt);
+
+        let result = runner::roundtrip(
+            &send_options(SenderKind::Kafka, cluster.bootstrap_servers()),
+            &receive_options(ReceiverKind::Mqtt, mqtt.address()),
+            Duration::from_secs(3),
+            Duration::from_secs(10),
+            false,
+            None,
+        )
+        .expect("Roundtrip failed");
+
+        let send = result.send.expect("Phase did not send");
+        assert!(send.sent > 0);
+        let verification = result.receivers[0]
+            .verification
+            .clone()
+            .expect("Phase did not verify");
+        assert_eq!(verification.lost, 0);
+        assert_eq!(verification.delivered, send.sent);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    /// Runs an 8s phase and restarts the broker 3s into sending. Returns the phase and when,
+    /// counted from the phase start, the broker was back.
+    #[allow(clippy::expect_used)]
+    fn roundtrip_with_restart(
+        mqtt: &mut EmbeddedBroker,
+        send: SendOptions,
+        receive: ReceiveOptions,
+    ) -> (PhaseResult, Duration) {
+        let started_at = Instant::now();
+        let phase = thread::spawn(move || {
+            runner::roundtrip(
+                &send,
+                &receive,
+                Duration::from_secs(8),
+                Duration::from_secs(5),
+                false,
+                None,
+            )
+        });
+
+        // The sender starts once the receivers had 5s to subscribe, give it a moment to send
+        mqtt.wait_for_publish(DEFAULT_MQTT_TOPIC, Duration::from_secs(15));
+        thread::sleep(Duration::from_secs(1));
+        mqtt.stop();
+        thread::sleep(Duration::from_secs(2));
+        mqtt.restart();
+        let restarted_after = started_at.elapsed();
+
+        let result = phase
+            .join()
+            .expect("Failed to join phase thread")
+            .expect("Roundtrip failed");
+        (result, restarted_after)
+    }
+
+    /// Sends stamped messages through the broker and checks both sides saw the same ids.
+    #[allow(clippy::expect_used)]
+    fn assert_sequence_ids<R: Receiver>(mqtt: &EmbeddedBroker, mut receiver: R) {
+        receiver.begin().expect("Failed to start receiver");
+        // Give the receiver time to subscribe, like the runner does
+        thread::sleep(Duration::from_secs(2));
+
+        let mut sender = MQTTSender::new(vec![mqtt.address()])
+            .expect("Failed to create sender")
+            .with_rate_limit(Some(RateLimit::constant(500.0, RateUnit::Messages)))
+            .with_sequence_numbers(true);
+        sender
+            .begin::<Chernobyl>(3, 10, 42)
+            .expect("Failed to start sender");
+        thread::sleep(Duration::from_secs(2));
+        sender.end();
+
+        let start = Instant::now();
+        while receiver.get_received_messages() < sender.get_sent_messages()
+            && start.elapsed() < Duration::from_secs(5)
+        {
+            thread::sleep(Duration::from_millis(100));
+        }
+        receiver.end();
+
+        let sent: HashSet<_> = sender.get_sent_message_ids().into_iter().collect();
+        let received: HashSet<_> = receiver.get_received_message_ids().into_iter().collect();
+        assert_eq!(sent.len() as u64, sender.get_sent_messages());
+        assert!(!sent.is_empty());
+        assert_eq!(sent, received);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mqtt_sequence_ids() {
+        let mqtt = EmbeddedBroker::start();
+        let receiver = MQTT3Receiver::new(vec![mqtt.address()], DEFAULT_MQTT_TOPIC.to_string())
+            .expect("Failed to create receiver");
+        assert_sequence_ids(&mqtt, receiver);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_mqtt5_sequence_ids() {
+        let mqtt = EmbeddedBroker::start();
+        let receiver = MQTT5Receiver::new(vec![mqtt.address_v5()], DEFAULT_MQTT_TOPIC.to_string())
+            .expect("Failed to create receiver");
+        assert_sequence_ids(&mqtt, receiver);
+    }
+
+    /// Messages received from the restarted broker, going by the throughput samples.
+    fn received_after(result: &PhaseResult, restarted_after: Duration) -> u64 {
+        let before = result
+            .throughput
+            .iter()
+            .filter(|sample| sample.elapsed_s <= restarted_after.as_secs_f64())
+            .map(|sample| sample.received[0])
+            .max()
+            .unwrap_or_default();
+        result.receivers[0].received - before
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_embedded_broker_restart() {
+        let mut mqtt = EmbeddedBroker::start();
+        let send = send_options(SenderKind::Mqtt, mqtt.address());
+        let receive = receive_options(ReceiverKind::Mqtt, mqtt.address());
+        let (result, restarted_after) = roundtrip_with_restart(&mut mqtt, send, receive);
+
+        let receiver = &result.receivers[0];
+        assert!(receiver.connection.disconnects > 0);
+        assert!(receiver.connection.reconnects > 0);
+        assert!(!receiver.connection.still_disconnected);
+        assert!(receiver.connection.downtime_ms > 0);
+        // The restarted broker knows nothing of the subscription, so this needs a resubscribe
+        assert!(received_after(&result, restarted_after) > 0);
+
+        let send = result.send.expect("Phase did not send");
+        let verification = receiver.verification.clone().expect("Phase did not verify");
+        // Messages in flight during the outage may be lost, the rest must arrive
+        assert!(verification.delivered > 0);
+        assert!(verification.delivered + verification.lost == send.sent);
+        assert_eq!(verification.unexpected, 0);
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_embedded_broker_restart_mqtt5() {
+        let mut mqtt = EmbeddedBroker::start();
+        let send = send_options(SenderKind::Mqtt, mqtt.address());
+        let receive = receive_options(ReceiverKind::Mqtt5, mqtt.address_v5());
+        let (result, restarted_after) = roundtrip_with_restart(&mut mqtt, send, receive);
+
+        let receiver = &result.receivers[0];
+        assert!(receiver.errors["connection"] > 0);
+        // With clean start every reconnect needs a new subscription
+        assert!(received_after(&result, restarted_after) > 0);
+        assert_eq!(receiver.expiry.clone().unwrap_or_default().with_expiry, 0);
+    }
+}
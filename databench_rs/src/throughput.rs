@@ -0,0 +1,153 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Sent and received counts at one point of a phase, with the rates since the previous one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ThroughputSample {
+    /// Seconds since the phase started.
+    pub elapsed_s: f64,
+    pub sent: u64,
+    /// One count per receiver, in the order of the phase's receivers.
+    pub received: Vec<u64>,
+    pub sent_per_second: f64,
+    pub received_per_second: Vec<f64>,
+}
+
+/// Samples message counters on a background thread while a phase runs. The sender's counter
+/// is added once the sender exists, receivers are known up front.
+pub struct ThroughputSampler {
+    running: Arc<AtomicBool>,
+    sent: Arc<Mutex<Option<Arc<AtomicU64>>>>,
+    handle: Option<JoinHandle<Vec<ThroughputSample>>>,
+}
+
+impl ThroughputSampler {
+    pub fn start(interval: Duration, received: Vec<Arc<AtomicU64>>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let sent: Arc<Mutex<Option<Arc<AtomicU64>>>> = Arc::new(Mutex::new(None));
+
+        let thread_running = running.clone();
+        let thread_sent = sent.clone();
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let mut samples = Vec::new();
+            let mut last = ThroughputSample {
+                received: vec![0; received.len()],
+                ..Default::default()
+            };
+            let mut next = interval;
+            loop {
+                let running = thread_running.load(Ordering::Relaxed);
+                if running && start.elapsed() < next {
+                    thread::sleep(Duration::from_millis(50).min(interval));
+                    continue;
+                }
+                next += interval;
+
+                #[allow(clippy::expect_used)]
+                let sent = thread_sent
+                    .lock()
+                    .expect("Failed to get sampler lock")
+                    .as_ref()
+                    .map(|counter| counter.load(Ordering::Relaxed))
+                    .unwrap_or_default();
+                let received = received
+                    .iter()
+                    .map(|counter| counter.load(Ordering::Relaxed))
+                    .collect();
+                let sample = next_sample(&last, start.elapsed(), sent, received);
+                samples.push(sample.clone());
+                last = sample;
+
+                if !running {
+                    return samples;
+                }
+            }
+        });
+
+        Self {
+            running,
+            sent,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn track_sent(&self, counter: Arc<AtomicU64>) {
+        #[allow(clippy::expect_used)]
+        let mut sent = self.sent.lock().expect("Failed to get sampler lock");
+        *sent = Some(counter);
+    }
+
+    /// Takes a last sample and returns all of them.
+    pub fn stop(mut self) -> Vec<ThroughputSample> {
+        self.running.store(false, Ordering::Relaxed);
+        self.handle
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for ThroughputSampler {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+fn next_sample(
+    last: &ThroughputSample,
+    elapsed: Duration,
+    sent: u64,
+    received: Vec<u64>,
+) -> ThroughputSample {
+    let seconds = elapsed.as_secs_f64() - last.elapsed_s;
+    let rate = |count: u64, last: u64| match seconds > 0.0 {
+        true => count.saturating_sub(last) as f64 / seconds,
+        false => 0.0,
+    };
+    ThroughputSample {
+        elapsed_s: elapsed.as_secs_f64(),
+        sent,
+        sent_per_second: rate(sent, last.sent),
+        received_per_second: received
+            .iter()
+            .zip(last.received.iter())
+            .map(|(&count, &last)| rate(count, last))
+            .collect(),
+        received,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::throughput::{next_sample, ThroughputSampler};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_throughput_samples() {
+        let first = next_sample(&Default::default(), Duration::from_secs(2), 100, vec![]);
+        assert_eq!(first.sent_per_second, 50.0);
+        let second = next_sample(&first, Duration::from_secs(3), 400, vec![]);
+        assert_eq!(second.sent_per_second, 300.0);
+
+        let received = Arc::new(AtomicU64::new(0));
+        let sampler = ThroughputSampler::start(Duration::from_millis(20), vec![received.clone()]);
+        let sent = Arc::new(AtomicU64::new(0));
+        sampler.track_sent(sent.clone());
+        sent.store(10, Ordering::Relaxed);
+        received.store(7, Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(100));
+        let samples = sampler.stop();
+        assert!(samples.len() >= 2);
+        let last = samples
+            .last()
+            .map(|sample| (sample.sent, sample.received[0]));
+        assert_eq!(last, Some((10, 7)));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::hashes::{HashLedger, MessageHash};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Compares what was sent with what was received, counting every hash on both sides.
+///
+/// Messages can optionally carry an id that is independent of their content (e.g. producer
+/// id and sequence number). A known id arriving with a different hash is counted as
+/// corrupted instead of as one lost and one unexpected message.
+#[derive(Debug, Default)]
+pub struct Verifier {
+    sent: HashMap<MessageHash, u64>,
+    received: HashMap<MessageHash, u64>,
+    sent_ids: HashMap<String, MessageHash>,
+    received_ids: Vec<(String, MessageHash)>,
+    /// Messages of aborted transactions, which must not be received.
+    aborted: HashSet<MessageHash>,
+    /// Messages whose delivery the producer reported as failed.
+    failed: HashSet<MessageHash>,
+    /// Hashes received messages claimed while their content hashed differently. They were
+    /// counted as received under the claimed hash.
+    corrupted: Vec<MessageHash>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerificationReport {
+    pub sent: u64,
+    pub received: u64,
+    /// Sent messages that were received at least once.
+    pub delivered: u64,
+    /// Sent messages that were never received.
+    pub lost: u64,
+    /// Distinct messages received more often than they were sent.
+    pub duplicated: u64,
+    /// Extra copies over all duplicated messages.
+    pub duplicates: u64,
+    /// How many messages were received how many times, for duplicated messages only.
+    pub duplicate_multiplicity: BTreeMap<u64, u64>,
+    /// Received messages that were never sent, e.g. left over from earlier runs.
+    pub unexpected: u64,
+    /// Received messages whose id was sent with a different hash, or whose content doesn't
+    /// match the hash they were sent with.
+    pub corrupted: u64,
+    /// Received messages that were sent in an aborted transaction, not counted as unexpected.
+    pub aborted_received: u64,
+    /// Received messages whose delivery was reported as failed, e.g. a produce request that
+    /// timed out after the broker wrote it. Not counted as unexpected.
+    pub failed_received: u64,
+}
+
+impl VerificationReport {
+    /// Percentage of sent messages that arrived intact.
+    pub fn delivered_percent(&self) -> f64 {
+        let expected = self.delivered + self.lost + self.corrupted;
+        if expected == 0 {
+            return 0.0;
+        }
+        self.delivered as f64 / expected as f64 * 100.0
+    }
+
+    /// Report for a ledger that both sender and receiver recorded into. Matched messages are
+    /// already gone, so a duplicate that arrives after its match is counted as unexpected.
+    pub fn from_reconciled(
+        ledger: &HashLedger,
+        aborted: Option<&HashLedger>,
+        failed: Option<&HashLedger>,
+        sent: u64,
+        received: u64,
+    ) -> Self {
+        let mut report = VerificationReport {
+            sent,
+            received,
+            ..Default::default()
+        };
+        let aborted = aborted.map(hash_set).unwrap_or_default();
+        let failed = failed.map(hash_set).unwrap_or_default();
+        ledger.for_each(|hash, count| {
+            if count > 0 {
+                report.lost += count as u64;
+            } else if aborted.contains(&hash) {
+                report.aborted_received += count.unsigned_abs();
+            } else if failed.contains(&hash) {
+                report.failed_received += count.unsigned_abs();
+            } else {
+                report.unexpected += count.unsigned_abs();
+            }
+        });
+        report.delivered = sent.saturating_sub(report.lost);
+        report
+    }
+
+    /// Counts `corrupted` messages that were matched under the hash they claimed as corrupted
+    /// instead of delivered.
+    pub fn with_corrupted(mut self, corrupted: u64) -> Self {
+        self.corrupted += corrupted;
+        self.delivered = self.delivered.saturating_sub(corrupted);
+        self
+    }
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_sent(&mut self, hash: MessageHash) {
+        *self.sent.entry(hash).or_default() += 1;
+    }
+
+    pub fn add_sent_with_id(&mut self, id: &str, hash: MessageHash) {
+        self.add_sent(hash);
+        self.sent_ids.insert(id.to_string(), hash);
+    }
+
+    pub fn add_received(&mut self, hash: MessageHash) {
+        *self.received.entry(hash).or_default() += 1;
+    }
+
+    pub fn add_received_with_id(&mut self, id: &str, hash: MessageHash) {
+        self.add_received(hash);
+        self.received_ids.push((id.to_string(), hash));
+    }
+
+    /// Adds ids of messages that were already counted, e.g. through `extend_sent`.
+    pub fn extend_sent_ids(&mut self, ids: &[(String, MessageHash)]) {
+        self.sent_ids
+            .extend(ids.iter().map(|(id, hash)| (id.clone(), *hash)));
+    }
+
+    /// Adds ids of messages that were already counted, e.g. through `extend_received`.
+    pub fn extend_received_ids(&mut self, ids: Vec<(String, MessageHash)>) {
+        self.received_ids.extend(ids);
+    }
+
+    /// Adds everything a sender recorded into its own ledger.
+    pub fn extend_sent(&mut self, ledger: &HashLedger) {
+        ledger.for_each(|hash, count| {
+            if count > 0 {
+                *self.sent.entry(hash).or_default() += count as u64;
+            }
+        });
+    }
+
+    /// Adds everything a receiver recorded into its own ledger.
+    pub fn extend_received(&mut self, ledger: &HashLedger) {
+        ledger.for_each(|hash, count| {
+            if count < 0 {
+                *self.received.entry(hash).or_default() += count.unsigned_abs();
+            }
+        });
+    }
+
+    /// Adds what a transactional sender sent in aborted transactions.
+    pub fn extend_aborted(&mut self, ledger: &HashLedger) {
+        self.aborted.extend(hash_set(ledger));
+    }
+
+    /// Adds what a sender's delivery reports marked as failed.
+    pub fn extend_failed(&mut self, ledger: &HashLedger) {
+        self.failed.extend(hash_set(ledger));
+    }
+
+    /// Adds hashes received messages claimed although their content hashed differently. They
+    /// must already be counted as received under the claimed hash.
+    pub fn extend_corrupted(&mut self, hashes: &[MessageHash]) {
+        self.corrupted.extend_from_slice(hashes);
+    }
+
+    pub fn report(&self) -> VerificationReport {
+        let mut report = VerificationReport {
+            sent: self.sent.values().sum(),
+            received: self.received.values().sum(),
+            ..Default::default()
+        };
+
+        // Take corrupted messages out before matching, so they are neither lost nor unexpected
+        let mut sent = self.sent.clone();
+        let mut received = self.received.clone();
+        let mut corrupted_ids = HashSet::new();
+        for (id, hash) in &self.received_ids {
+            let Some(sent_hash) = self.sent_ids.get(id) else {
+                continue;
+            };
+            if sent_hash == hash {
+                continue;
+            }
+            report.corrupted += 1;
+            decrement(&mut received, *hash);
+            if corrupted_ids.insert(id) {
+                decrement(&mut sent, *sent_hash);
+            }
+        }
+        for hash in &self.corrupted {
+            // A claim that was never sent stays unexpected
+            if sent.get(hash).is_some_and(|&count| count > 0) {
+                report.corrupted += 1;
+                decrement(&mut sent, *hash);
+                decrement(&mut received, *hash);
+            }
+        }
+
+        for (hash, &sent_count) in &sent {
+            let received_count = received.get(hash).copied().unwrap_or_default();
+            report.delivered += sent_count.min(received_count);
+            report.lost += sent_count.saturating_sub(received_count);
+            if received_count > sent_count {
+                report.duplicated += 1;
+                report.duplicates += received_count - sent_count;
+                *report
+                    .duplicate_multiplicity
+                    .entry(received_count)
+                    .or_default() += 1;
+            }
+        }
+        for (hash, count) in &received {
+            if sent.contains_key(hash) {
+                continue;
+            }
+            if self.aborted.contains(hash) {
+                report.aborted_received += count;
+            } else if self.failed.contains(hash) {
+                report.failed_received += count;
+            } else {
+                report.unexpected += count;
+            }
+        }
+
+        report
+    }
+}
+
+fn hash_set(ledger: &HashLedger) -> HashSet<MessageHash> {
+    let mut hashes = HashSet::new();
+    ledger.for_each(|hash, _| {
+        hashes.insert(hash);
+    });
+    hashes
+}
+
+fn decrement(counts: &mut HashMap<MessageHash, u64>, hash: MessageHash) {
+    if let Some(count) = counts.get_mut(&hash) {
+        *count = count.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::sequence::Sequencer;
+    use crate::generator::{Message, MetadataLocation};
+    use crate::hashes::{HashLedger, Side};
+    use crate::verify::{VerificationReport, Verifier};
+
+    #[test]
+    fn test_verifier() {
+        let mut verifier = Verifier::new();
+        for hash in 1..=5 {
+            verifier.add_sent(hash);
+        }
+        verifier.add_sent_with_id("p1:a.k:1", 6);
+        for hash in [1, 2, 2, 3, 3, 3, 99] {
+            verifier.add_received(hash);
+        }
+        verifier.add_received_with_id("p1:a.k:1", 7);
+
+        let report = verifier.report();
+        assert_eq!(report.sent, 6);
+        assert_eq!(report.received, 8);
+        assert_eq!(report.delivered, 3);
+        assert_eq!(report.lost, 2);
+        assert_eq!(report.duplicated, 2);
+        assert_eq!(report.duplicates, 3);
+        assert_eq!(report.duplicate_multiplicity.get(&2), Some(&1));
+        assert_eq!(report.duplicate_multiplicity.get(&3), Some(&1));
+        assert_eq!(report.unexpected, 1);
+        assert_eq!(report.corrupted, 1);
+        assert!((report.delivered_percent() - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn test_ids_of_different_streams() {
+        let mut sequencer = Sequencer::new("p1".to_string());
+        let mut verifier = Verifier::new();
+        for (hash, key) in [(1, "k1"), (2, "k2")] {
+            let mut message = Message {
+                topic: "a".to_string(),
+                value: b"{}".to_vec(),
+                key: key.to_string(),
+                headers: vec![],
+                sequence: None,
+            };
+            sequencer.stamp(&mut message, MetadataLocation::Payload);
+            let stamp = message.sequence.unwrap();
+            assert_eq!(stamp.seq, 0);
+            let id = stamp.id(&format!("a.{}", key));
+            verifier.add_sent_with_id(&id, hash);
+            verifier.add_received_with_id(&id, hash);
+        }
+
+        let report = verifier.report();
+        assert_eq!(report.delivered, 2);
+        assert_eq!(report.corrupted, 0);
+    }
+
+    #[test]
+    fn test_corrupted_hashes() {
+        let mut verifier = Verifier::new();
+        for hash in [1, 2] {
+            verifier.add_sent(hash);
+        }
+        // 2 arrived with different content, 3 claims a hash that was never sent
+        for hash in [1, 2, 3] {
+            verifier.add_received(hash);
+        }
+        verifier.extend_corrupted(&[2, 3]);
+        let report = verifier.report();
+        assert_eq!(report.delivered, 1);
+        assert_eq!(report.corrupted, 1);
+        assert_eq!(report.lost, 0);
+        assert_eq!(report.unexpected, 1);
+
+        let ledger = HashLedger::new();
+        ledger.record(&mut vec![1, 2], Side::Sent);
+        ledger.record(&mut vec![1, 2], Side::Received);
+        let report =
+            VerificationReport::from_reconciled(&ledger, None, None, 2, 2).with_corrupted(1);
+        assert_eq!(report.delivered, 1);
+        assert_eq!(report.corrupted, 1);
+    }
+
+    #[test]
+    fn test_aborted_transactions() {
+        let aborted = HashLedger::new();
+        aborted.record(&mut vec![7, 8], Side::Sent);
+
+        let mut verifier = Verifier::new();
+        verifier.add_sent(1);
+        verifier.extend_aborted(&aborted);
+        for hash in [1, 7, 99] {
+            verifier.add_received(hash);
+        }
+        let report = verifier.report();
+        assert_eq!(report.delivered, 1);
+        assert_eq!(report.aborted_received, 1);
+        assert_eq!(report.unexpected, 1);
+
+        let ledger = HashLedger::new();
+        ledger.record(&mut vec![1], Side::Sent);
+        ledger.record(&mut vec![1, 8, 99], Side::Received);
+        let report = VerificationReport::from_reconciled(&ledger, Some(&aborted), None, 1, 3);
+        assert_eq!(report.aborted_received, 1);
+        assert_eq!(report.unexpected, 1);
+    }
+
+    #[test]
+    fn test_failed_deliveries() {
+        let failed = HashLedger::new();
+        failed.record(&mut vec![5, 6], Side::Sent);
+
+        let mut verifier = Verifier::new();
+        verifier.add_sent(1);
+        verifier.extend_failed(&failed);
+        for hash in [1, 5, 99] {
+            verifier.add_received(hash);
+        }
+        let report = verifier.report();
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.lost, 0);
+        assert_eq!(report.failed_received, 1);
+        assert_eq!(report.unexpected, 1);
+
+        let ledger = HashLedger::new();
+        ledger.record(&mut vec![1], Side::Sent);
+        ledger.record(&mut vec![6], Side::Received);
+        let report = VerificationReport::from_reconciled(&ledger, None, Some(&failed), 1, 1);
+        assert_eq!(report.lost, 1);
+        assert_eq!(report.failed_received, 1);
+    }
+
+    #[test]
+    fn test_reconciled_report() {
+        let ledger = HashLedger::new();
+        ledger.record(&mut vec![1, 2, 3, 4], Side::Sent);
+        ledger.record(&mut vec![1, 2, 5], Side::Received);
+        // 1 and 2 matched and were evicted
+        assert_eq!(ledger.len(), 3);
+
+        let report = VerificationReport::from_reconciled(&ledger, None, None, 4, 3);
+        assert_eq!(report.delivered, 2);
+        assert_eq!(report.lost, 2);
+        assert_eq!(report.unexpected, 1);
+    }
+}